@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::date_sources::{DateSource, ResolvedDate};
+use crate::error::FixError;
+use crate::metadata_utils::format_metadata_value;
+
+// The date picked for every media file and the source it came from.
+// Disagreeing sources are printed at the end of the run, the whole report is written as JSON with --date-report.
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceDate {
+    pub source: DateSource,
+    // e.g. "2015:06:22 19:06:40.000+00:00"
+    pub date_time: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DateRecord {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub date: SourceDate,
+    pub disagreements: Vec<SourceDate>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct DateReport {
+    pub files: Vec<DateRecord>,
+}
+
+impl DateReport {
+    pub fn record(&mut self, path: impl AsRef<Path>, resolved: &ResolvedDate) {
+        let source_date = |(source, date_time)| SourceDate { source, date_time: format_metadata_value(date_time) };
+        self.files.push(DateRecord {
+            path: path.as_ref().to_path_buf(),
+            date: source_date((resolved.source, &resolved.date_time)),
+            disagreements: resolved.disagreements.iter().map(|(source, date_time)| source_date((*source, date_time))).collect(),
+        });
+    }
+
+    pub fn disagreements(&self) -> impl Iterator<Item = &DateRecord> {
+        self.files.iter().filter(|record| !record.disagreements.is_empty())
+    }
+
+    pub fn write_json(&self, report_path: &Path) -> Result<(), FixError> {
+        let json = serde_json::to_string_pretty(self).expect("paths and strings only");
+        fs::write(report_path, json + "\n").map_err(|e| FixError::io(report_path, e))
+    }
+
+    pub fn summary(&self) -> String {
+        let records: Vec<&DateRecord> = self.disagreements().collect();
+        if records.is_empty() {
+            return String::new();
+        }
+
+        let mut summary = format!("{} file(s) with disagreeing dates:\n", records.len());
+        for record in records {
+            summary.push_str(&format!("  {}  {} from {}\n", record.path.display(), record.date.date_time, record.date.source));
+            for other in &record.disagreements {
+                summary.push_str(&format!("    but {} from {}\n", other.date_time, other.source));
+            }
+        }
+        summary
+    }
+}
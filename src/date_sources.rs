@@ -0,0 +1,207 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::Serialize;
+use crate::filename_date::FileNameDateExtractor;
+use crate::metadata_utils::{read_exif_date, read_quicktime_date};
+use crate::takeout_sidecar::TakeoutSidecar;
+use crate::timezone::TimezoneResolver;
+
+// Where the date written to a media file comes from.
+//
+// The sources are tried in priority order and the first one with a date wins. With a
+// disagreement threshold every source of the list is read, and the ones too far from the
+// winner are kept for review.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateSource {
+    // DateTimeOriginal, or CreateDate, already in the file
+    Exif,
+    // The Apple creation date key, or the movie header creation time, already in the file
+    #[serde(rename = "quicktime")]
+    QuickTime,
+    // photoTakenTime of the sidecar
+    PhotoTakenTime,
+    // creationTime of the sidecar, i.e. the upload time
+    CreationTime,
+    // e.g. IMG_20190704_183012.jpg, see filename_date
+    #[serde(rename = "filename")]
+    FileName,
+    // "Photos from 2019", taken as 2019-01-01 00:00:00
+    Folder,
+    // Modification time of the source file
+    Mtime,
+}
+
+pub const ALL_DATE_SOURCES: [DateSource; 7] = [
+    DateSource::Exif,
+    DateSource::QuickTime,
+    DateSource::PhotoTakenTime,
+    DateSource::CreationTime,
+    DateSource::FileName,
+    DateSource::Folder,
+    DateSource::Mtime,
+];
+
+// What each pipeline used before the priority could be configured
+pub const DEFAULT_GOOGLE_PHOTOS_PRIORITY: [DateSource; 1] = [DateSource::PhotoTakenTime];
+pub const DEFAULT_IPHONE_PHOTOS_PRIORITY: [DateSource; 2] = [DateSource::FileName, DateSource::Mtime];
+
+impl DateSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            DateSource::Exif => "exif",
+            DateSource::QuickTime => "quicktime",
+            DateSource::PhotoTakenTime => "photo-taken-time",
+            DateSource::CreationTime => "creation-time",
+            DateSource::FileName => "filename",
+            DateSource::Folder => "folder",
+            DateSource::Mtime => "mtime",
+        }
+    }
+
+    // The ones that have to read the file itself, not only its name or sidecar
+    pub fn reads_file(self) -> bool {
+        matches!(self, DateSource::Exif | DateSource::QuickTime | DateSource::Mtime)
+    }
+
+    pub fn reads_sidecar(self) -> bool {
+        matches!(self, DateSource::PhotoTakenTime | DateSource::CreationTime)
+    }
+}
+
+impl fmt::Display for DateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// "exif,quicktime,photo-taken-time", or "none"
+pub fn parse_date_sources(list: &str) -> Result<Vec<DateSource>, String> {
+    if list.trim() == "none" {
+        return Ok(Vec::new());
+    }
+
+    let mut sources = Vec::new();
+    for name in list.split(',').map(str::trim) {
+        let source = ALL_DATE_SOURCES.into_iter().find(|source| source.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = ALL_DATE_SOURCES.iter().map(|source| source.name()).collect();
+            format!("unknown date source {:?}, expected one of {} or none", name, names.join(", "))
+        })?;
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    Ok(sources)
+}
+
+// "90s", "30m", "12h" or "2d"
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid duration {:?}, expected a number followed by s, m, h or d, e.g. 12h", text);
+
+    let split = text.len().checked_sub(1).ok_or_else(invalid)?;
+    let (number, unit) = (text.get(..split).ok_or_else(invalid)?, text.get(split..).ok_or_else(invalid)?);
+    let number = number.parse::<u32>().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(invalid()),
+    };
+    Duration::try_seconds(i64::from(number) * seconds).ok_or_else(invalid)
+}
+
+// 2019 for "Photos from 2019", the folders Takeout sorts the photos outside of albums into
+pub fn year_of_folder(folder_name: &str) -> Option<i32> {
+    let year = folder_name.strip_prefix("Photos from ")?;
+    year.parse::<i32>().ok().filter(|_| year.len() == 4)
+}
+
+// The closest "Photos from YYYY" directory the media is in
+pub fn date_from_folder(media_path: &Path) -> Option<NaiveDateTime> {
+    media_path.parent()?.ancestors().find_map(|directory| {
+        let year = year_of_folder(directory.file_name()?.to_str()?)?;
+        Some(NaiveDate::from_ymd_opt(year, 1, 1)?.and_time(NaiveTime::MIN))
+    })
+}
+
+// What is known about one media file
+#[derive(Debug, Clone, Copy)]
+pub struct DateInputs<'a> {
+    // Where the file comes from, for its name and folder
+    pub media_path: &'a Path,
+    // Where it can be read: the source file, or the copy extracted from a Takeout zip
+    pub file_path: &'a Path,
+    pub sidecar: Option<&'a TakeoutSidecar>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedDate {
+    pub source: DateSource,
+    pub date_time: DateTime<FixedOffset>,
+    // Other sources more than the threshold away from date_time
+    pub disagreements: Vec<(DateSource, DateTime<FixedOffset>)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DateResolver {
+    pub priority: Vec<DateSource>,
+    pub file_names: FileNameDateExtractor,
+    // --flag-date-disagreement
+    pub disagreement_threshold: Option<Duration>,
+}
+
+impl DateResolver {
+    // Whether a media file without a sidecar can get a date at all
+    pub fn works_without_sidecar(&self) -> bool {
+        self.priority.iter().any(|source| !source.reads_sidecar())
+    }
+
+    // The same resolver without the sources that read the file, for Takeout zip entries during --dry-run
+    pub fn without_file_sources(&self) -> DateResolver {
+        DateResolver { priority: self.priority.iter().copied().filter(|source| !source.reads_file()).collect(), ..self.clone() }
+    }
+
+    pub fn resolve(&self, inputs: &DateInputs, timezones: &TimezoneResolver) -> Option<ResolvedDate> {
+        let mut dates = self
+            .priority
+            .iter()
+            .filter_map(|&source| self.date_from(source, inputs, timezones).map(|date_time| (source, date_time)));
+        let (source, date_time) = dates.next()?;
+
+        let disagreements = match self.disagreement_threshold {
+            Some(threshold) => dates.filter(|(_, other)| (*other - date_time).abs() > threshold).collect(),
+            None => Vec::new(),
+        };
+        Some(ResolvedDate { source, date_time, disagreements })
+    }
+
+    // A file that cannot be read has no date for that source, the next one is tried
+    pub fn date_from(&self, source: DateSource, inputs: &DateInputs, timezones: &TimezoneResolver) -> Option<DateTime<FixedOffset>> {
+        let media_path = inputs.media_path;
+        let sidecar_time = |sidecar: &TakeoutSidecar, utc: Option<DateTime<Utc>>| {
+            Some(timezones.local_time(media_path, &utc?, sidecar.location().as_ref()))
+        };
+
+        match source {
+            DateSource::Exif => read_exif_date(inputs.file_path).ok().flatten(),
+            DateSource::QuickTime => read_quicktime_date(inputs.file_path).ok().flatten(),
+            DateSource::PhotoTakenTime => inputs.sidecar.and_then(|sidecar| sidecar_time(sidecar, sidecar.photo_taken_time())),
+            DateSource::CreationTime => inputs.sidecar.and_then(|sidecar| sidecar_time(sidecar, sidecar.creation_time())),
+            DateSource::FileName => {
+                let local_time = self.file_names.date_time(media_path.file_name()?.to_str()?)?;
+                Some(timezones.from_local(media_path, &local_time))
+            },
+            DateSource::Folder => date_from_folder(media_path).map(|local_time| timezones.from_local(media_path, &local_time)),
+            // Written as UTC, as the iPhone pipeline always did
+            DateSource::Mtime => {
+                let modified: DateTime<Utc> = fs::metadata(inputs.file_path).ok()?.modified().ok()?.into();
+                Some(modified.fixed_offset())
+            },
+        }
+    }
+}
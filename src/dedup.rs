@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::output_layout::{AlbumCopy, AlbumPlacement};
+
+// --dedup: byte-identical media files, e.g. a photo in "Photos from YYYY" and in its albums, or in
+// several Takeout parts, are copied once. The copy kept gets the sidecar metadata of all of them.
+
+// Only the files sharing a size, or a name and size, with another file are worth hashing
+pub fn shares_key<K: Hash + Eq>(keys: &[K]) -> Vec<bool> {
+    let mut counts: HashMap<&K, usize> = HashMap::new();
+    for key in keys {
+        *counts.entry(key).or_default() += 1;
+    }
+    keys.iter().map(|key| counts[key] > 1).collect()
+}
+
+// Runs after place_album_copies: a file that still gets a copy of its own is left out
+// when an earlier one with the same content does as well
+pub fn place_duplicates<K: Hash + Eq>(copies: &[AlbumCopy<K>], placements: &mut [AlbumPlacement]) {
+    let mut kept: HashMap<&K, usize> = HashMap::new();
+    for (index, (copy, placement)) in copies.iter().zip(placements.iter_mut()).enumerate() {
+        if let (Some(key), AlbumPlacement::Own) = (&copy.key, *placement) {
+            match kept.get(key) {
+                Some(&first) => *placement = AlbumPlacement::SameAs(first),
+                None => {
+                    kept.insert(key, index);
+                },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Duplicate {
+    pub path: PathBuf,
+    // The source file whose copy it is
+    pub copy_of: PathBuf,
+    pub size: u64,
+}
+
+// Duplicates left out or linked instead of copied, printed at the end of the run
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateReport {
+    pub duplicates: Vec<Duplicate>,
+}
+
+impl DuplicateReport {
+    pub fn record(&mut self, path: impl AsRef<Path>, copy_of: impl AsRef<Path>, size: u64) {
+        self.duplicates.push(Duplicate { path: path.as_ref().to_path_buf(), copy_of: copy_of.as_ref().to_path_buf(), size });
+    }
+
+    pub fn bytes_saved(&self) -> u64 {
+        self.duplicates.iter().map(|duplicate| duplicate.size).sum()
+    }
+
+    pub fn summary(&self) -> String {
+        if self.duplicates.is_empty() {
+            return String::new();
+        }
+        format!("{} duplicate file(s) not copied again, {} bytes saved\n", self.duplicates.len(), self.bytes_saved())
+    }
+}
@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Everything that can go wrong while fixing a single file.
+// None of these abort the run, they end up in the RunReport instead.
+#[derive(Debug)]
+pub enum FixError {
+    Io { path: PathBuf, source: io::Error },
+    UnsupportedMediaType { path: PathBuf, media_type: String },
+    ToolMissing { tool: &'static str },
+    ToolFailed { tool: &'static str, path: PathBuf, message: String },
+    MissingPairedMedia { sidecar: PathBuf },
+    Rename { from: PathBuf, to: PathBuf, source: io::Error },
+    InvalidUtf8Path { path: PathBuf },
+    InvalidMetadata { path: PathBuf, message: String },
+    DestinationExists { path: PathBuf },
+}
+
+impl FixError {
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> FixError {
+        FixError::Io { path: path.as_ref().to_path_buf(), source }
+    }
+
+    // A spawn failure with NotFound means the external tool is not installed
+    pub fn from_spawn(tool: &'static str, path: impl AsRef<Path>, source: io::Error) -> FixError {
+        if source.kind() == io::ErrorKind::NotFound {
+            FixError::ToolMissing { tool }
+        } else {
+            FixError::ToolFailed { tool, path: path.as_ref().to_path_buf(), message: source.to_string() }
+        }
+    }
+
+    pub fn tool_failed(tool: &'static str, path: impl AsRef<Path>, message: impl Into<String>) -> FixError {
+        FixError::ToolFailed { tool, path: path.as_ref().to_path_buf(), message: message.into() }
+    }
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixError::Io { path, source } => write!(f, "I/O error on {:?}: {}", path, source),
+            FixError::UnsupportedMediaType { path, media_type } => {
+                write!(f, "unsupported or misidentified file format {} for {:?}", media_type, path)
+            },
+            FixError::ToolMissing { tool } => write!(f, "{} is not installed or not in PATH", tool),
+            FixError::ToolFailed { tool, path, message } => write!(f, "{} failed on {:?}: {}", tool, path, message),
+            FixError::MissingPairedMedia { sidecar } => write!(f, "relevant image file not found for sidecar {:?}", sidecar),
+            FixError::Rename { from, to, source } => write!(f, "failed to rename {:?} to {:?}: {}", from, to, source),
+            FixError::InvalidUtf8Path { path } => write!(f, "invalid UTF-8 path {:?}", path),
+            FixError::InvalidMetadata { path, message } => write!(f, "invalid metadata in {:?}: {}", path, message),
+            FixError::DestinationExists { path } => write!(f, "{:?} already exists in the output directory", path),
+        }
+    }
+}
+
+impl Error for FixError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FixError::Io { source, .. } | FixError::Rename { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+pub fn path_to_str(path: &Path) -> Result<&str, FixError> {
+    path.to_str().ok_or_else(|| FixError::InvalidUtf8Path { path: path.to_path_buf() })
+}
@@ -0,0 +1,414 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use crate::error::FixError;
+
+// Minimal EXIF (TIFF structure) reader/writer, enough for the date tags this tool writes.
+//
+// Writing never moves existing data: the changed IFD is appended to the end of the TIFF block
+// and only the pointer to it is updated. Every offset already stored in the block
+// (thumbnail, MakerNote, interop IFD, ...) therefore stays valid.
+
+pub const TAG_EXIF_IFD_POINTER: u16 = 0x8769;
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+pub const TAG_CREATE_DATE: u16 = 0x9004;
+pub const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+pub const TAG_GPS_IFD_POINTER: u16 = 0x8825;
+pub const TAG_GPS_LATITUDE: u16 = 0x0002;
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+
+pub const EXIF_HEADER: &[u8] = b"Exif\0\0";
+// APP1 length field (2 bytes) + "Exif\0\0" have to fit in a 16-bit segment length
+const MAX_TIFF_IN_APP1: usize = 0xFFFF - 2 - 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ifd {
+    Primary,
+    Exif,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExifDates {
+    pub date_time_original: Option<String>,
+    pub create_date: Option<String>,
+    pub offset_time_original: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteOrder {
+    Intel,
+    Motorola,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            ByteOrder::Intel => u16::from_le_bytes(bytes),
+            ByteOrder::Motorola => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            ByteOrder::Intel => u32::from_le_bytes(bytes),
+            ByteOrder::Motorola => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn u16_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::Intel => value.to_le_bytes(),
+            ByteOrder::Motorola => value.to_be_bytes(),
+        }
+    }
+
+    fn u32_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Intel => value.to_le_bytes(),
+            ByteOrder::Motorola => value.to_be_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    // the raw 4 byte value/offset field, in the file byte order
+    value: [u8; 4],
+    // value bytes stored after the IFD when they do not fit in `value`
+    out_of_line: Option<Vec<u8>>,
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    order: ByteOrder,
+}
+
+impl<'a> Tiff<'a> {
+    fn parse(data: &'a [u8]) -> Result<Tiff<'a>, String> {
+        if data.len() < 8 {
+            return Err("TIFF header too short".to_string());
+        }
+        let order = match &data[0..2] {
+            b"II" => ByteOrder::Intel,
+            b"MM" => ByteOrder::Motorola,
+            _ => return Err("invalid TIFF byte order".to_string()),
+        };
+        if order.u16(&data[2..4]) != 42 {
+            return Err("invalid TIFF magic number".to_string());
+        }
+        Ok(Tiff { data, order })
+    }
+
+    fn ifd0_offset(&self) -> usize {
+        self.order.u32(&self.data[4..8]) as usize
+    }
+
+    // Returns the entries and the next IFD offset
+    fn read_ifd(&self, offset: usize) -> Result<(Vec<IfdEntry>, u32), String> {
+        let count_bytes = self.data.get(offset..offset + 2).ok_or("IFD offset out of range")?;
+        let count = self.order.u16(count_bytes) as usize;
+        let entries_end = offset + 2 + count * 12;
+        let next_bytes = self.data.get(entries_end..entries_end + 4).ok_or("IFD runs past the end of the EXIF block")?;
+
+        let entries = (0..count)
+            .map(|index| {
+                let start = offset + 2 + index * 12;
+                let raw = &self.data[start..start + 12];
+                let mut value = [0u8; 4];
+                value.copy_from_slice(&raw[8..12]);
+                IfdEntry {
+                    tag: self.order.u16(&raw[0..2]),
+                    field_type: self.order.u16(&raw[2..4]),
+                    count: self.order.u32(&raw[4..8]),
+                    value,
+                    out_of_line: None,
+                }
+            })
+            .collect();
+
+        Ok((entries, self.order.u32(next_bytes)))
+    }
+
+    fn exif_ifd_offset(&self) -> Result<Option<usize>, String> {
+        let (entries, _) = self.read_ifd(self.ifd0_offset())?;
+        Ok(entries
+            .iter()
+            .find(|entry| entry.tag == TAG_EXIF_IFD_POINTER)
+            .map(|entry| self.order.u32(&entry.value) as usize))
+    }
+
+    fn read_ascii(&self, entry: &IfdEntry) -> Option<String> {
+        if entry.field_type != TYPE_ASCII {
+            return None;
+        }
+        let count = entry.count as usize;
+        let bytes = if count <= 4 {
+            &entry.value[..count]
+        } else {
+            let offset = self.order.u32(&entry.value) as usize;
+            self.data.get(offset..offset + count)?
+        };
+        let text = bytes.split(|&b| b == 0).next().unwrap_or(&[]);
+        Some(String::from_utf8_lossy(text).trim().to_string())
+    }
+
+    fn ifd_offset(&self, ifd: Ifd) -> Result<Option<usize>, String> {
+        match ifd {
+            Ifd::Primary => Ok(Some(self.ifd0_offset())),
+            Ifd::Exif => self.exif_ifd_offset(),
+        }
+    }
+}
+
+pub fn read_ascii_tag(tiff: &[u8], ifd: Ifd, tag: u16) -> Result<Option<String>, String> {
+    let tiff = Tiff::parse(tiff)?;
+    let Some(offset) = tiff.ifd_offset(ifd)? else {
+        return Ok(None);
+    };
+    let (entries, _) = tiff.read_ifd(offset)?;
+    Ok(entries
+        .iter()
+        .find(|entry| entry.tag == tag)
+        .and_then(|entry| tiff.read_ascii(entry))
+        .filter(|text| !text.is_empty()))
+}
+
+pub fn read_exif_dates(tiff: &[u8]) -> Result<ExifDates, String> {
+    Ok(ExifDates {
+        date_time_original: read_ascii_tag(tiff, Ifd::Exif, TAG_DATE_TIME_ORIGINAL)?,
+        create_date: read_ascii_tag(tiff, Ifd::Exif, TAG_CREATE_DATE)?,
+        offset_time_original: read_ascii_tag(tiff, Ifd::Exif, TAG_OFFSET_TIME_ORIGINAL)?,
+    })
+}
+
+// Only a GPS IFD that actually holds a latitude counts, some cameras write an empty one
+pub fn has_gps_coordinates(tiff: &[u8]) -> Result<bool, String> {
+    let tiff = Tiff::parse(tiff)?;
+    let (ifd0_entries, _) = tiff.read_ifd(tiff.ifd0_offset())?;
+    let Some(pointer) = ifd0_entries.iter().find(|entry| entry.tag == TAG_GPS_IFD_POINTER) else {
+        return Ok(false);
+    };
+    let (gps_entries, _) = tiff.read_ifd(tiff.order.u32(&pointer.value) as usize)?;
+    Ok(gps_entries.iter().any(|entry| entry.tag == TAG_GPS_LATITUDE))
+}
+
+fn ascii_entry(tag: u16, text: &str) -> IfdEntry {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    let mut value = [0u8; 4];
+    let out_of_line = if bytes.len() <= 4 {
+        value[..bytes.len()].copy_from_slice(&bytes);
+        None
+    } else {
+        Some(bytes.clone())
+    };
+    IfdEntry { tag, field_type: TYPE_ASCII, count: bytes.len() as u32, value, out_of_line }
+}
+
+fn pointer_entry(order: ByteOrder, tag: u16, offset: u32) -> IfdEntry {
+    IfdEntry { tag, field_type: TYPE_LONG, count: 1, value: order.u32_bytes(offset), out_of_line: None }
+}
+
+// Appends the IFD (word aligned) followed by its out-of-line values, returns the IFD offset
+fn append_ifd(data: &mut Vec<u8>, order: ByteOrder, mut entries: Vec<IfdEntry>, next_ifd: u32) -> u32 {
+    entries.sort_by_key(|entry| entry.tag);
+
+    if data.len() % 2 == 1 {
+        data.push(0);
+    }
+    let ifd_offset = data.len();
+    let mut value_offset = ifd_offset + 2 + entries.len() * 12 + 4;
+
+    let mut ifd = Vec::new();
+    let mut values = Vec::new();
+    ifd.extend_from_slice(&order.u16_bytes(entries.len() as u16));
+    for entry in &entries {
+        ifd.extend_from_slice(&order.u16_bytes(entry.tag));
+        ifd.extend_from_slice(&order.u16_bytes(entry.field_type));
+        ifd.extend_from_slice(&order.u32_bytes(entry.count));
+        match &entry.out_of_line {
+            Some(bytes) => {
+                ifd.extend_from_slice(&order.u32_bytes(value_offset as u32));
+                values.extend_from_slice(bytes);
+                if bytes.len() % 2 == 1 {
+                    values.push(0);
+                }
+                value_offset = ifd_offset + 2 + entries.len() * 12 + 4 + values.len();
+            },
+            None => ifd.extend_from_slice(&entry.value),
+        }
+    }
+    ifd.extend_from_slice(&order.u32_bytes(next_ifd));
+
+    data.extend_from_slice(&ifd);
+    data.extend_from_slice(&values);
+    ifd_offset as u32
+}
+
+// Sets (adds or replaces) ASCII tags of the Exif IFD.
+// With no existing TIFF block a new one is created, holding only these tags.
+pub fn set_exif_ascii_tags(tiff: Option<&[u8]>, tags: &[(u16, String)]) -> Result<Vec<u8>, String> {
+    let Some(tiff_data) = tiff else {
+        let order = ByteOrder::Motorola;
+        let mut data = b"MM\0\x2a\0\0\0\x08".to_vec();
+        // IFD0 holds only the Exif pointer: 2 + 12 + 4 bytes, so the Exif IFD starts at 8 + 18
+        let exif_ifd_offset = 8 + 18;
+        append_ifd(&mut data, order, vec![pointer_entry(order, TAG_EXIF_IFD_POINTER, exif_ifd_offset)], 0);
+        let exif_entries = tags.iter().map(|(tag, text)| ascii_entry(*tag, text)).collect();
+        append_ifd(&mut data, order, exif_entries, 0);
+        return Ok(data);
+    };
+
+    let parsed = Tiff::parse(tiff_data)?;
+    let order = parsed.order;
+    let ifd0_offset = parsed.ifd0_offset();
+    let (ifd0_entries, ifd0_next) = parsed.read_ifd(ifd0_offset)?;
+
+    let pointer_index = ifd0_entries.iter().position(|entry| entry.tag == TAG_EXIF_IFD_POINTER);
+    let mut exif_entries = match pointer_index {
+        Some(index) => parsed.read_ifd(order.u32(&ifd0_entries[index].value) as usize)?.0,
+        None => Vec::new(),
+    };
+    exif_entries.retain(|entry| !tags.iter().any(|(tag, _)| *tag == entry.tag));
+    exif_entries.extend(tags.iter().map(|(tag, text)| ascii_entry(*tag, text)));
+
+    let mut data = tiff_data.to_vec();
+    let new_exif_offset = append_ifd(&mut data, order, exif_entries, 0);
+
+    match pointer_index {
+        Some(index) => {
+            // Update the pointer in place, IFD0 itself does not move
+            let value_start = ifd0_offset + 2 + index * 12 + 8;
+            data[value_start..value_start + 4].copy_from_slice(&order.u32_bytes(new_exif_offset));
+        },
+        None => {
+            let mut new_ifd0_entries = ifd0_entries;
+            new_ifd0_entries.push(pointer_entry(order, TAG_EXIF_IFD_POINTER, new_exif_offset));
+            let new_ifd0_offset = append_ifd(&mut data, order, new_ifd0_entries, ifd0_next);
+            data[4..8].copy_from_slice(&order.u32_bytes(new_ifd0_offset));
+        },
+    }
+
+    Ok(data)
+}
+
+// JPEG container: the TIFF block lives in an APP1 segment starting with "Exif\0\0"
+
+// The byte range of the whole APP1 segment and of the TIFF block inside it
+type ExifSegment = (Range<usize>, Range<usize>);
+
+fn find_jpeg_exif_segment(jpeg: &[u8]) -> Result<Option<ExifSegment>, String> {
+    if jpeg.len() < 2 || jpeg[0..2] != [0xFF, 0xD8] {
+        return Err("not a JPEG file".to_string());
+    }
+
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() {
+        if jpeg[pos] != 0xFF {
+            return Err(format!("invalid JPEG marker at byte {}", pos));
+        }
+        let marker = jpeg[pos + 1];
+        match marker {
+            // fill bytes
+            0xFF => {
+                pos += 1;
+                continue;
+            },
+            // markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            },
+            // start of scan or end of image, no more metadata segments
+            0xDA | 0xD9 => return Ok(None),
+            _ => {},
+        }
+
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let segment_end = pos + 2 + length;
+        if length < 2 || segment_end > jpeg.len() {
+            return Err(format!("truncated JPEG segment at byte {}", pos));
+        }
+
+        let payload = &jpeg[pos + 4..segment_end];
+        if marker == 0xE1 && payload.starts_with(EXIF_HEADER) {
+            return Ok(Some((pos..segment_end, pos + 4 + EXIF_HEADER.len()..segment_end)));
+        }
+
+        pos = segment_end;
+    }
+
+    Err("JPEG ended before the image data".to_string())
+}
+
+pub fn read_jpeg_exif(jpeg: &[u8]) -> Result<Option<&[u8]>, String> {
+    Ok(find_jpeg_exif_segment(jpeg)?.map(|(_, tiff_range)| &jpeg[tiff_range]))
+}
+
+// Replaces the Exif APP1 segment, or inserts one right after SOI (and after a JFIF APP0 segment,
+// which has to stay first)
+pub fn write_jpeg_exif(jpeg: &[u8], tiff: &[u8]) -> Result<Vec<u8>, String> {
+    if tiff.len() > MAX_TIFF_IN_APP1 {
+        return Err(format!("EXIF block of {} bytes does not fit in a JPEG APP1 segment", tiff.len()));
+    }
+
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&((2 + EXIF_HEADER.len() + tiff.len()) as u16).to_be_bytes());
+    segment.extend_from_slice(EXIF_HEADER);
+    segment.extend_from_slice(tiff);
+
+    let replaced_range = match find_jpeg_exif_segment(jpeg)? {
+        Some((segment_range, _)) => segment_range,
+        None => {
+            let mut insert_at = 2;
+            if jpeg.len() >= 6 && jpeg[2..4] == [0xFF, 0xE0] {
+                insert_at = 4 + u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize;
+            }
+            insert_at..insert_at
+        },
+    };
+
+    let mut output = Vec::with_capacity(jpeg.len() + segment.len());
+    output.extend_from_slice(&jpeg[..replaced_range.start]);
+    output.extend_from_slice(&segment);
+    output.extend_from_slice(&jpeg[replaced_range.end..]);
+    Ok(output)
+}
+
+pub fn read_jpeg_date_tags(file_path: &Path) -> Result<ExifDates, FixError> {
+    let jpeg = fs::read(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let invalid = |message: String| FixError::InvalidMetadata { path: file_path.to_path_buf(), message };
+
+    match read_jpeg_exif(&jpeg).map_err(invalid)? {
+        Some(tiff) => read_exif_dates(tiff).map_err(invalid),
+        None => Ok(ExifDates::default()),
+    }
+}
+
+pub fn jpeg_has_gps_coordinates(file_path: &Path) -> Result<bool, FixError> {
+    let jpeg = fs::read(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let invalid = |message: String| FixError::InvalidMetadata { path: file_path.to_path_buf(), message };
+
+    match read_jpeg_exif(&jpeg).map_err(invalid)? {
+        Some(tiff) => has_gps_coordinates(tiff).map_err(invalid),
+        None => Ok(false),
+    }
+}
+
+pub fn write_jpeg_date_tags(file_path: &Path, tags: &[(u16, String)]) -> Result<(), FixError> {
+    let jpeg = fs::read(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let invalid = |message: String| FixError::InvalidMetadata { path: file_path.to_path_buf(), message };
+
+    let existing_tiff = read_jpeg_exif(&jpeg).map_err(invalid)?;
+    let tiff = set_exif_ascii_tags(existing_tiff, tags).map_err(invalid)?;
+    let output = write_jpeg_exif(&jpeg, &tiff).map_err(invalid)?;
+
+    fs::write(file_path, output).map_err(|e| FixError::io(file_path, e))
+}
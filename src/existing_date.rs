@@ -0,0 +1,68 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Utc};
+use crate::date_sources::parse_duration;
+
+// What happens to a DateTimeOriginal/CreateDate, or QuickTime creation time, already in the file.
+//
+// By default it is kept and only missing dates are filled in. The other policies compare it
+// with the resolved date and rewrite it when they say so.
+
+// Digital cameras did not write EXIF dates before that
+const EARLIEST_PLAUSIBLE_YEAR: i32 = 1990;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExistingDatePolicy {
+    #[default]
+    Keep,
+    Overwrite,
+    // Only clock resets (1970, 2000-01-01, ...) and dates in the future
+    OverwriteIfImplausible,
+    // Only when further than this from the resolved date
+    OverwriteIfDiffersBy(Duration),
+}
+
+impl ExistingDatePolicy {
+    // "keep", "overwrite", "overwrite-if-implausible" or "overwrite-if-differs-by=12h"
+    pub fn parse(text: &str) -> Result<ExistingDatePolicy, String> {
+        match text.trim() {
+            "keep" => Ok(ExistingDatePolicy::Keep),
+            "overwrite" => Ok(ExistingDatePolicy::Overwrite),
+            "overwrite-if-implausible" => Ok(ExistingDatePolicy::OverwriteIfImplausible),
+            other => match other.strip_prefix("overwrite-if-differs-by=") {
+                Some(duration) => Ok(ExistingDatePolicy::OverwriteIfDiffersBy(parse_duration(duration)?)),
+                None => Err(format!(
+                    "unknown policy {:?}, expected keep, overwrite, overwrite-if-implausible or overwrite-if-differs-by=<duration>",
+                    other
+                )),
+            },
+        }
+    }
+
+    // existing is None when the tags are there but cannot be parsed, e.g. "0000:00:00 00:00:00".
+    // Such a date is only kept by keep.
+    pub fn overwrites(&self, existing: Option<&DateTime<FixedOffset>>, resolved: &DateTime<FixedOffset>) -> bool {
+        match (self, existing) {
+            (ExistingDatePolicy::Keep, _) => false,
+            (ExistingDatePolicy::Overwrite, _) | (_, None) => true,
+            (ExistingDatePolicy::OverwriteIfImplausible, Some(existing)) => is_implausible(existing, &Utc::now()),
+            (ExistingDatePolicy::OverwriteIfDiffersBy(threshold), Some(existing)) => (*existing - *resolved).abs() > *threshold,
+        }
+    }
+
+    // How --dry-run words it for files it cannot read, e.g. Takeout zip entries
+    pub fn plan(&self) -> &'static str {
+        match self {
+            ExistingDatePolicy::Keep => "unless already present",
+            ExistingDatePolicy::Overwrite => "replacing any date already present",
+            ExistingDatePolicy::OverwriteIfImplausible => "replacing a date already present only if implausible",
+            ExistingDatePolicy::OverwriteIfDiffersBy(_) => "replacing a date already present only if too far from it",
+        }
+    }
+}
+
+// Before 1990, on January 1st 2000, or more than a day after now
+pub fn is_implausible(date_time: &DateTime<FixedOffset>, now: &DateTime<Utc>) -> bool {
+    let date = date_time.date_naive();
+    date.year() < EARLIEST_PLAUSIBLE_YEAR
+        || (date.year() == 2000 && date.ordinal() == 1)
+        || *date_time > *now + Duration::days(1)
+}
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use crate::media_kind::MediaKind;
+
+// --fix-extensions: the copies get the extension of their content, e.g. a PNG saved as IMG_0328.JPG
+// becomes IMG_0328.png in the output.
+//
+// The stem is kept, so the Live Photo video or the .AAE next to a photo still goes with it. Companion
+// files named after the whole file name, such as IMG_0328.JPG.xmp, are renamed along with it.
+
+// The name for content of that kind, None when the extension already fits it.
+// The new extension is upper case when the old one was.
+pub fn fixed_file_name(file_name: &str, kind: MediaKind) -> Option<String> {
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    if stem.is_empty() || kind.fits_extension(extension) {
+        return None;
+    }
+    let new_extension = match extension.chars().any(|c| c.is_ascii_lowercase()) {
+        true => kind.extension().to_string(),
+        false => kind.extension().to_ascii_uppercase(),
+    };
+    Some(format!("{}.{}", stem, new_extension))
+}
+
+// The new file name of each file, None when it keeps its name.
+// paths are the source files or the zip entry names, kinds their detected content, and is_taken tells
+// whether a name is already used next to them. A file whose new name is taken keeps its extension,
+// a counter would split it from its companions.
+pub fn fix_file_names(paths: &[&Path], kinds: &[MediaKind], is_taken: impl Fn(&Path) -> bool) -> Vec<Option<String>> {
+    let mut claimed = HashSet::new();
+    let mut claim = |path: &Path, new_name: String| {
+        let new_path = path.with_file_name(&new_name);
+        (!is_taken(&new_path) && claimed.insert(new_path)).then_some(new_name)
+    };
+
+    let mut renamed: HashMap<&Path, String> = HashMap::new();
+    let mut new_names = Vec::new();
+    for (path, kind) in paths.iter().zip(kinds) {
+        let new_name = file_name(path).and_then(|file_name| fixed_file_name(file_name, *kind)).and_then(|new_name| claim(path, new_name));
+        if let Some(new_name) = &new_name {
+            renamed.insert(path, new_name.clone());
+        }
+        new_names.push(new_name);
+    }
+
+    // e.g. IMG_0328.JPG.xmp follows IMG_0328.JPG
+    for (path, new_name) in paths.iter().zip(new_names.iter_mut()) {
+        let Some(file_name) = file_name(path).filter(|_| new_name.is_none()) else { continue };
+        *new_name = file_name
+            .match_indices('.')
+            .find_map(|(dot, _)| renamed.get(path.with_file_name(&file_name[..dot]).as_path()).map(|fixed| format!("{}{}", fixed, &file_name[dot..])))
+            .and_then(|companion_name| claim(path, companion_name));
+    }
+    new_names
+}
+
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(|name| name.to_str())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionFix {
+    pub path: PathBuf,
+    // The copy in the output
+    pub renamed_to: PathBuf,
+}
+
+// Copies given another extension than their source, printed at the end of the run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExtensionReport {
+    pub fixes: Vec<ExtensionFix>,
+}
+
+impl ExtensionReport {
+    pub fn record(&mut self, path: impl AsRef<Path>, renamed_to: impl AsRef<Path>) {
+        self.fixes.push(ExtensionFix { path: path.as_ref().to_path_buf(), renamed_to: renamed_to.as_ref().to_path_buf() });
+    }
+
+    pub fn summary(&self) -> String {
+        if self.fixes.is_empty() {
+            return String::new();
+        }
+        let mut summary = format!("{} file(s) renamed to the extension of their content:\n", self.fixes.len());
+        for fix in &self.fixes {
+            summary.push_str(&format!("  {} -> {}\n", fix.path.display(), fix.renamed_to.display()));
+        }
+        summary
+    }
+}
@@ -1,80 +1,260 @@
+use std::collections::HashSet;
 use std::fs;
+use std::io;
 use filetime::{FileTime, set_file_times};
 use std::str;
-use std::path::{Path, PathBuf};
-use std::io;
-use rand::{Rng, thread_rng};
-use rand::distributions::Alphanumeric;
+use std::path::{Component, Path, PathBuf};
 use std::ffi::OsStr;
+use crate::error::FixError;
+use crate::log_line;
 
 
-pub(crate) fn restore_file_modification_time(file_path: &str, metadata: fs::Metadata) -> std::io::Result<()> {
+pub(crate) fn restore_file_modification_time(file_path: &str, metadata: fs::Metadata) -> Result<(), FixError> {
     // usage:
     // let metadata = fs::metadata(src_path)?;
     // restore_file_modification_time(file_path, metadata);
     //
     let accessed = FileTime::from_last_access_time(&metadata);
     let modified = FileTime::from_last_modification_time(&metadata);
-    set_file_times(file_path, accessed, modified)?;
+    set_file_times(file_path, accessed, modified).map_err(|e| FixError::io(file_path, e))?;
     Ok(())
 }
 
-pub fn copy_file_preserving_metadata(src: &Path, dest: &Path) -> io::Result<()> {
-    println!("copying src_path to dest_path: {:?} {:?}", src, dest);
-    let final_dest = if dest.exists() {
-        let unique_path = generate_unique_path(dest);
-        println!("generate_unique_path -> final_dest: {:?}", unique_path);
-        unique_path
-    } else {
-        dest.to_path_buf()
+// Returns the path actually written, which differs from dest when dest already exists and on_collision renames.
+// None when on_collision leaves the file already at dest alone.
+pub fn copy_file_preserving_metadata(src: &Path, dest: &Path, on_collision: CollisionPolicy) -> Result<Option<PathBuf>, FixError> {
+    log_line!("copying src_path to dest_path: {:?} {:?}", src, dest);
+    let Some(final_dest) = collision_destination(dest, on_collision)? else {
+        log_line!("{:?} already exists, left alone", dest);
+        return Ok(None);
     };
+    if final_dest != dest {
+        log_line!("generate_unique_path -> final_dest: {:?}", final_dest);
+    }
 
-    fs::copy(src, &final_dest)?;
-    let metadata = fs::metadata(src)?;
+    fs::copy(src, &final_dest).map_err(|e| FixError::io(src, e))?;
+    let metadata = fs::metadata(src).map_err(|e| FixError::io(src, e))?;
     let accessed = FileTime::from_last_access_time(&metadata);
     let modified = FileTime::from_last_modification_time(&metadata);
-    set_file_times(&final_dest, accessed, modified)?;
-    Ok(())
+    set_file_times(&final_dest, accessed, modified).map_err(|e| FixError::io(&final_dest, e))?;
+    Ok(Some(final_dest))
 }
 
-fn generate_random_string(length: usize) -> String {
-    thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(length)
-        .map(char::from)
-        .collect()
+// --on-collision: what happens to a file already at the destination, e.g. from an earlier run.
+// Files of the same run sharing a name are always renamed with --collision-naming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    #[default]
+    Rename,
+    Skip,
+    Overwrite,
+    // Skips when the file there has the same content, renames otherwise
+    SkipIfIdentical,
+    Fail,
 }
 
-fn generate_unique_path(dest: &Path) -> PathBuf {
-    let mut new_dest = dest.to_path_buf();
-    let file_stem = dest.file_stem().unwrap_or_else(|| OsStr::new("")).to_str().unwrap_or("");
-    let extension = dest.extension().unwrap_or_else(|| OsStr::new("")).to_str().unwrap_or("");
-
-    while new_dest.exists() {
-        let random_string = generate_random_string(6); // Generates a 6-character random string
-        let new_file_name = format!("{}-{}.{}", file_stem, random_string, extension);
-        new_dest = dest.with_file_name(new_file_name);
+impl CollisionPolicy {
+    pub fn parse(name: &str) -> Result<CollisionPolicy, String> {
+        match name {
+            "rename" => Ok(CollisionPolicy::Rename),
+            "skip" => Ok(CollisionPolicy::Skip),
+            "overwrite" => Ok(CollisionPolicy::Overwrite),
+            "skip-if-identical" => Ok(CollisionPolicy::SkipIfIdentical),
+            "fail" => Ok(CollisionPolicy::Fail),
+            _ => Err(format!("unknown --on-collision value {:?}, expected rename, skip, overwrite, skip-if-identical or fail", name)),
+        }
     }
 
-    new_dest
+    // How --dry-run words it
+    pub fn plan(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Rename => "renamed",
+            CollisionPolicy::Skip => "left alone",
+            CollisionPolicy::Overwrite => "overwritten",
+            CollisionPolicy::SkipIfIdentical => "left alone if identical, renamed otherwise",
+            CollisionPolicy::Fail => "the file fails",
+        }
+    }
+}
+
+// Where a file goes when something may already be at dest, None to leave that alone.
+// With skip-if-identical, reserve_destination only gives a taken name when the file there is identical.
+pub fn collision_destination(dest: &Path, on_collision: CollisionPolicy) -> Result<Option<PathBuf>, FixError> {
+    // A dangling symbolic link is in the way as well
+    if fs::symlink_metadata(dest).is_err() {
+        return Ok(Some(dest.to_path_buf()));
+    }
+
+    match on_collision {
+        CollisionPolicy::Rename => Ok(Some(generate_unique_path(dest))),
+        CollisionPolicy::Skip | CollisionPolicy::SkipIfIdentical => Ok(None),
+        CollisionPolicy::Overwrite => {
+            // Removed rather than written through, another album may have a hard link to it
+            fs::remove_file(dest).map_err(|e| FixError::io(dest, e))?;
+            Ok(Some(dest.to_path_buf()))
+        },
+        CollisionPolicy::Fail => Err(FixError::DestinationExists { path: dest.to_path_buf() }),
+    }
+}
+
+pub fn create_parent_dir(path: &Path) -> Result<(), FixError> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent).map_err(|e| FixError::io(parent, e)),
+        _ => Ok(()),
+    }
+}
+
+// A second name for target, both inside the output directory.
+// Symbolic links are relative, so the output directory can be moved as a whole.
+pub fn link_file(target: &Path, link: &Path, symbolic: bool) -> Result<(), FixError> {
+    create_parent_dir(link)?;
+    let linked = if symbolic {
+        symlink_file(&relative_path(link.parent().unwrap_or_else(|| Path::new("")), target), link)
+    } else {
+        fs::hard_link(target, link)
+    };
+    linked.map_err(|e| FixError::io(link, e))
+}
+
+#[cfg(unix)]
+fn symlink_file(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_file(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
 }
 
-pub struct PathComponents {
-    pub parent_path: PathBuf,
-    pub file_name: String,
+// e.g. "../Trip/IMG_0001.JPG" from "output/Family" to "output/Trip/IMG_0001.JPG"
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().filter(|component| *component != Component::CurDir).collect();
+    let to_components: Vec<Component> = to.components().filter(|component| *component != Component::CurDir).collect();
+    let common = from.iter().zip(&to_components).take_while(|(a, b)| a == b).count();
+
+    let mut relative: PathBuf = from[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&to_components[common..]);
+    relative
+}
+
+// --collision-naming: how a file is renamed when its name is already taken in the output.
+// The same input always gives the same names, so two runs give the same output tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionNaming {
+    // IMG_0001-1.JPG, IMG_0001-2.JPG, ...
+    #[default]
+    Counter,
+    // IMG_0001-3f2a9c1e.JPG, the start of the content hash of the source
+    Hash,
+    // Trip_IMG_0001.JPG, the folder the source was in
+    Folder,
+}
+
+// Length of the content hash put in the name
+const SHORT_HASH_LENGTH: usize = 8;
+
+impl CollisionNaming {
+    pub fn parse(name: &str) -> Result<CollisionNaming, String> {
+        match name {
+            "counter" => Ok(CollisionNaming::Counter),
+            "hash" => Ok(CollisionNaming::Hash),
+            "folder" => Ok(CollisionNaming::Folder),
+            _ => Err(format!("unknown --collision-naming value {:?}, expected counter, hash or folder", name)),
+        }
+    }
 }
 
-pub fn split_path_components(path: &Path) -> PathComponents {
-    let parent_path = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
-    let file_name = path.file_name().unwrap_or_else(|| std::ffi::OsStr::new("")).to_string_lossy().to_string();
+// Like reserve_unique_path, but with an --on-collision policy other than rename the names only go
+// by the files of this run, so a rerun gives every file the name it got before, and the policy is
+// applied to a file already there when copying.
+// With skip-if-identical, a file already there that is_identical does not find identical keeps its
+// name, the copy gets a free one.
+pub fn reserve_destination(
+    dest: &Path,
+    naming: CollisionNaming,
+    on_collision: CollisionPolicy,
+    source: &Path,
+    hash: impl Fn() -> Option<String>,
+    is_identical: impl FnOnce(&Path) -> bool,
+    reserved: &mut HashSet<PathBuf>,
+) -> PathBuf {
+    if on_collision == CollisionPolicy::Rename {
+        return reserve_unique_path(dest, naming, source, hash, reserved);
+    }
 
-    PathComponents {
-        parent_path,
-        file_name,
+    let in_run = reserve_path(dest, naming, source, &hash, |path| reserved.contains(path));
+    reserved.insert(in_run.clone());
+    let taken = fs::symlink_metadata(&in_run).is_ok();
+    match on_collision {
+        CollisionPolicy::SkipIfIdentical if taken && !is_identical(&in_run) => reserve_unique_path(dest, naming, source, hash, reserved),
+        _ => in_run,
     }
 }
 
-pub fn get_extension(image_file_path: &str) -> Option<&str> {
-    let path = Path::new(image_file_path);
-    path.extension().and_then(OsStr::to_str)
-}
\ No newline at end of file
+fn generate_unique_path(dest: &Path) -> PathBuf {
+    generate_unique_path_avoiding(dest, None, |path| path.exists())
+}
+
+// Same as generate_unique_path, but also skips the names already handed out in this run.
+// Lets every copy get its destination up front, before several workers start writing.
+// source is the source file, or "<zip part>/<entry name>", and hash gives its content hash,
+// e.g. "sha256:..." or "crc32:...". Both are only used when the name is taken.
+pub fn reserve_unique_path(
+    dest: &Path,
+    naming: CollisionNaming,
+    source: &Path,
+    hash: impl FnOnce() -> Option<String>,
+    reserved: &mut HashSet<PathBuf>,
+) -> PathBuf {
+    let unique_path = reserve_path(dest, naming, source, hash, |path| path.exists() || reserved.contains(path));
+    reserved.insert(unique_path.clone());
+    unique_path
+}
+
+// The name --collision-naming gives dest when is_taken says it is taken
+fn reserve_path(
+    dest: &Path,
+    naming: CollisionNaming,
+    source: &Path,
+    hash: impl FnOnce() -> Option<String>,
+    is_taken: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    let renamed = match naming {
+        _ if !is_taken(dest) => None,
+        CollisionNaming::Counter => None,
+        CollisionNaming::Hash => hash().map(|hash| {
+            let digest = hash.rsplit(':').next().unwrap_or_default();
+            with_file_stem(dest, |stem| format!("{}-{}", stem, &digest[..digest.len().min(SHORT_HASH_LENGTH)]))
+        }),
+        CollisionNaming::Folder => source
+            .parent()
+            .and_then(|folder| folder.file_name())
+            .map(|folder| with_file_stem(dest, |stem| format!("{}_{}", folder.to_string_lossy(), stem))),
+    };
+
+    generate_unique_path_avoiding(dest, renamed, is_taken)
+}
+
+// The renamed path is tried first, then a counter is added until the name is free
+fn generate_unique_path_avoiding(dest: &Path, renamed: Option<PathBuf>, is_taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let base = match renamed {
+        Some(renamed) if !is_taken(&renamed) => return renamed,
+        Some(renamed) => renamed,
+        None if !is_taken(dest) => return dest.to_path_buf(),
+        None => dest.to_path_buf(),
+    };
+
+    (1..)
+        .map(|counter| with_file_stem(&base, |stem| format!("{}-{}", stem, counter)))
+        .find(|new_dest| !is_taken(new_dest))
+        .expect("a free name")
+}
+
+fn with_file_stem(path: &Path, rename: impl FnOnce(&str) -> String) -> PathBuf {
+    let file_stem = path.file_stem().unwrap_or_else(|| OsStr::new("")).to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}", rename(&file_stem), extension.to_string_lossy()),
+        None => rename(&file_stem),
+    };
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::path::Path;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use crate::error::FixError;
+
+// Capture dates embedded in file names.
+//
+// Every pattern is a regex with the named groups year, month and day, and optionally hour,
+// minute and second. The patterns are tried in order and the first one giving a valid date wins:
+// the ones of the --filename-patterns file first, then the built-in ones.
+
+const BUILT_IN_PATTERNS: [&str; 3] = [
+    // WhatsApp Image 2021-05-06 at 12.34.56.jpeg
+    r"(?P<year>(?:19|20)\d{2})-(?P<month>\d{2})-(?P<day>\d{2}) at (?P<hour>\d{2})\.(?P<minute>\d{2})\.(?P<second>\d{2})",
+    // IMG_20190704_183012.jpg, PXL_20231224_081530123.mp4, Screenshot_2020-01-02-10-11-12.png,
+    // signal-2022-03-04-101112.jpg, 2019-07-04 18.30.12.jpg
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})[-_.]?(?P<month>\d{2})[-_.]?(?P<day>\d{2})[-_ T.]?(?P<hour>\d{2})[-_.:]?(?P<minute>\d{2})[-_.:]?(?P<second>\d{2})",
+    // VID-20180512-WA0003.mp4, taken as midnight
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})[-_.]?(?P<month>\d{2})[-_.]?(?P<day>\d{2})(?:\D|$)",
+];
+
+#[derive(Debug, Clone)]
+pub struct FileNameDateExtractor {
+    patterns: Vec<Regex>,
+}
+
+impl Default for FileNameDateExtractor {
+    fn default() -> FileNameDateExtractor {
+        FileNameDateExtractor::with_patterns(Vec::new())
+    }
+}
+
+impl FileNameDateExtractor {
+    // User patterns are tried before the built-in ones
+    pub fn with_patterns(user_patterns: Vec<Regex>) -> FileNameDateExtractor {
+        let built_in = BUILT_IN_PATTERNS.iter().map(|pattern| Regex::new(pattern).expect("built-in file name pattern"));
+        FileNameDateExtractor { patterns: user_patterns.into_iter().chain(built_in).collect() }
+    }
+
+    pub fn date_time(&self, file_name: &str) -> Option<NaiveDateTime> {
+        self.patterns.iter().find_map(|pattern| {
+            pattern.captures_iter(file_name).find_map(|captures| {
+                let number = |name: &str| captures.name(name).and_then(|value| value.as_str().parse::<u32>().ok());
+                let year = match number("year")? {
+                    // "21" from a pattern with a two digit year
+                    year @ 0..=99 => 2000 + year,
+                    year => year,
+                };
+                let date = NaiveDate::from_ymd_opt(year as i32, number("month")?, number("day")?)?;
+                let time = match captures.name("hour") {
+                    Some(_) => NaiveTime::from_hms_opt(number("hour")?, number("minute").unwrap_or(0), number("second").unwrap_or(0))?,
+                    None => NaiveTime::MIN,
+                };
+                Some(date.and_time(time))
+            })
+        })
+    }
+}
+
+// One regex per line, blank lines and lines starting with # are ignored:
+//   ^DJI_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})
+pub fn parse_file_name_patterns(text: &str) -> Result<Vec<Regex>, String> {
+    let mut patterns = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: String| format!("line {}: {}", index + 1, message);
+        let pattern = Regex::new(line).map_err(|e| invalid(e.to_string()))?;
+        let names: Vec<&str> = pattern.capture_names().flatten().collect();
+        if let Some(missing) = ["year", "month", "day"].into_iter().find(|name| !names.contains(name)) {
+            return Err(invalid(format!("the pattern has no (?P<{}>...) group", missing)));
+        }
+        patterns.push(pattern);
+    }
+
+    Ok(patterns)
+}
+
+pub fn read_file_name_patterns(pattern_file: &Path) -> Result<FileNameDateExtractor, FixError> {
+    let text = fs::read_to_string(pattern_file).map_err(|e| FixError::io(pattern_file, e))?;
+    let patterns = parse_file_name_patterns(&text).map_err(|message| FixError::InvalidMetadata { path: pattern_file.to_path_buf(), message })?;
+    Ok(FileNameDateExtractor::with_patterns(patterns))
+}
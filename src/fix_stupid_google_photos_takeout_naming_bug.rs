@@ -1,7 +1,7 @@
 use std::ffi::OsStr;
 use regex::Regex;
 use std::path::{Path, PathBuf};
-use crate::file_utils; // Import the file_utils module
+use std::sync::OnceLock;
 
 // This is a stupid bug in Takeout from google photos
 
@@ -11,38 +11,43 @@ use crate::file_utils; // Import the file_utils module
 // IMG_0253.HEIC(1).json -> IMG_0253(1).HEIC
 // sample.gif.json -> sample.gif
 // sample.png.json -> sample.png
+// IMG_1234.JPG.supplemental-metadata.json -> IMG_1234.JPG
+// IMG_1234.JPG.supplemental-metadata(1).json -> IMG_1234(1).JPG
+// IMG_1234.JPG.supplemental-me.json -> IMG_1234.JPG
+
+pub const SUPPLEMENTAL_METADATA: &str = "supplemental-metadata";
+
+// Newer exports name the sidecar "<media name>.supplemental-metadata.json", with the duplicate counter
+// in front of ".json". The whole name is cut to 51 characters, so the suffix can be any prefix of
+// "supplemental-metadata", down to ".s.json".
+// Returns the media file name, or None for any other naming.
+pub fn strip_supplemental_metadata(file_name: &str) -> Option<String> {
+    static SUPPLEMENTAL: OnceLock<Regex> = OnceLock::new();
+    let regex = SUPPLEMENTAL.get_or_init(|| Regex::new(r"(?i)^(?P<media>.+)\.(?P<suffix>[a-z-]+)(\((?P<number>\d+)\))?\.json$").unwrap());
+    let caps = regex.captures(file_name)?;
+
+    let suffix = caps.name("suffix").unwrap().as_str().to_ascii_lowercase();
+    if !SUPPLEMENTAL_METADATA.starts_with(&suffix) {
+        return None;
+    }
 
-
-
-
-// pub fn swap_position(json_file_path: &Path) -> Option<PathBuf> {
-//     let regex = Regex::new(r"(?P<filename>.+?)\.(?P<extension>[^.]+)\((?P<number>\d+)\)\.json").unwrap();
-//
-//     // Get the string representation of the path
-//     let path_str = json_file_path.to_str()?;
-//
-//     // Capture the groups using regex
-//     regex.captures(path_str).map(|caps| {
-//         // Create a new PathBuf from the original path
-//         let mut new_path = json_file_path.to_path_buf();
-//
-//         // Build new file name with swapped order
-//         let new_file_name = format!("{filename}({number}).{extension}.json",
-//                                     filename = caps.name("filename").unwrap().as_str(),
-//                                     extension = caps.name("extension").unwrap().as_str(),
-//                                     number = caps.name("number").unwrap().as_str().parse::<u32>().unwrap());
-//
-//         // Change to the new file name by applying regex
-//         new_path.set_file_name(new_file_name);
-//
-//         // Return the new path
-//         new_path
-//     })
-// }
-
+    let media = caps.name("media").unwrap().as_str();
+    match caps.name("number") {
+        Some(number) => match media.rsplit_once('.') {
+            Some((stem, extension)) => Some(format!("{}({}).{}", stem, number.as_str(), extension)),
+            None => Some(format!("{}({})", media, number.as_str())),
+        },
+        None => Some(media.to_string()),
+    }
+}
 
 pub fn swap_file_name_str_position(file_name: &str) -> String {
-    let regex = Regex::new(r"(?P<filename>.+?)\.(?P<extension>[^.]+)\((?P<number>\d+)\)\.json").unwrap();
+    if let Some(media) = strip_supplemental_metadata(file_name) {
+        return format!("{}.json", media);
+    }
+
+    static COUNTER: OnceLock<Regex> = OnceLock::new();
+    let regex = COUNTER.get_or_init(|| Regex::new(r"(?P<filename>.+?)\.(?P<extension>[^.]+)\((?P<number>\d+)\)\.json").unwrap());
 
     if let Some(caps) = regex.captures(file_name) {
         format!("{filename}({number}).{extension}.json",
@@ -58,47 +63,19 @@ pub fn swap_file_name_str_position(file_name: &str) -> String {
 
 pub fn swap_position(json_file_path: &Path) -> Option<PathBuf> {
     // Split the path into components
-    let components = file_utils::split_path_components(json_file_path);
+    let parent_path = json_file_path.parent().unwrap_or_else(|| Path::new(""));
+    let file_name = json_file_path.file_name().unwrap_or_else(|| OsStr::new("")).to_string_lossy();
 
     // Handle the file name
-    let new_file_name = swap_file_name_str_position(&components.file_name);
+    let new_file_name = swap_file_name_str_position(&file_name);
 
     // Concatenate old parent path and new_file_name
-    let mut new_path = components.parent_path;
+    let mut new_path = parent_path.to_path_buf();
     new_path.push(new_file_name);
 
     Some(new_path)
 }
 
-// pub fn swap_position(json_file_path: &Path) -> Option<PathBuf> {
-//     let regex = Regex::new(r"(?P<filename>.+?)\.(?P<extension>[^.]+)\((?P<number>\d+)\)\.json").unwrap();
-//
-//     // Get the string representation of the path
-//     let path_str = json_file_path.to_str()?;
-//
-//     // Capture the groups using regex
-//     regex.captures(path_str).map(|caps| {
-//         // Create a new PathBuf from the parent directory of the original path
-//         let parent_dir = json_file_path.parent().unwrap_or_else(|| Path::new(""));
-//         let mut new_path = parent_dir.to_path_buf();
-//
-//         // Build new file name with swapped order
-//         let new_file_name = format!("{filename}({number}).{extension}.json",
-//                                     filename = caps.name("filename").unwrap().as_str(),
-//                                     extension = caps.name("extension").unwrap().as_str(),
-//                                     number = caps.name("number").unwrap().as_str().parse::<u32>().unwrap());
-//
-//         // Change to the new file name by applying regex
-//         new_path.push(new_file_name);
-//
-//         // Return the new path
-//         new_path
-//     })
-// }
-
-
-
-
 fn strip_json_suffix(json_file_path: &Path) -> PathBuf {
     // This code makes use of the with_extension method
     // to remove the .json suffix.
@@ -121,6 +98,7 @@ pub fn get_new_image_file_path_by_swap_position(json_file_path: &Path) -> PathBu
 
 // BAAC2A4F-AF2C-44EE-B4BF-5FCB1FC0EE38-5325-00000.png
 // BAAC2A4F-AF2C-44EE-B4BF-5FCB1FC0EE38-5325-0000.json
+#[allow(unused_variables, unused_assignments, clippy::let_and_return)]
 pub fn fix_image_file_path_by_fix_0(json_file_path: &Path) -> PathBuf {
 
     let mut new_dest = json_file_path.to_path_buf();
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use filetime::FileTime;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use crate::error::FixError;
+
+// Progress journal kept in the output directory, one JSON object per line.
+//
+// Every item gets a "started" line before anything is written to the output and a "done" or
+// "failed" line afterwards. A run killed in between leaves the "started" line behind, so
+// `--resume` knows the copy at `dest` may be incomplete and redoes it.
+
+pub const JOURNAL_FILE_NAME: &str = ".google_photos_fix_metadata.journal";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalStatus {
+    Started,
+    Done,
+    Failed,
+}
+
+impl JournalStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            JournalStatus::Started => "started",
+            JournalStatus::Done => "done",
+            JournalStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(status: &str) -> Option<JournalStatus> {
+        match status {
+            "started" => Some(JournalStatus::Started),
+            "done" => Some(JournalStatus::Done),
+            "failed" => Some(JournalStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalRecord {
+    // Source file, or "<zip part>/<entry name>" for zip entries
+    pub source: PathBuf,
+    // Content hash of the source, "sha256:..." for files. For zip entries the "crc32:..." of the
+    // zip directory, or "sha256:..." when the entry was read to tell it apart from another one
+    pub hash: String,
+    // e.g. "copy", "copy+metadata", "extract+metadata"
+    pub action: String,
+    pub dest: PathBuf,
+    pub status: JournalStatus,
+    pub error: Option<String>,
+    // stat_key of dest once done, tells whether the file there is still the one written
+    pub written: Option<String>,
+}
+
+impl JournalRecord {
+    pub fn new(source: impl AsRef<Path>, hash: &str, action: &str, dest: impl AsRef<Path>, status: JournalStatus) -> JournalRecord {
+        JournalRecord {
+            source: source.as_ref().to_path_buf(),
+            hash: hash.to_string(),
+            action: action.to_string(),
+            dest: dest.as_ref().to_path_buf(),
+            status,
+            error: None,
+            written: None,
+        }
+    }
+
+    // Same source content, same action, and the result is still in the output directory
+    pub fn is_completed(&self, hash: &str, action: &str) -> bool {
+        self.status == JournalStatus::Done && self.hash == hash && self.action == action && self.dest.exists()
+    }
+
+    fn to_line(&self) -> String {
+        json!({
+            "source": self.source.to_string_lossy(),
+            "hash": self.hash,
+            "action": self.action,
+            "dest": self.dest.to_string_lossy(),
+            "status": self.status.as_str(),
+            "error": self.error,
+            "written": self.written,
+        })
+        .to_string()
+    }
+
+    fn from_line(line: &str) -> Option<JournalRecord> {
+        let json: serde_json::Value = serde_json::from_str(line).ok()?;
+        Some(JournalRecord {
+            source: PathBuf::from(json.get("source")?.as_str()?),
+            hash: json.get("hash")?.as_str()?.to_string(),
+            action: json.get("action")?.as_str()?.to_string(),
+            dest: PathBuf::from(json.get("dest")?.as_str()?),
+            status: JournalStatus::parse(json.get("status")?.as_str()?)?,
+            error: json.get("error").and_then(|error| error.as_str()).map(|error| error.to_string()),
+            written: json.get("written").and_then(|written| written.as_str()).map(|written| written.to_string()),
+        })
+    }
+}
+
+// What the journal of the earlier runs says, read before a run without resume starts it over
+#[derive(Debug, Default)]
+pub struct JournalHistory {
+    // Last record of each source, only filled with resume
+    previous: HashMap<PathBuf, JournalRecord>,
+    // The last done record of each destination
+    written: HashMap<PathBuf, JournalRecord>,
+}
+
+impl JournalHistory {
+    // Reads the journal of output_dir without opening it for writing
+    pub fn read(output_dir: &Path, resume: bool) -> Result<JournalHistory, FixError> {
+        let path = output_dir.join(JOURNAL_FILE_NAME);
+        let mut history = JournalHistory::default();
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(FixError::io(&path, e)),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| FixError::io(&path, e))?;
+            // The last line is cut short when the previous run was killed while writing it
+            let Some(record) = JournalRecord::from_line(&line) else { continue };
+            if record.status == JournalStatus::Done {
+                history.written.insert(record.dest.clone(), record.clone());
+            }
+            if resume {
+                history.previous.insert(record.source.clone(), record);
+            }
+        }
+        Ok(history)
+    }
+
+    pub fn previous(&self, source: &Path) -> Option<&JournalRecord> {
+        self.previous.get(source)
+    }
+
+    // Names taken by earlier runs, even when the copy never made it to disk
+    pub fn previous_destinations(&self) -> impl Iterator<Item = &Path> {
+        self.previous.values().map(|record| record.dest.as_path())
+    }
+
+    // Whether the file at dest was written from a source with this hash by an earlier run and has
+    // not changed since, or has the same content. The file is only read when the journal does not know it.
+    // Only a SHA-256 is compared, a crc32 can match for different content.
+    pub fn written_from(&self, dest: &Path, hash: &str) -> bool {
+        if key_form(hash) != Some("sha256") {
+            return false;
+        }
+        if let Some(record) = self.written.get(dest).filter(|record| record.written.is_some() && key_form(&record.hash) == key_form(hash)) {
+            if record.written == stat_key(dest).ok() {
+                return record.hash == hash;
+            }
+        }
+        hash_file(dest).is_ok_and(|existing| existing == hash)
+    }
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+    history: JournalHistory,
+}
+
+impl Journal {
+    // Without resume the journal starts over, with it the earlier records are loaded and kept
+    pub fn open(output_dir: &Path, resume: bool) -> Result<Journal, FixError> {
+        fs::create_dir_all(output_dir).map_err(|e| FixError::io(output_dir, e))?;
+        let path = output_dir.join(JOURNAL_FILE_NAME);
+
+        let history = JournalHistory::read(output_dir, resume)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&path)
+            .map_err(|e| FixError::io(&path, e))?;
+
+        Ok(Journal { path, file: Mutex::new(file), history })
+    }
+
+    pub fn history(&self) -> &JournalHistory {
+        &self.history
+    }
+
+    pub fn previous(&self, source: &Path) -> Option<&JournalRecord> {
+        self.history.previous(source)
+    }
+
+    // Runs work between a "started" and a "done"/"failed" record
+    pub fn run<R>(&self, started: JournalRecord, work: impl FnOnce() -> Result<R, FixError>) -> Result<R, FixError> {
+        self.record(&started)?;
+        let result = work();
+        self.finish(started, &result)?;
+        result
+    }
+
+    pub fn finish<R>(&self, started: JournalRecord, result: &Result<R, FixError>) -> Result<(), FixError> {
+        let mut finished = started;
+        match result {
+            Ok(_) => {
+                finished.status = JournalStatus::Done;
+                finished.written = stat_key(&finished.dest).ok();
+            },
+            Err(e) => {
+                finished.status = JournalStatus::Failed;
+                finished.error = Some(e.to_string());
+            },
+        }
+        self.record(&finished)
+    }
+
+    pub fn record(&self, record: &JournalRecord) -> Result<(), FixError> {
+        let mut line = record.to_line();
+        line.push('\n');
+
+        // One write per line, flushed right away, so a crash loses at most the line being written
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        file.write_all(line.as_bytes()).and_then(|_| file.flush()).map_err(|e| FixError::io(&self.path, e))
+    }
+}
+
+// Output left behind by an interrupted or failed attempt, it is redone from scratch
+pub fn remove_stale_output(dest: &Path) -> Result<(), FixError> {
+    match fs::remove_file(dest) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(FixError::io(dest, e)),
+        _ => Ok(()),
+    }
+}
+
+// e.g. "sha256" or "crc32"
+fn key_form(key: &str) -> Option<&str> {
+    key.split_once(':').map(|(form, _)| form)
+}
+
+// Size and modification time, changes when the file is written to, without reading it
+pub fn stat_key(file_path: &Path) -> Result<String, FixError> {
+    let metadata = fs::metadata(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let modified = FileTime::from_last_modification_time(&metadata);
+    Ok(format!("stat:{}:{}.{:09}", metadata.len(), modified.unix_seconds(), modified.nanoseconds()))
+}
+
+pub fn hash_file(file_path: &Path) -> Result<String, FixError> {
+    let file = File::open(file_path).map_err(|e| FixError::io(file_path, e))?;
+    hash_reader(file).map_err(|e| FixError::io(file_path, e))
+}
+
+// Same as hash_file, for a zip entry
+pub fn hash_reader(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+
+    let digest = hasher.finalize();
+    Ok(format!("sha256:{}", digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()))
+}
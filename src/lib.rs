@@ -3,6 +3,28 @@ pub mod fix_stupid_google_photos_takeout_naming_bug;
 pub use fix_stupid_google_photos_takeout_naming_bug::get_new_image_file_path_by_swap_position;
 pub use fix_stupid_google_photos_takeout_naming_bug::swap_position;
 
+pub mod date_report;
+pub mod date_sources;
+pub mod dedup;
+pub mod error;
+pub mod existing_date;
+pub mod exif;
+pub mod extension_fix;
 pub mod file_utils;
-
-
+pub mod filename_date;
+pub mod journal;
+pub mod log_buffer;
+pub mod media_kind;
+pub mod metadata_utils;
+pub mod orphan_report;
+pub mod output_layout;
+pub mod placement;
+pub mod png_metadata;
+pub mod quicktime;
+pub mod report;
+pub mod sidecar_matcher;
+pub mod sidecar_metadata;
+pub mod takeout_sidecar;
+pub mod takeout_zip;
+pub mod timezone;
+pub mod worker_pool;
@@ -0,0 +1,37 @@
+use std::cell::RefCell;
+
+// Per-file log lines. Inside `capture` the lines of the current thread are kept and handed back
+// to the caller, so the output of parallel workers can be printed in input order.
+// Outside of it they go straight to stdout.
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+pub fn log(line: String) {
+    let printed = CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(lines) => {
+            lines.push(line);
+            None
+        },
+        None => Some(line),
+    });
+    if let Some(line) = printed {
+        println!("{}", line);
+    }
+}
+
+pub fn capture<R>(work: impl FnOnce() -> R) -> (R, Vec<String>) {
+    let previous = CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let result = work();
+    let lines = CAPTURED.with(|captured| captured.replace(previous)).unwrap_or_default();
+    (result, lines)
+}
+
+// println! for anything logged while a single file is processed
+#[macro_export]
+macro_rules! log_line {
+    ($($arg:tt)*) => {
+        $crate::log_buffer::log(format!($($arg)*))
+    };
+}
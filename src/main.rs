@@ -1,19 +1,43 @@
-mod metadata_utils;
-mod file_utils;
-mod fix_stupid_google_photos_takeout_naming_bug;
-
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::BufReader;
-use std::path::Path;
-use chrono::{DateTime, NaiveDateTime, Utc};
-use clap::{Arg, Command};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use clap::{Arg, ArgMatches, Command};
 use walkdir::{DirEntry, WalkDir};
-use google_photos_fix_metadata::get_new_image_file_path_by_swap_position;
-use crate::fix_stupid_google_photos_takeout_naming_bug::fix_image_file_path_by_fix_0;
-use crate::metadata_utils::add_metadata_wrapper;
+use google_photos_fix_metadata::date_sources::{self, DateInputs, DateResolver, DateSource, DEFAULT_GOOGLE_PHOTOS_PRIORITY, DEFAULT_IPHONE_PHOTOS_PRIORITY};
+use google_photos_fix_metadata::error::{path_to_str, FixError};
+use google_photos_fix_metadata::existing_date::ExistingDatePolicy;
+use google_photos_fix_metadata::file_utils::{self, CollisionNaming, CollisionPolicy};
+use google_photos_fix_metadata::filename_date::{self, FileNameDateExtractor};
+use google_photos_fix_metadata::journal::{self, Journal, JournalHistory, JournalRecord, JournalStatus};
+use google_photos_fix_metadata::log_line;
+use google_photos_fix_metadata::output_layout::{AlbumCopies, AlbumPlacement, OutputLayout};
+use google_photos_fix_metadata::placement::{kept_copy, plan_media_files, plan_takeout_zip_entries, CopyOptions, CopyPlan};
+use google_photos_fix_metadata::metadata_utils::{add_metadata_wrapper, format_metadata_value, plan_metadata_wrapper, MetadataWrite};
+use google_photos_fix_metadata::report::RunReport;
+use google_photos_fix_metadata::sidecar_matcher::{EditedPolicy, MediaIndex};
+use google_photos_fix_metadata::sidecar_metadata::{add_sidecar_metadata, plan_sidecar_metadata, MetadataOptions};
+use google_photos_fix_metadata::takeout_sidecar::TakeoutSidecar;
+use google_photos_fix_metadata::takeout_zip::{TakeoutZipEntry, TakeoutZipSet};
+use google_photos_fix_metadata::timezone::{self, TimezoneResolver};
+use google_photos_fix_metadata::worker_pool;
+
+// Subdirectories of the output directory with --output-per-source
+const GOOGLE_PHOTOS_OUTPUT: &str = "google-photos";
+const IPHONE_PHOTOS_OUTPUT: &str = "iphone-photos";
+const TAKEOUT_ZIP_OUTPUT: &str = "takeout-zip";
+
+const EXCLUDED_FILES: [&str; 3] = [
+    "print-subscriptions.json",
+    "shared_album_comments.json",
+    "user-generated-memory-titles.json",
+];
 
+// Sorted by name, so every run sees the files in the same order and gives them the same names
 fn get_recursive_file_list(path: &str) -> Vec<DirEntry> {
     WalkDir::new(path)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(|e| e.ok())
         .filter(|entry| entry.file_type().is_file())
@@ -33,25 +57,45 @@ fn filter_excluded_files(files: Vec<DirEntry>, excluded_files: &[&str]) -> Vec<D
         .collect()
 }
 
-fn get_metadata_from_json(file_path: &DirEntry) -> Option<String> {
-    let file = File::open(file_path.path()).ok()?;
-    let reader = BufReader::new(file);
-    let json: serde_json::Value = serde_json::from_reader(reader).ok()?;
-
-    let timestamp_str = json
-        .get("photoTakenTime")?
-        .get("timestamp")?
-        .as_str()?;
+// What gets applied to the copy of one media file
+#[derive(Debug, Clone)]
+struct FileMetadata {
+    // e.g. "2015:06:22 19:06:40.000+00:00"
+    date_time: String,
+    sidecar: Option<TakeoutSidecar>,
+}
 
-    let timestamp = timestamp_str.parse::<i64>().ok()?;
+fn get_sidecar_from_json(file_path: &DirEntry) -> Result<Option<TakeoutSidecar>, FixError> {
+    let file = File::open(file_path.path()).map_err(|e| FixError::io(file_path.path(), e))?;
+    get_sidecar_from_json_reader(file_path.path(), BufReader::new(file))
+}
 
-    let naive_datetime = NaiveDateTime::from_timestamp(timestamp, 0);
-    let datetime_utc: DateTime<Utc> = DateTime::from_utc(naive_datetime, Utc);
+// Only sidecars with a photoTakenTime are used, like before the full schema was read.
+// A sidecar that is not JSON at all is an error, so it is reported rather than taken for a missing one.
+fn get_sidecar_from_json_reader<R: Read>(json_file_path: &Path, reader: R) -> Result<Option<TakeoutSidecar>, FixError> {
+    let sidecar = TakeoutSidecar::from_reader(reader).map_err(|e| FixError::InvalidMetadata {
+        path: json_file_path.to_path_buf(),
+        message: e.to_string(),
+    })?;
+    Ok(sidecar.photo_taken_time().is_some().then_some(sidecar))
+}
 
-    // %H is 24-hr, verified. Do not change to %I 12-hr
-    let formatted_str = datetime_utc.format("%Y:%m:%d %H:%M:%S%.3f%:z").to_string();
+// The date goes by the priority of the resolver, the sidecar is only one of its sources.
+// photoTakenTime is UTC, the tags get the local time of the place the photo was taken at.
+// The file is reported as report_path, which is the zip part followed by the entry name for Takeout zips.
+fn resolve_metadata(
+    report_path: &Path,
+    inputs: &DateInputs,
+    dates: &DateResolver,
+    timezones: &TimezoneResolver,
+    report: &mut RunReport,
+) -> Option<(DateSource, FileMetadata)> {
+    let resolved = dates.resolve(inputs, timezones)?;
+    report.dates.record(report_path, &resolved);
 
-    Some(formatted_str)
+    let date_time = format_metadata_value(&resolved.date_time);
+    println!("Date of {:?}: {} from {}", report_path, date_time, resolved.source);
+    Some((resolved.source, FileMetadata { date_time, sidecar: inputs.sidecar.cloned() }))
 }
 
 fn get_all_json_files(all_files: Vec<DirEntry>) -> Vec<DirEntry> {
@@ -67,124 +111,961 @@ fn get_all_json_files(all_files: Vec<DirEntry>) -> Vec<DirEntry> {
         .collect()
 }
 
-fn check_if_every_json_has_media_file_beforehand(filtered_json_files: &[DirEntry]) {
+// The sidecar metadata is only collected here, it is applied to the copy in output_dir
+// so the source tree is never modified.
+// A sidecar without its media file is reported and skipped, the rest of the run goes on.
+// Edited copies get the sidecar of their original, the files --edited leaves out are returned as well.
+fn collect_google_photos_sidecars(
+    directories: &[&str],
+    edited_policy: EditedPolicy,
+    report: &mut RunReport,
+) -> (HashMap<PathBuf, TakeoutSidecar>, HashSet<PathBuf>) {
+    let mut sidecars_by_media = HashMap::new();
+    let mut skipped = HashSet::new();
 
-    let mut panic = false;
-    for file in filtered_json_files {
-        if let Some(metadata_str) = get_metadata_from_json(&file) {
+    for path in directories {
+        let all_files = get_recursive_file_list(path);
+        let media_index = MediaIndex::new(all_files.iter().map(|file| file.path()));
 
-            // let image_file_path = file.path().to_str().map(|path_str| fix_image_file_path_by_swap_position(path_str));
-            let image_file_path = get_new_image_file_path_by_swap_position(&file.path());
+        let all_json_files = get_all_json_files(all_files);
 
-            if !fs::metadata(&image_file_path).is_ok() {
-                //  Try to find it by fixing 0
-                let json_file_path = file.path();
-                let image_file_path = fix_image_file_path_by_fix_0(json_file_path);
-                if !image_file_path.exists() {
-                    println!("Relevant image file not found: {:?}", file);
-                    panic = true;
+        let filtered_json_files = filter_excluded_files(all_json_files, &EXCLUDED_FILES);
+
+        for json_file in &filtered_json_files {
+            println!("Filtered file: {:?}", json_file.path());
+        }
+
+        for json_file in &filtered_json_files {
+            let sidecar = match get_sidecar_from_json(json_file) {
+                Ok(sidecar) => sidecar,
+                Err(e) => {
+                    report.record_failure(json_file.path(), e);
+                    continue;
+                },
+            };
+            if let Some(sidecar) = sidecar {
+                match media_index.find_media_for_json(json_file.path()) {
+                    Some(sidecar_match) => {
+                        for media_path in sidecar_match.versions() {
+                            sidecars_by_media.insert(media_path.clone(), sidecar.clone());
+                        }
+                        skipped.extend(edited_policy.skipped(&sidecar_match.media, &sidecar_match.edited).into_iter().cloned());
+                    },
+                    None => {
+                        report.orphans.record_sidecar(json_file.path());
+                        report.record_failure(json_file.path(), FixError::MissingPairedMedia {
+                            sidecar: json_file.path().to_path_buf(),
+                        });
+                    },
                 }
             }
         }
+
+    }
+
+    (sidecars_by_media, skipped)
+}
+
+// Picks the date of every media file before anything is copied.
+// With sidecars, as in Google Photos, the media files without one are reported as orphans.
+fn resolve_dates(
+    directories: &[&str],
+    sidecars_by_media: Option<&HashMap<PathBuf, TakeoutSidecar>>,
+    skipped: &HashSet<PathBuf>,
+    dates: &DateResolver,
+    timezones: &TimezoneResolver,
+    report: &mut RunReport,
+) -> HashMap<PathBuf, FileMetadata> {
+    let mut metadata_by_media = HashMap::new();
+
+    for path in directories {
+        for file in get_recursive_file_list(path) {
+            let file_extension = file.path().extension().and_then(|ext| ext.to_str()).unwrap_or("");
+            if !is_media_extension(file_extension) || skipped.contains(file.path()) {
+                continue;
+            }
+
+            let sidecar = sidecars_by_media.and_then(|sidecars| sidecars.get(file.path()));
+            let inputs = DateInputs { media_path: file.path(), file_path: file.path(), sidecar };
+            let resolved = resolve_metadata(file.path(), &inputs, dates, timezones, report);
+
+            if let (Some(_), None) = (sidecars_by_media, sidecar) {
+                report.orphans.record_media(file.path(), orphan_date(&resolved));
+            }
+            if let Some((_, metadata)) = resolved {
+                metadata_by_media.insert(file.path().to_path_buf(), metadata);
+            }
+        }
     }
 
+    metadata_by_media
+}
+
+fn orphan_date(resolved: &Option<(DateSource, FileMetadata)>) -> Option<(DateSource, String)> {
+    resolved.as_ref().map(|(source, metadata)| (*source, metadata.date_time.clone()))
+}
+
+// Shared by every source of a run
+struct RunContext<'a> {
+    copy: CopyOptions<'a>,
+    jobs: usize,
+    journal: Journal,
+    metadata_options: MetadataOptions,
+    timezones: TimezoneResolver,
+    edited_policy: EditedPolicy,
+    on_existing: ExistingDatePolicy,
+    // --date-priority, or the pipeline defaults with --no-sidecar-fallback
+    google_dates: DateResolver,
+    iphone_dates: DateResolver,
+}
 
-    if panic {
-        panic!("Relevant image file not found");
+impl RunContext<'_> {
+    // An item started by an earlier run is redone, the plan keeps its destination
+    fn resume_state(&self, source: &Path, hash: &str, action: &str) -> Result<ResumeState, FixError> {
+        match self.journal.previous(source) {
+            Some(previous) if previous.is_completed(hash, action) => Ok(ResumeState::Completed),
+            Some(previous) => {
+                journal::remove_stale_output(&previous.dest)?;
+                Ok(ResumeState::Redo)
+            },
+            None => Ok(ResumeState::New),
+        }
     }
+}
 
+// Destinations used by earlier runs stay reserved, --resume writes to them again
+fn reserved_destinations(history: &JournalHistory) -> HashSet<PathBuf> {
+    history.previous_destinations().map(|dest| dest.to_path_buf()).collect()
 }
 
-fn update_media_metadata_from_json(json_file: &DirEntry) {
-    if let Some(metadata_str) = get_metadata_from_json(json_file) {
-        let mut image_file_path = get_new_image_file_path_by_swap_position(&json_file.path());
+// Where the copies of one kind of source go, the journal stays at the top of the output directory
+fn source_output_dir(output_dir: &Path, output_per_source: bool, subdir: &str) -> PathBuf {
+    if output_per_source { output_dir.join(subdir) } else { output_dir.to_path_buf() }
+}
 
-        if fs::metadata(&image_file_path).is_err() {
-            image_file_path = fix_image_file_path_by_fix_0(&json_file.path());
+enum ResumeState {
+    Completed,
+    Redo,
+    New,
+}
 
-            if fs::metadata(&image_file_path).is_err() {
-                panic!("cannot find associated image file");
+fn patch_google_photos_image(
+    directories: Vec<&str>,
+    context: &RunContext,
+    reserved_destinations: &mut HashSet<PathBuf>,
+    report: &mut RunReport,
+) -> Result<(), FixError> {
+    let (sidecars_by_media, skipped) = collect_google_photos_sidecars(&directories, context.edited_policy, report);
+    let metadata_by_media = resolve_dates(&directories, Some(&sidecars_by_media), &skipped, &context.google_dates, &context.timezones, report);
+
+    let get_metadata = |file_path: &Path| metadata_by_media.get(file_path).cloned();
+    copy_files_to_output(directories, get_metadata, &skipped, GOOGLE_PHOTOS_OUTPUT, context, reserved_destinations, report)
+}
+
+// The media files of the source directories, with the source directory each one was found in
+fn get_media_files<'a>(directories: &[&'a str]) -> Vec<(&'a str, PathBuf)> {
+    let mut media_files = Vec::new();
+    for path in directories {
+        for file in get_recursive_file_list(path) {
+            let file_extension = file.path().extension().and_then(|ext| ext.to_str()).unwrap_or("");
+            if file.path().file_name().is_some() && is_media_extension(file_extension) {
+                media_files.push((*path, file.into_path()));
             }
         }
+    }
+    media_files
+}
 
-        if let Some(image_file_path_str) = image_file_path.to_str() {
-            add_metadata_wrapper(image_file_path_str, &metadata_str)
-                .expect("Failed to add metadata");
+// The metadata of the copy --dedup keeps, with the sidecar of a copy it leaves out.
+// The date of the kept copy wins, a duplicate only gives one when the kept copy has none.
+fn merge_duplicate_metadata(kept: Option<FileMetadata>, duplicate: Option<FileMetadata>) -> Option<FileMetadata> {
+    match (kept, duplicate) {
+        (Some(mut kept), Some(duplicate)) => {
+            kept.sidecar = merge_sidecars(kept.sidecar, duplicate.sidecar.as_ref());
+            Some(kept)
+        },
+        (kept, duplicate) => kept.or(duplicate),
+    }
+}
+
+fn merge_sidecars(kept: Option<TakeoutSidecar>, duplicate: Option<&TakeoutSidecar>) -> Option<TakeoutSidecar> {
+    match (kept, duplicate) {
+        (Some(mut kept), Some(duplicate)) => {
+            kept.merge(duplicate);
+            Some(kept)
+        },
+        (kept, duplicate) => kept.or_else(|| duplicate.cloned()),
+    }
+}
+
+// Copies every media file to output_dir first, then fixes the metadata of the copy only
+fn copy_files_to_output<F>(
+    directories: Vec<&str>,
+    get_metadata: F,
+    skipped: &HashSet<PathBuf>,
+    output_subdir: &str,
+    context: &RunContext,
+    reserved_destinations: &mut HashSet<PathBuf>,
+    report: &mut RunReport,
+) -> Result<(), FixError>
+where
+    F: Fn(&Path) -> Option<FileMetadata> + Sync,
+{
+    let output_dir = &source_output_dir(context.copy.output_dir, context.copy.output_per_source, output_subdir);
+    fs::create_dir_all(output_dir).map_err(|e| FixError::io(output_dir, e))?;
+
+    let mut media_files = Vec::new();
+    for (source_dir, file_path) in get_media_files(&directories) {
+        if skipped.contains(&file_path) {
+            println!("Skipping {:?}, left out by --edited", file_path);
         } else {
-            eprintln!("Invalid UTF-8 path");
-            panic!("Invalid UTF-8 path");
+            media_files.push((source_dir, file_path));
         }
     }
+    let CopyPlan { placements, mut destinations } = plan_media_files(
+        &media_files,
+        |file_path| get_metadata(file_path).map(|metadata| metadata.date_time),
+        output_dir,
+        &context.copy,
+        context.journal.history(),
+        reserved_destinations,
+    );
+
+    // With --dedup the copy kept gets the sidecars of the copies left out or linked to it
+    let mut merged_metadata: HashMap<&Path, Option<FileMetadata>> = HashMap::new();
+    for (index, (_, file_path)) in media_files.iter().enumerate() {
+        let kept = kept_copy(&placements, index);
+        if context.copy.dedup && kept != index {
+            let kept_path = media_files[kept].1.as_path();
+            let metadata = merged_metadata.remove(kept_path).unwrap_or_else(|| get_metadata(kept_path));
+            merged_metadata.insert(kept_path, merge_duplicate_metadata(metadata, get_metadata(file_path)));
+        }
+    }
+    let get_metadata = |file_path: &Path| match merged_metadata.get(file_path) {
+        Some(metadata) => metadata.clone(),
+        None => get_metadata(file_path),
+    };
+
+    let mut copy_jobs = Vec::new();
+    let mut link_jobs = Vec::new();
+
+    for (index, (((_, file_path), placement), dest_path)) in media_files.iter().zip(&placements).zip(&destinations).enumerate() {
+        match (placement.album, dest_path) {
+            (AlbumPlacement::Own, Some(dest_path)) => copy_jobs.push((index, file_path.clone(), dest_path.clone())),
+            (AlbumPlacement::LinkTo(first), Some(dest_path)) => link_jobs.push((index, first, file_path.clone(), placement.hash.clone(), dest_path.clone())),
+            (AlbumPlacement::SameAs(first), _) => {
+                println!("Skipping {:?}, same photo as {:?}", file_path, media_files[first].1);
+                report.duplicates.record(file_path, &media_files[kept_copy(&placements, first)].1, file_size(file_path));
+            },
+            _ => {},
+        }
+    }
+
+    let is_renamed = |index: usize| Some(placements[index].file_name.as_os_str()) != media_files[index].1.file_name();
+    let mut failed = HashSet::new();
+    worker_pool::run_ordered(
+        &copy_jobs,
+        context.jobs,
+        |(media_index, src_path, dest_path)| copy_and_fix_file(src_path, placements[*media_index].hash.as_ref(), dest_path, &get_metadata, context),
+        |index, result, lines| {
+            print_lines(lines);
+            let (media_index, src_path, _) = &copy_jobs[index];
+            match result {
+                Ok((final_dest_path, written)) => {
+                    if let MetadataWrite::NoWriter(kind) = written {
+                        report.record_unwritten(src_path, kind);
+                    }
+                    if is_renamed(*media_index) {
+                        report.extensions.record(src_path, &final_dest_path);
+                    }
+                    destinations[*media_index] = Some(final_dest_path);
+                },
+                Err(e) => {
+                    failed.insert(src_path.clone());
+                    report.record_failure(src_path, e);
+                },
+            }
+        },
+    );
+
+    // The links go to the fixed copies
+    for (index, first, src_path, hash, link_path) in link_jobs {
+        let (target_source, target) = (&media_files[first].1, destinations[first].as_ref().expect("the first copy is placed"));
+        if failed.contains(target_source) {
+            println!("Not linking {:?}, the copy of {:?} failed", src_path, target_source);
+            continue;
+        }
+        match link_to_copy(&src_path, hash.as_deref().unwrap_or_default(), target, &link_path, context) {
+            Ok(()) => {
+                report.duplicates.record(&src_path, target_source, file_size(&src_path));
+                if is_renamed(index) {
+                    report.extensions.record(&src_path, &link_path);
+                }
+            },
+            Err(e) => report.record_failure(&src_path, e),
+        }
+    }
+
+    Ok(())
 }
 
-fn patch_google_photos_image(directories: Vec<&str>) {
-    let excluded_files = vec![
-        "print-subscriptions.json",
-        "shared_album_comments.json",
-        "user-generated-memory-titles.json",
-    ];
+fn file_size(file_path: &Path) -> u64 {
+    fs::metadata(file_path).map(|metadata| metadata.len()).unwrap_or(0)
+}
 
-    for path in directories {
-        let all_files = get_recursive_file_list(path);
+// A copy of the photo in another album, for --album-copies hardlink or symlink
+fn link_to_copy(src_path: &Path, hash: &str, target: &Path, link_path: &Path, context: &RunContext) -> Result<(), FixError> {
+    let symbolic = context.copy.album_copies == AlbumCopies::Symlink;
+    let action = if symbolic { "symlink" } else { "hardlink" };
 
-        let all_json_files = get_all_json_files(all_files);
+    if let ResumeState::Completed = context.resume_state(src_path, hash, action)? {
+        println!("Already done in a previous run, skipping {:?}", src_path);
+        return Ok(());
+    }
 
-        let filtered_json_files = filter_excluded_files(all_json_files, &excluded_files);
+    context.journal.run(JournalRecord::new(src_path, hash, action, link_path, JournalStatus::Started), || {
+        // --on-collision applies to a file left at link_path by an earlier run, like to a copy
+        let Some(link_path) = file_utils::collision_destination(link_path, context.copy.on_collision)? else {
+            println!("Skipping {:?}, {:?} already exists", src_path, link_path);
+            return Ok(());
+        };
+        file_utils::link_file(target, &link_path, symbolic)?;
+        println!("Linked {:?} to {:?}", link_path, target);
+        Ok(())
+    })
+}
 
+// Returns where the copy ended up, --on-collision may have given it another name,
+// and whether its format could take the metadata
+fn copy_and_fix_file<F>(
+    src_path: &Path,
+    known_hash: Option<&String>,
+    dest_path: &Path,
+    get_metadata: &F,
+    context: &RunContext,
+) -> Result<(PathBuf, MetadataWrite), FixError>
+where
+    F: Fn(&Path) -> Option<FileMetadata>,
+{
+    // --dedup and by-album already hashed the files that share a size
+    let hash = match known_hash {
+        Some(hash) => hash.clone(),
+        None => journal::hash_file(src_path)?,
+    };
+    let metadata = get_metadata(src_path);
+    let action = if metadata.is_some() { "copy+metadata" } else { "copy" };
 
-        check_if_every_json_has_media_file_beforehand(&filtered_json_files);
+    if let ResumeState::Completed = context.resume_state(src_path, &hash, action)? {
+        log_line!("Already done in a previous run, skipping {:?}", src_path);
+        return Ok((dest_path.to_path_buf(), MetadataWrite::Done));
+    }
 
-        for json_file in &filtered_json_files {
-            println!("Filtered file: {:?}", json_file.path());
+    context.journal.run(JournalRecord::new(src_path, &hash, action, dest_path, JournalStatus::Started), || {
+        file_utils::create_parent_dir(dest_path)?;
+        let Some(final_dest_path) = file_utils::copy_file_preserving_metadata(src_path, dest_path, context.copy.on_collision)? else {
+            log_line!("Skipping {:?}, {:?} already exists", src_path, dest_path);
+            return Ok((dest_path.to_path_buf(), MetadataWrite::Done));
+        };
+        log_line!("Copied {:?} to {:?}", src_path, final_dest_path);
+
+        let written = match &metadata {
+            Some(metadata) => apply_metadata(path_to_str(&final_dest_path)?, metadata, context)?,
+            None => MetadataWrite::Done,
+        };
+        Ok((final_dest_path, written))
+    })
+}
+
+// A format without a writer gets no sidecar fields either, the copy is kept as it is
+fn apply_metadata(image_file_path: &str, metadata: &FileMetadata, context: &RunContext) -> Result<MetadataWrite, FixError> {
+    let written = add_metadata_wrapper(image_file_path, &metadata.date_time, context.on_existing)?;
+
+    let metadata_options = &context.metadata_options;
+    match &metadata.sidecar {
+        Some(sidecar) if written == MetadataWrite::Done && metadata_options.writes_sidecar_fields() => {
+            add_sidecar_metadata(image_file_path, sidecar, metadata_options)?
+        },
+        _ => {},
+    }
+    Ok(written)
+}
+
+// The buffered output of one file, printed once every file before it is done
+fn print_lines(lines: Vec<String>) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+fn is_media_extension(file_extension: &str) -> bool {
+    file_extension != "json" && file_extension != "html" && file_extension != "xml" && file_extension != "zip"
+}
+
+// What the sidecars of the zip parts say about their media entries
+struct TakeoutZipMetadata {
+    sidecars_by_media: HashMap<TakeoutZipEntry, TakeoutSidecar>,
+    // sidecars whose media could not be found in any part
+    missing_media: Vec<TakeoutZipEntry>,
+    // sidecars that could not be read
+    invalid: Vec<(TakeoutZipEntry, FixError)>,
+    // media entries --edited leaves out
+    skipped: HashSet<TakeoutZipEntry>,
+}
+
+// Reads every sidecar of the zip parts and returns it keyed by its media entry,
+// edited copies included
+fn collect_takeout_zip_sidecars(zip_set: &mut TakeoutZipSet, edited_policy: EditedPolicy) -> Result<TakeoutZipMetadata, FixError> {
+    let json_entries = zip_set.json_entries(&EXCLUDED_FILES);
+
+    // Only sidecars carrying a photoTakenTime need a media file, like the directory pipeline
+    let mut sidecars_by_json = HashMap::new();
+    let mut invalid = Vec::new();
+    for json_entry in &json_entries {
+        let json_path = zip_set.entry_path(json_entry);
+        let reader = zip_set.open_entry(json_entry)?;
+        match get_sidecar_from_json_reader(&json_path, reader) {
+            Ok(Some(sidecar)) => {
+                sidecars_by_json.insert(json_entry.clone(), sidecar);
+            },
+            Ok(None) => {},
+            Err(e) => invalid.push((json_entry.clone(), e)),
         }
+    }
 
-        for json_file in &filtered_json_files {
-            update_media_metadata_from_json(json_file)
+    let sidecar_entries: Vec<_> = json_entries
+        .into_iter()
+        .filter(|json_entry| sidecars_by_json.contains_key(json_entry))
+        .collect();
+    let pairing = zip_set.pair_sidecars(&sidecar_entries);
+
+    let mut sidecars_by_media = HashMap::new();
+    let mut skipped = HashSet::new();
+    for (json_entry, media_entry) in pairing.pairs {
+        println!("Paired {} with {}", json_entry.name, media_entry.name);
+        let sidecar = sidecars_by_json.remove(&json_entry).expect("pair_sidecars only gets the sidecars read, each once");
+        let edited = pairing.edited.get(&media_entry).map(Vec::as_slice).unwrap_or_default();
+
+        for edited_entry in edited {
+            println!("Paired {} with edited copy {}", json_entry.name, edited_entry.name);
+            sidecars_by_media.insert(edited_entry.clone(), sidecar.clone());
         }
+        skipped.extend(edited_policy.skipped(&media_entry, edited).into_iter().cloned());
 
+        sidecars_by_media.insert(media_entry, sidecar);
     }
+
+    Ok(TakeoutZipMetadata { sidecars_by_media, missing_media: pairing.missing_media, invalid, skipped })
 }
 
-fn copy_files_to_output(directories: Vec<&str>, output_dir: &Path) -> std::io::Result<()> {
-    fs::create_dir_all(output_dir)?;
+fn takeout_zip_media_entries(zip_set: &TakeoutZipSet) -> Vec<TakeoutZipEntry> {
+    zip_set
+        .entries()
+        .iter()
+        .filter(|entry| is_media_extension(entry.extension().unwrap_or("")))
+        .cloned()
+        .collect()
+}
 
-    for path in directories {
-        let all_files = get_recursive_file_list(path);
+fn patch_google_photos_takeout_zip(
+    zip_paths: Vec<&str>,
+    context: &RunContext,
+    reserved_destinations: &mut HashSet<PathBuf>,
+    report: &mut RunReport,
+) -> Result<(), FixError> {
+    let mut zip_set = TakeoutZipSet::open(&zip_paths)?;
 
-        for file in all_files {
-            if let Some(file_name) = file.path().file_name() {
-                let dest_path = output_dir.join(file_name);
-                let file_extension = file.path().extension().and_then(|ext| ext.to_str()).unwrap_or("");
-
-                if file_extension != "json" && file_extension != "html" && file_extension != "xml" && file_extension != "zip" {
-                    if let Err(e) = file_utils::copy_file_preserving_metadata(file.path(), &dest_path) {
-                        eprintln!("Failed to copy file: {:?} to {:?} due to {:?}", file.path(), dest_path, e);
-                        panic!("panic!");
-                    } else {
-                        println!("Copied {:?} to {:?}", file.path(), dest_path);
-                    }
+    let TakeoutZipMetadata { mut sidecars_by_media, missing_media, invalid, skipped } = collect_takeout_zip_sidecars(&mut zip_set, context.edited_policy)?;
+
+    for (json_entry, e) in invalid {
+        report.record_failure(zip_set.entry_path(&json_entry), e);
+    }
+
+    for json_entry in &missing_media {
+        let sidecar = zip_set.entry_path(json_entry);
+        report.orphans.record_sidecar(&sidecar);
+        report.record_failure(&sidecar, FixError::MissingPairedMedia { sidecar: sidecar.clone() });
+    }
+
+    let output_dir = &source_output_dir(context.copy.output_dir, context.copy.output_per_source, TAKEOUT_ZIP_OUTPUT);
+    fs::create_dir_all(output_dir).map_err(|e| FixError::io(output_dir, e))?;
+
+    let mut media_entries = Vec::new();
+    for media_entry in takeout_zip_media_entries(&zip_set) {
+        if skipped.contains(&media_entry) {
+            println!("Skipping {:?}, left out by --edited", zip_set.entry_path(&media_entry));
+        } else {
+            media_entries.push(media_entry);
+        }
+    }
+    let from_names = context.google_dates.without_file_sources();
+    let CopyPlan { placements, mut destinations } = plan_takeout_zip_entries(
+        &mut zip_set,
+        &media_entries,
+        |media_entry| takeout_zip_entry_date(media_entry, &sidecars_by_media, &from_names, &context.timezones),
+        output_dir,
+        &context.copy,
+        context.journal.history(),
+        reserved_destinations,
+    )?;
+
+    // With --dedup the entry kept gets the sidecars of the entries left out or linked to it
+    for index in 0..media_entries.len() {
+        let kept = kept_copy(&placements, index);
+        if context.copy.dedup && kept != index {
+            if let Some(merged) = merge_sidecars(sidecars_by_media.remove(&media_entries[kept]), sidecars_by_media.get(&media_entries[index])) {
+                sidecars_by_media.insert(media_entries[kept].clone(), merged);
+            }
+        }
+    }
+
+    // Extraction reads the zip parts sequentially, only the metadata fixes run on the workers
+    let mut fix_jobs = Vec::new();
+    let mut link_jobs = Vec::new();
+    let mut failed = HashSet::new();
+    for (index, (media_entry, placement)) in media_entries.iter().zip(&placements).enumerate() {
+        let source = zip_set.entry_path(media_entry);
+        // The crc32 of the zip directory, or the hash the placement read, no need to read the entry twice
+        let hash = placement.hash.clone().unwrap_or_default();
+        let sidecar = sidecars_by_media.get(media_entry);
+        let action = if sidecar.is_some() || context.google_dates.works_without_sidecar() { "extract+metadata" } else { "extract" };
+
+        match placement.album {
+            AlbumPlacement::Own => {},
+            AlbumPlacement::LinkTo(first) => {
+                let link_path = destinations[index].clone().expect("a link is placed");
+                let renamed = placement.file_name != media_entry.file_name();
+                link_jobs.push((first, source, hash, link_path, zip_set.size(media_entry).unwrap_or(0), renamed));
+                continue;
+            },
+            AlbumPlacement::SameAs(first) => {
+                println!("Skipping {:?}, same photo as {:?}", source, zip_set.entry_path(&media_entries[first]));
+                let kept = zip_set.entry_path(&media_entries[kept_copy(&placements, first)]);
+                report.duplicates.record(&source, kept, zip_set.size(media_entry).unwrap_or(0));
+                continue;
+            },
+        }
+
+        // The plan already has the destination of an earlier run, only its stale output is removed here
+        match context.resume_state(&source, &hash, action) {
+            Ok(ResumeState::Completed) => {
+                println!("Already done in a previous run, skipping {:?}", source);
+                continue;
+            },
+            Ok(ResumeState::Redo | ResumeState::New) => {},
+            Err(e) => {
+                failed.insert(source.clone());
+                report.record_failure(&source, e);
+                continue;
+            },
+        }
+        let dest_path = destinations[index].clone().expect("a copy is placed");
+
+        // --on-collision applies to a file left in the output by an earlier run, like copy_file_preserving_metadata
+        let started = JournalRecord::new(&source, &hash, action, &dest_path, JournalStatus::Started);
+        let extracted = context
+            .journal
+            .record(&started)
+            .and_then(|_| file_utils::create_parent_dir(&dest_path))
+            .and_then(|_| file_utils::collision_destination(&dest_path, context.copy.on_collision))
+            .and_then(|final_dest_path| match final_dest_path {
+                Some(final_dest_path) => zip_set.extract_entry(media_entry, &final_dest_path).map(|_| Some(final_dest_path)),
+                None => Ok(None),
+            });
+
+        let dest_path = match &extracted {
+            Ok(Some(final_dest_path)) => final_dest_path.clone(),
+            Ok(None) => {
+                println!("Skipping {:?}, {:?} already exists", source, dest_path);
+                if let Err(e) = context.journal.finish(started, &extracted) {
+                    report.record_failure(&source, e);
+                }
+                continue;
+            },
+            Err(_) => {
+                if let Err(e) = context.journal.finish(started, &extracted).and(extracted) {
+                    failed.insert(source.clone());
+                    report.record_failure(&source, e);
                 }
+                continue;
+            },
+        };
+        destinations[index] = Some(dest_path.clone());
+        println!("Extracted {} from {:?} to {:?}", media_entry.name, zip_set.part_path(media_entry.part), dest_path);
+        if placement.file_name != media_entry.file_name() {
+            report.extensions.record(&source, &dest_path);
+        }
+
+        // The date is only picked once extracted, some sources read the file
+        let inputs = DateInputs { media_path: Path::new(&media_entry.name), file_path: &dest_path, sidecar };
+        let resolved = resolve_metadata(&source, &inputs, &context.google_dates, &context.timezones, report);
+        if sidecar.is_none() {
+            report.orphans.record_media(&source, orphan_date(&resolved));
+        }
+
+        // With a date, the item is only finished once the metadata is fixed as well
+        let Some((_, metadata)) = resolved else {
+            if let Err(e) = context.journal.finish(started, &extracted) {
+                failed.insert(source.clone());
+                report.record_failure(&source, e);
             }
+            continue;
+        };
+
+        fix_jobs.push((source, hash, dest_path, metadata));
+    }
+
+    worker_pool::run_ordered(
+        &fix_jobs,
+        context.jobs,
+        |(source, hash, dest_path, metadata)| {
+            let started = JournalRecord::new(source, hash, "extract+metadata", dest_path, JournalStatus::Started);
+            context.journal.run(started, || {
+                path_to_str(dest_path).and_then(|dest_path_str| apply_metadata(dest_path_str, metadata, context))
+            })
+        },
+        |index, result, lines| {
+            print_lines(lines);
+            match result {
+                Ok(MetadataWrite::NoWriter(kind)) => report.record_unwritten(&fix_jobs[index].0, kind),
+                Ok(MetadataWrite::Done) => {},
+                Err(e) => {
+                    failed.insert(fix_jobs[index].0.clone());
+                    report.record_failure(&fix_jobs[index].0, e);
+                },
+            }
+        },
+    );
+
+    for (first, source, hash, link_path, size, renamed) in link_jobs {
+        let target_source = zip_set.entry_path(&media_entries[first]);
+        let Some(target) = destinations[first].as_ref().filter(|_| !failed.contains(&target_source)) else {
+            println!("Not linking {:?}, the extraction of {:?} failed", source, target_source);
+            continue;
+        };
+        match link_to_copy(&source, &hash, target, &link_path, context) {
+            Ok(()) => {
+                report.duplicates.record(&source, &target_source, size);
+                if renamed {
+                    report.extensions.record(&source, &link_path);
+                }
+            },
+            Err(e) => report.record_failure(&source, e),
         }
     }
+
     Ok(())
 }
 
-fn process_iphone_photos(directories: Vec<&str>) {
+// Entries are only extracted after they are placed, so by-date goes by the date known without reading the file
+fn takeout_zip_entry_date(
+    media_entry: &TakeoutZipEntry,
+    sidecars_by_media: &HashMap<TakeoutZipEntry, TakeoutSidecar>,
+    from_names: &DateResolver,
+    timezones: &TimezoneResolver,
+) -> Option<String> {
+    let media_path = Path::new(&media_entry.name);
+    let inputs = DateInputs { media_path, file_path: media_path, sidecar: sidecars_by_media.get(media_entry) };
+    from_names.resolve(&inputs, timezones).map(|resolved| format_metadata_value(&resolved.date_time))
+}
+
+// --dry-run: the destination of the plan, and why it is not the name the file would get otherwise
+fn describe_destination(plan: &CopyPlan, output_dir: &Path, index: usize, source: &Path, context: &PlanContext) -> String {
+    let dest_path = plan.destinations[index].as_ref().expect("a copy or link is placed");
+    if context.history.previous(source).is_some() {
+        format!("{:?} (started by an earlier run, resumed)", dest_path)
+    } else if *dest_path != plan.wanted(output_dir, index) {
+        format!("{:?} (name already taken)", dest_path)
+    } else if dest_path.exists() {
+        format!("{:?} (already exists, {})", dest_path, context.copy.on_collision.plan())
+    } else {
+        format!("{:?}", dest_path)
+    }
+}
+
+// planned_dates are the dates the plan_* functions show, for --layout by-date
+fn plan_copy_files_to_output(
+    directories: &[&str],
+    skipped: &HashSet<PathBuf>,
+    planned_dates: &HashMap<PathBuf, String>,
+    output_subdir: &str,
+    context: &PlanContext,
+    planned_destinations: &mut HashSet<PathBuf>,
+) {
+    let output_dir = &source_output_dir(context.copy.output_dir, context.copy.output_per_source, output_subdir);
+    let mut media_files = Vec::new();
+    for (source_dir, file_path) in get_media_files(directories) {
+        if skipped.contains(&file_path) {
+            println!("[dry-run] skip {:?}, left out by --edited", file_path);
+        } else {
+            media_files.push((source_dir, file_path));
+        }
+    }
+    let plan = plan_media_files(
+        &media_files,
+        |file_path| planned_dates.get(file_path).cloned(),
+        output_dir,
+        &context.copy,
+        &context.history,
+        planned_destinations,
+    );
+
+    for (index, ((_, file_path), placement)) in media_files.iter().zip(&plan.placements).enumerate() {
+        match placement.album {
+            AlbumPlacement::Own => {
+                println!("[dry-run] copy {:?} -> {}", file_path, describe_destination(&plan, output_dir, index, file_path, context))
+            },
+            AlbumPlacement::LinkTo(first) => println!(
+                "[dry-run] {} {:?} -> {}, same photo as {:?}",
+                if context.copy.album_copies == AlbumCopies::Symlink { "symlink" } else { "hardlink" },
+                file_path,
+                describe_destination(&plan, output_dir, index, file_path, context),
+                media_files[first].1
+            ),
+            AlbumPlacement::SameAs(first) => println!("[dry-run] skip {:?}, same photo as {:?}", file_path, media_files[first].1),
+        }
+    }
+}
+
+// Shared by every source of a dry run, like RunContext
+struct PlanContext<'a> {
+    copy: CopyOptions<'a>,
+    metadata_options: MetadataOptions,
+    timezones: TimezoneResolver,
+    edited_policy: EditedPolicy,
+    on_existing: ExistingDatePolicy,
+    // The journal of the earlier runs, as the run reads it
+    history: JournalHistory,
+}
+
+// The date a dry run shows, nothing is recorded in the report
+fn plan_date(inputs: &DateInputs, dates: &DateResolver, timezones: &TimezoneResolver) -> Option<(DateSource, FileMetadata)> {
+    let resolved = dates.resolve(inputs, timezones)?;
+    Some((resolved.source, FileMetadata { date_time: format_metadata_value(&resolved.date_time), sidecar: inputs.sidecar.cloned() }))
+}
+
+// Returns the files --edited leaves out, like collect_google_photos_sidecars, and the planned dates
+fn plan_google_photos_image(directories: &[&str], dates: &DateResolver, context: &PlanContext) -> (HashSet<PathBuf>, HashMap<PathBuf, String>) {
+    let mut skipped = HashSet::new();
+    let mut planned_dates = HashMap::new();
+
     for path in directories {
         let all_files = get_recursive_file_list(path);
+        let media_index = MediaIndex::new(all_files.iter().map(|file| file.path()));
+        let media_files: Vec<PathBuf> = all_files
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .filter(|file_path| is_media_extension(file_path.extension().and_then(|ext| ext.to_str()).unwrap_or("")))
+            .collect();
+        // The sidecar of each media file and where it was read from
+        let mut sidecars_by_media = HashMap::new();
+        let all_json_files = get_all_json_files(all_files);
+        let filtered_json_files = filter_excluded_files(all_json_files, &EXCLUDED_FILES);
 
-        for file in all_files {
-            if let Ok(metadata) = fs::metadata(file.path()) {
-                if let Ok(modified_time) = metadata.modified() {
-                    let datetime: DateTime<Utc> = DateTime::from(modified_time);
-                    // %H is 24-hr, and is verified here. Do not change to %I, 12-hr.
-                    let formatted_str = datetime.format("%Y:%m:%d %H:%M:%S%.3f%:z").to_string();
-                    add_metadata_wrapper(file.path().to_str().unwrap(), &formatted_str);
-                }
+        for json_file in &filtered_json_files {
+            let sidecar = match get_sidecar_from_json(json_file) {
+                Ok(Some(sidecar)) => sidecar,
+                Ok(None) => {
+                    println!("[dry-run] {:?}: skip, no photoTakenTime", json_file.path());
+                    continue;
+                },
+                Err(e) => {
+                    println!("[dry-run] {:?}: fail, {}", json_file.path(), e);
+                    continue;
+                },
+            };
+
+            match media_index.find_media_for_json(json_file.path()) {
+                Some(sidecar_match) => {
+                    for media_path in sidecar_match.versions() {
+                        sidecars_by_media.insert(media_path.clone(), (json_file.path().to_path_buf(), sidecar.clone()));
+                    }
+                    skipped.extend(context.edited_policy.skipped(&sidecar_match.media, &sidecar_match.edited).into_iter().cloned());
+                },
+                None => println!("[dry-run] {:?}: fail, relevant image file not found", json_file.path()),
             }
         }
+
+        for media_file in media_files.iter().filter(|media_file| !skipped.contains(*media_file)) {
+            let paired = sidecars_by_media.get(media_file);
+            let inputs = DateInputs { media_path: media_file, file_path: media_file, sidecar: paired.map(|(_, sidecar)| sidecar) };
+            let planned = plan_date(&inputs, dates, &context.timezones);
+
+            match (paired, &planned) {
+                (Some((json_path, _)), Some((source, metadata))) => {
+                    let plan = plan_metadata_wrapper(&media_file.to_string_lossy(), &metadata.date_time, context.on_existing);
+                    println!("[dry-run] {:?} -> {:?}: {} (from {})", json_path, media_file, plan, source);
+                    if let Some(sidecar_plan) = plan_sidecar(metadata, &context.metadata_options) {
+                        println!("[dry-run] {:?}: also set {}", media_file, sidecar_plan);
+                    }
+                },
+                (Some((json_path, _)), None) => println!("[dry-run] {:?} -> {:?}: no date from any source, left alone", json_path, media_file),
+                (None, Some((source, metadata))) => {
+                    let plan = plan_metadata_wrapper(&media_file.to_string_lossy(), &metadata.date_time, context.on_existing);
+                    println!("[dry-run] {:?}: no sidecar, date from {}: {}", media_file, source, plan);
+                },
+                (None, None) => println!("[dry-run] {:?}: no sidecar, left alone", media_file),
+            }
+            if let Some((_, metadata)) = planned {
+                planned_dates.insert(media_file.clone(), metadata.date_time);
+            }
+        }
+    }
+
+    (skipped, planned_dates)
+}
+
+fn plan_iphone_photos(directories: &[&str], dates: &DateResolver, context: &PlanContext) -> HashMap<PathBuf, String> {
+    let mut planned_dates = HashMap::new();
+
+    for path in directories {
+        for file in get_recursive_file_list(path) {
+            let file_extension = file.path().extension().and_then(|ext| ext.to_str()).unwrap_or("");
+            if !is_media_extension(file_extension) {
+                continue;
+            }
+
+            let inputs = DateInputs { media_path: file.path(), file_path: file.path(), sidecar: None };
+            if let Some((source, metadata)) = plan_date(&inputs, dates, &context.timezones) {
+                let plan = plan_metadata_wrapper(&file.path().to_string_lossy(), &metadata.date_time, context.on_existing);
+                println!("[dry-run] {:?}: {} (from {})", file.path(), plan, source);
+                planned_dates.insert(file.path().to_path_buf(), metadata.date_time);
+            }
+        }
+    }
+
+    planned_dates
+}
+
+// Zip entries are not extracted during a dry run, so the media type is only known at extraction time
+fn plan_google_photos_takeout_zip(
+    zip_paths: &[&str],
+    dates: &DateResolver,
+    context: &PlanContext,
+    planned_destinations: &mut HashSet<PathBuf>,
+) -> Result<(), FixError> {
+    let mut zip_set = TakeoutZipSet::open(zip_paths)?;
+
+    let TakeoutZipMetadata { sidecars_by_media, missing_media, invalid, skipped } = collect_takeout_zip_sidecars(&mut zip_set, context.edited_policy)?;
+
+    for (json_entry, e) in &invalid {
+        println!("[dry-run] {:?}: fail, {}", zip_set.entry_path(json_entry), e);
+    }
+
+    // Entries are not extracted, so the sources reading the file only come into play at extraction time
+    let from_names = dates.without_file_sources();
+    let reads_file = from_names.priority.len() < dates.priority.len();
+
+    for json_entry in &missing_media {
+        println!("[dry-run] {} in {:?}: fail, relevant image file not found", json_entry.name, zip_set.part_path(json_entry.part));
+    }
+
+    let mut media_entries = Vec::new();
+    for media_entry in takeout_zip_media_entries(&zip_set) {
+        if skipped.contains(&media_entry) {
+            println!("[dry-run] skip {}, left out by --edited", media_entry.name);
+        } else {
+            media_entries.push(media_entry);
+        }
+    }
+    let output_dir = &source_output_dir(context.copy.output_dir, context.copy.output_per_source, TAKEOUT_ZIP_OUTPUT);
+    let plan = plan_takeout_zip_entries(
+        &mut zip_set,
+        &media_entries,
+        |media_entry| takeout_zip_entry_date(media_entry, &sidecars_by_media, &from_names, &context.timezones),
+        output_dir,
+        &context.copy,
+        &context.history,
+        planned_destinations,
+    )?;
+
+    for (index, (media_entry, placement)) in media_entries.iter().zip(&plan.placements).enumerate() {
+        let source = zip_set.entry_path(media_entry);
+        match placement.album {
+            AlbumPlacement::Own => {},
+            AlbumPlacement::LinkTo(first) => {
+                let link = if context.copy.album_copies == AlbumCopies::Symlink { "symlink" } else { "hardlink" };
+                let dest = describe_destination(&plan, output_dir, index, &source, context);
+                println!("[dry-run] {} {} -> {}, same photo as {}", link, media_entry.name, dest, media_entries[first].name);
+                continue;
+            },
+            AlbumPlacement::SameAs(first) => {
+                println!("[dry-run] skip {}, same photo as {}", media_entry.name, media_entries[first].name);
+                continue;
+            },
+        }
+
+        let dest = describe_destination(&plan, output_dir, index, &source, context);
+        let media_path = Path::new(&media_entry.name);
+        let sidecar = sidecars_by_media.get(media_entry);
+        let inputs = DateInputs { media_path, file_path: media_path, sidecar };
+        let without_sidecar = if sidecar.is_some() { "" } else { "no sidecar, " };
+
+        match plan_date(&inputs, &from_names, &context.timezones) {
+            Some((source, metadata)) => {
+                println!(
+                    "[dry-run] extract {} -> {}: {}set DateTimeOriginal/CreateDate to {} (from {}) {}",
+                    media_entry.name, dest, without_sidecar, metadata.date_time, source, context.on_existing.plan()
+                );
+                if let Some(sidecar_plan) = plan_sidecar(&metadata, &context.metadata_options) {
+                    println!("[dry-run] {}: also set {}", dest, sidecar_plan);
+                }
+            },
+            None if reads_file => {
+                println!("[dry-run] extract {} -> {}: {}date read from the extracted file if it has one", media_entry.name, dest, without_sidecar)
+            },
+            None => println!("[dry-run] extract {} -> {}: {}copied as is", media_entry.name, dest, without_sidecar),
+        }
     }
+
+    Ok(())
+}
+
+fn plan_sidecar(metadata: &FileMetadata, metadata_options: &MetadataOptions) -> Option<String> {
+    plan_sidecar_metadata(metadata.sidecar.as_ref()?, metadata_options)
+}
+
+fn process_iphone_photos(
+    directories: Vec<&str>,
+    context: &RunContext,
+    reserved_destinations: &mut HashSet<PathBuf>,
+    report: &mut RunReport,
+) -> Result<(), FixError> {
+    // The dates are read from the source, the copy only gets the tags
+    let metadata_by_media = resolve_dates(&directories, None, &HashSet::new(), &context.iphone_dates, &context.timezones, report);
+    let get_metadata = |file_path: &Path| metadata_by_media.get(file_path).cloned();
+    copy_files_to_output(directories, get_metadata, &HashSet::new(), IPHONE_PHOTOS_OUTPUT, context, reserved_destinations, report)
+}
+
+fn timezone_resolver(matches: &ArgMatches) -> Result<TimezoneResolver, String> {
+    let default_zone = matches.get_one::<String>("timezone").map(|name| timezone::parse_timezone(name)).transpose()?;
+    let mappings = match matches.get_one::<String>("timezone-map") {
+        Some(mapping_file) => timezone::read_timezone_mappings(Path::new(mapping_file)).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+
+    Ok(TimezoneResolver { default_zone, mappings, from_gps: matches.get_flag("timezone-from-gps") })
+}
+
+// --date-priority replaces the priority of both pipelines. Without it Google Photos uses the sidecar,
+// then the --no-sidecar-fallback sources, and iPhone photos the file name, then the modification time.
+fn date_resolvers(matches: &ArgMatches) -> Result<(DateResolver, DateResolver), String> {
+    let file_names = match matches.get_one::<String>("filename-patterns") {
+        Some(pattern_file) => filename_date::read_file_name_patterns(Path::new(pattern_file)).map_err(|e| e.to_string())?,
+        None => FileNameDateExtractor::default(),
+    };
+    let disagreement_threshold = matches.get_one::<String>("flag-date-disagreement").map(|text| date_sources::parse_duration(text)).transpose()?;
+    let resolver = |priority| DateResolver { priority, file_names: file_names.clone(), disagreement_threshold };
+
+    if let Some(priority) = matches.get_one::<String>("date-priority") {
+        let priority = date_sources::parse_date_sources(priority)?;
+        return Ok((resolver(priority.clone()), resolver(priority)));
+    }
+
+    let fallback = date_sources::parse_date_sources(matches.get_one::<String>("no-sidecar-fallback").unwrap())?;
+    let mut google_priority = DEFAULT_GOOGLE_PHOTOS_PRIORITY.to_vec();
+    google_priority.extend(fallback.into_iter().filter(|source| !DEFAULT_GOOGLE_PHOTOS_PRIORITY.contains(source)));
+
+    Ok((resolver(google_priority), resolver(DEFAULT_IPHONE_PHOTOS_PRIORITY.to_vec())))
 }
 
 fn main() {
@@ -203,27 +1084,328 @@ fn main() {
                 .action(clap::ArgAction::Append)
                 .help("Source directories for iPhone Photos"),
         )
+        .arg(
+            Arg::new("src-takeout-zip")
+                .long("src-takeout-zip")
+                .action(clap::ArgAction::Append)
+                .help("Google Takeout zip parts, read without extracting them"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .value_name("DIR")
+                .default_value("output")
+                .help("Directory the fixed copies are written to"),
+        )
+        .arg(
+            Arg::new("output-per-source")
+                .long("output-per-source")
+                .action(clap::ArgAction::SetTrue)
+                .help("Give each kind of source its own subdirectory of the output directory: google-photos, iphone-photos and takeout-zip"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print the planned changes without writing anything to disk"),
+        )
+        .arg(
+            Arg::new("jobs")
+                .long("jobs")
+                .short('j')
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .help("Number of files processed in parallel, 0 uses one per CPU"),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .action(clap::ArgAction::SetTrue)
+                .help("Continue an interrupted run, skipping the files it already completed"),
+        )
+        .arg(
+            Arg::new("restore-gps")
+                .long("restore-gps")
+                .action(clap::ArgAction::SetTrue)
+                .help("Write the sidecar location as GPS tags to files that have no GPS yet"),
+        )
+        .arg(
+            Arg::new("write-description")
+                .long("write-description")
+                .action(clap::ArgAction::SetTrue)
+                .help("Write the sidecar description as ImageDescription and XMP dc:description"),
+        )
+        .arg(
+            Arg::new("write-people")
+                .long("write-people")
+                .action(clap::ArgAction::SetTrue)
+                .help("Write the people tagged in Google Photos as XMP PersonInImage"),
+        )
+        .arg(
+            Arg::new("write-favorites")
+                .long("write-favorites")
+                .action(clap::ArgAction::SetTrue)
+                .help("Give favorited photos an XMP Rating of 5"),
+        )
+        .arg(
+            Arg::new("edited")
+                .long("edited")
+                .value_parser(["both", "original-only", "edited-only"])
+                .default_value("both")
+                .help("Which versions of photos edited in Google Photos are copied, both get the sidecar metadata"),
+        )
+        .arg(
+            Arg::new("layout")
+                .long("layout")
+                .value_parser(["flat", "mirror", "by-date", "by-album"])
+                .default_value("flat")
+                .help("How the output directory is organized: flat, mirror of the source folders, YYYY/MM folders, or one folder per album"),
+        )
+        .arg(
+            Arg::new("album-copies")
+                .long("album-copies")
+                .value_parser(["hardlink", "symlink", "single"])
+                .default_value("hardlink")
+                .help("With --layout by-album, what a photo gets in the albums after the first one it is in"),
+        )
+        .arg(
+            Arg::new("on-collision")
+                .long("on-collision")
+                .value_parser(["rename", "skip", "overwrite", "skip-if-identical", "fail"])
+                .default_value("rename")
+                .help("What happens to a file already in the output directory, e.g. from an earlier run, when a copy gets its name"),
+        )
+        .arg(
+            Arg::new("dedup")
+                .long("dedup")
+                .action(clap::ArgAction::SetTrue)
+                .help("Copy byte-identical media files once, with the sidecar metadata of every copy"),
+        )
+        .arg(
+            Arg::new("fix-extensions")
+                .long("fix-extensions")
+                .action(clap::ArgAction::SetTrue)
+                .help("Give the copies the extension of their content, e.g. IMG_0328.JPG holding a PNG becomes IMG_0328.png"),
+        )
+        .arg(
+            Arg::new("collision-naming")
+                .long("collision-naming")
+                .value_parser(["counter", "hash", "folder"])
+                .default_value("counter")
+                .help("How a file is renamed when its name is taken: IMG-1.JPG, IMG-<content hash>.JPG or <source folder>_IMG.JPG"),
+        )
+        .arg(
+            Arg::new("on-existing")
+                .long("on-existing")
+                .value_name("POLICY")
+                .default_value("keep")
+                .help("What happens to a date already in the file: keep, overwrite, overwrite-if-implausible or overwrite-if-differs-by=<duration>"),
+        )
+        .arg(
+            Arg::new("no-sidecar-fallback")
+                .long("no-sidecar-fallback")
+                .value_name("SOURCES")
+                .default_value("none")
+                .help("Where media without a sidecar get their date from, tried in order after photo-taken-time: exif,filename,folder, or none to leave them alone"),
+        )
+        .arg(
+            Arg::new("date-priority")
+                .long("date-priority")
+                .value_name("SOURCES")
+                .conflicts_with("no-sidecar-fallback")
+                .help("Where every media file gets its date from, tried in order: exif,quicktime,photo-taken-time,creation-time,filename,folder,mtime"),
+        )
+        .arg(
+            Arg::new("flag-date-disagreement")
+                .long("flag-date-disagreement")
+                .value_name("DURATION")
+                .help("List the files whose date sources differ by more than DURATION, e.g. 12h or 2d"),
+        )
+        .arg(
+            Arg::new("filename-patterns")
+                .long("filename-patterns")
+                .value_name("FILE")
+                .help("File of extra regexes for dates in file names, with (?P<year>...), (?P<month>...) and (?P<day>...) groups"),
+        )
+        .arg(
+            Arg::new("orphan-report")
+                .long("orphan-report")
+                .value_name("FILE")
+                .help("Write the sidecars without media and the media without sidecar to FILE as JSON"),
+        )
+        .arg(
+            Arg::new("date-report")
+                .long("date-report")
+                .value_name("FILE")
+                .help("Write the date picked for every media file, its source and the disagreeing sources to FILE as JSON"),
+        )
+        .arg(
+            Arg::new("timezone")
+                .long("timezone")
+                .value_name("Area/City")
+                .help("Time zone the Google Photos dates are written in, e.g. Asia/Tokyo [default: UTC]"),
+        )
+        .arg(
+            Arg::new("timezone-map")
+                .long("timezone-map")
+                .value_name("FILE")
+                .help("File mapping directories or date ranges to time zones, for photos taken while travelling"),
+        )
+        .arg(
+            Arg::new("timezone-from-gps")
+                .long("timezone-from-gps")
+                .action(clap::ArgAction::SetTrue)
+                .help("Use the time zone of the sidecar location when there is one"),
+        )
         .get_matches();
 
-    if let Some(directories) = matches.get_many::<String>("src-google-photos") {
-        let directories: Vec<&str> = directories.map(|s| s.as_str()).collect();
-        patch_google_photos_image(directories.clone());
+    let dry_run = matches.get_flag("dry-run");
+    let jobs = match *matches.get_one::<usize>("jobs").unwrap() {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        jobs => jobs,
+    };
+    let output_dir = Path::new(matches.get_one::<String>("output").unwrap());
+    let output_per_source = matches.get_flag("output-per-source");
+    let metadata_options = MetadataOptions {
+        restore_gps: matches.get_flag("restore-gps"),
+        write_description: matches.get_flag("write-description"),
+        write_people: matches.get_flag("write-people"),
+        write_favorites: matches.get_flag("write-favorites"),
+    };
+
+    let edited_policy = EditedPolicy::parse(matches.get_one::<String>("edited").unwrap()).expect("checked by clap");
+
+    let layout = OutputLayout::parse(matches.get_one::<String>("layout").unwrap()).expect("checked by clap");
+    let album_copies = AlbumCopies::parse(matches.get_one::<String>("album-copies").unwrap()).expect("checked by clap");
+    let dedup = matches.get_flag("dedup");
+    let fix_extensions = matches.get_flag("fix-extensions");
+    let collision_naming = CollisionNaming::parse(matches.get_one::<String>("collision-naming").unwrap()).expect("checked by clap");
+    let on_collision = CollisionPolicy::parse(matches.get_one::<String>("on-collision").unwrap()).expect("checked by clap");
+
+    let on_existing = match ExistingDatePolicy::parse(matches.get_one::<String>("on-existing").unwrap()) {
+        Ok(on_existing) => on_existing,
+        Err(e) => {
+            eprintln!("Invalid --on-existing setting: {}", e);
+            std::process::exit(1);
+        },
+    };
 
-        let output_dir = Path::new("output");
+    let (google_dates, iphone_dates) = match date_resolvers(&matches) {
+        Ok(resolvers) => resolvers,
+        Err(e) => {
+            eprintln!("Invalid date source settings: {}", e);
+            std::process::exit(1);
+        },
+    };
 
-        if let Err(e) = copy_files_to_output(directories, output_dir) {
-            eprintln!("Failed to copy files to output directory: {:?}", e);
+    let timezones = match timezone_resolver(&matches) {
+        Ok(timezones) => timezones,
+        Err(e) => {
+            eprintln!("Invalid time zone settings: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    let copy = CopyOptions { output_dir, layout, album_copies, dedup, collision_naming, on_collision, output_per_source, fix_extensions };
+    let resume = matches.get_flag("resume");
+
+    if dry_run {
+        // The plan goes by the journal of the earlier runs, like the run itself
+        let history = match JournalHistory::read(output_dir, resume) {
+            Ok(history) => history,
+            Err(e) => {
+                eprintln!("Failed to read the progress journal: {}", e);
+                std::process::exit(1);
+            },
+        };
+        let mut planned_destinations = reserved_destinations(&history);
+        let context = PlanContext { copy, metadata_options, timezones, edited_policy, on_existing, history };
+
+        if let Some(directories) = matches.get_many::<String>("src-google-photos") {
+            let directories: Vec<&str> = directories.map(|s| s.as_str()).collect();
+            let (skipped, planned_dates) = plan_google_photos_image(&directories, &google_dates, &context);
+            plan_copy_files_to_output(&directories, &skipped, &planned_dates, GOOGLE_PHOTOS_OUTPUT, &context, &mut planned_destinations);
+        }
+
+        if let Some(directories) = matches.get_many::<String>("src-iphone-photos") {
+            let directories: Vec<&str> = directories.map(|s| s.as_str()).collect();
+            let planned_dates = plan_iphone_photos(&directories, &iphone_dates, &context);
+            plan_copy_files_to_output(&directories, &HashSet::new(), &planned_dates, IPHONE_PHOTOS_OUTPUT, &context, &mut planned_destinations);
+        }
+
+        if let Some(zip_paths) = matches.get_many::<String>("src-takeout-zip") {
+            let zip_paths: Vec<&str> = zip_paths.map(|s| s.as_str()).collect();
+            if let Err(e) = plan_google_photos_takeout_zip(&zip_paths, &google_dates, &context, &mut planned_destinations) {
+                eprintln!("Failed to read Takeout zip files: {}", e);
+            }
+        }
+
+        return;
+    }
+
+    let mut report = RunReport::new();
+
+    let journal = match Journal::open(output_dir, resume) {
+        Ok(journal) => journal,
+        Err(e) => {
+            eprintln!("Failed to open the progress journal: {}", e);
+            std::process::exit(1);
+        },
+    };
+    let context = RunContext {
+        copy,
+        jobs,
+        journal,
+        metadata_options,
+        timezones,
+        edited_policy,
+        on_existing,
+        google_dates,
+        iphone_dates,
+    };
+    // Shared by the sources like in a dry run, so the names match its plan
+    let mut reserved_destinations = reserved_destinations(context.journal.history());
+
+    if let Some(directories) = matches.get_many::<String>("src-google-photos") {
+        let directories: Vec<&str> = directories.map(|s| s.as_str()).collect();
+        let source = directories.join(", ");
+        if let Err(e) = patch_google_photos_image(directories, &context, &mut reserved_destinations, &mut report) {
+            report.record_failure(source, e);
         }
     }
 
     if let Some(directories) = matches.get_many::<String>("src-iphone-photos") {
         let directories: Vec<&str> = directories.map(|s| s.as_str()).collect();
-        process_iphone_photos(directories.clone());
+        let source = directories.join(", ");
+        if let Err(e) = process_iphone_photos(directories, &context, &mut reserved_destinations, &mut report) {
+            report.record_failure(source, e);
+        }
+    }
 
-        let output_dir = Path::new("output");
+    if let Some(zip_paths) = matches.get_many::<String>("src-takeout-zip") {
+        let zip_paths: Vec<&str> = zip_paths.map(|s| s.as_str()).collect();
+        let source = zip_paths.join(", ");
 
-        if let Err(e) = copy_files_to_output(directories, output_dir) {
-            eprintln!("Failed to copy files to output directory: {:?}", e);
+        if let Err(e) = patch_google_photos_takeout_zip(zip_paths, &context, &mut reserved_destinations, &mut report) {
+            report.record_failure(source, e);
         }
     }
+
+    if let Some(report_path) = matches.get_one::<String>("orphan-report") {
+        if let Err(e) = report.orphans.write_json(Path::new(report_path)) {
+            report.record_failure(report_path, e);
+        }
+    }
+
+    if let Some(report_path) = matches.get_one::<String>("date-report") {
+        if let Err(e) = report.dates.write_json(Path::new(report_path)) {
+            report.record_failure(report_path, e);
+        }
+    }
+
+    report.print_summary();
+
+    if report.has_failures() {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use crate::error::FixError;
+
+// Media type detection from the first bytes of a file, replacing `file --mime-type`.
+//
+// Takeout keeps the original file names, so the extension cannot be trusted: a .JPG may be
+// a PNG screenshot, a .MP4 may be a QuickTime movie.
+
+// Enough for the ISO BMFF ftyp box and for the IFD0 of TIFF based RAW files
+const SNIFF_LEN: usize = 64 * 1024;
+
+const TIFF_TAG_MAKE: u16 = 0x010F;
+const TIFF_TAG_DNG_VERSION: u16 = 0xC612;
+// The RAW image data of a NEF sits in a SubIFD, a plain TIFF from a Nikon scanner has none
+const TIFF_TAG_SUB_IFDS: u16 = 0x014A;
+// Sony's SR2 private IFD, found in every ARW
+const TIFF_TAG_SR2_PRIVATE: u16 = 0xC634;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaKind {
+    Jpeg,
+    Png,
+    Gif,
+    Heic,
+    Avif,
+    Tiff,
+    WebP,
+    Bmp,
+    Ico,
+    Dng,
+    Cr2,
+    Cr3,
+    Nef,
+    Arw,
+    Orf,
+    Rw2,
+    Raf,
+    Mp4,
+    Mov,
+    M4v,
+    ThreeGp,
+    Mkv,
+    Avi,
+    Mpeg,
+    Wmv,
+    Unknown,
+}
+
+impl MediaKind {
+    // The usual extension for the format, also what exiftool expects to see
+    pub fn extension(self) -> &'static str {
+        match self {
+            MediaKind::Jpeg => "jpg",
+            MediaKind::Png => "png",
+            MediaKind::Gif => "gif",
+            MediaKind::Heic => "heic",
+            MediaKind::Avif => "avif",
+            MediaKind::Tiff => "tiff",
+            MediaKind::WebP => "webp",
+            MediaKind::Bmp => "bmp",
+            MediaKind::Ico => "ico",
+            MediaKind::Dng => "dng",
+            MediaKind::Cr2 => "cr2",
+            MediaKind::Cr3 => "cr3",
+            MediaKind::Nef => "nef",
+            MediaKind::Arw => "arw",
+            MediaKind::Orf => "orf",
+            MediaKind::Rw2 => "rw2",
+            MediaKind::Raf => "raf",
+            MediaKind::Mp4 => "mp4",
+            MediaKind::Mov => "mov",
+            MediaKind::M4v => "m4v",
+            MediaKind::ThreeGp => "3gp",
+            MediaKind::Mkv => "mkv",
+            MediaKind::Avi => "avi",
+            MediaKind::Mpeg => "mpg",
+            MediaKind::Wmv => "wmv",
+            MediaKind::Unknown => "unknown",
+        }
+    }
+
+    pub fn is_video(self) -> bool {
+        matches!(
+            self,
+            MediaKind::Mp4 | MediaKind::Mov | MediaKind::M4v | MediaKind::ThreeGp | MediaKind::Mkv | MediaKind::Avi | MediaKind::Mpeg | MediaKind::Wmv
+        )
+    }
+
+    pub fn is_raw(self) -> bool {
+        matches!(
+            self,
+            MediaKind::Dng | MediaKind::Cr2 | MediaKind::Cr3 | MediaKind::Nef | MediaKind::Arw | MediaKind::Orf | MediaKind::Rw2 | MediaKind::Raf
+        )
+    }
+
+    // The format an extension stands for, None for extensions that are no media format, e.g. .AAE or .XMP
+    pub fn from_extension(extension: &str) -> Option<MediaKind> {
+        match extension.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" | "jpe" | "jfif" => Some(MediaKind::Jpeg),
+            "png" => Some(MediaKind::Png),
+            "gif" => Some(MediaKind::Gif),
+            "heic" | "heif" | "hif" => Some(MediaKind::Heic),
+            "avif" => Some(MediaKind::Avif),
+            "tif" | "tiff" => Some(MediaKind::Tiff),
+            "webp" => Some(MediaKind::WebP),
+            "bmp" | "dib" => Some(MediaKind::Bmp),
+            "ico" => Some(MediaKind::Ico),
+            "dng" => Some(MediaKind::Dng),
+            "cr2" => Some(MediaKind::Cr2),
+            "cr3" => Some(MediaKind::Cr3),
+            "nef" => Some(MediaKind::Nef),
+            "arw" => Some(MediaKind::Arw),
+            "orf" => Some(MediaKind::Orf),
+            "rw2" => Some(MediaKind::Rw2),
+            "raf" => Some(MediaKind::Raf),
+            "mp4" => Some(MediaKind::Mp4),
+            "mov" | "qt" => Some(MediaKind::Mov),
+            "m4v" => Some(MediaKind::M4v),
+            "3gp" | "3g2" => Some(MediaKind::ThreeGp),
+            "mkv" | "webm" => Some(MediaKind::Mkv),
+            "avi" => Some(MediaKind::Avi),
+            "mpg" | "mpeg" | "mts" | "m2ts" | "ts" | "vob" => Some(MediaKind::Mpeg),
+            "wmv" | "asf" => Some(MediaKind::Wmv),
+            _ => None,
+        }
+    }
+
+    // Whether a file of this kind may keep the extension.
+    // Unknown content and unknown extensions fit anything. RAW files are told apart by their maker tags,
+    // which a TIFF based RAW may lack, and MP4 and M4V are the same container.
+    pub fn fits_extension(self, extension: &str) -> bool {
+        match MediaKind::from_extension(extension) {
+            None => true,
+            Some(_) if self == MediaKind::Unknown => true,
+            Some(kind) if kind.is_raw() => self.is_raw() || self == MediaKind::Tiff,
+            Some(MediaKind::Mp4 | MediaKind::M4v) => matches!(self, MediaKind::Mp4 | MediaKind::M4v),
+            Some(kind) => kind == self,
+        }
+    }
+
+    pub fn detect(header: &[u8]) -> MediaKind {
+        let starts_with = |magic: &[u8]| header.starts_with(magic);
+        let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+        if starts_with(&[0xFF, 0xD8, 0xFF]) {
+            MediaKind::Jpeg
+        } else if starts_with(b"\x89PNG\r\n\x1a\n") {
+            MediaKind::Png
+        } else if starts_with(b"GIF87a") || starts_with(b"GIF89a") {
+            MediaKind::Gif
+        } else if starts_with(b"RIFF") && at(8, b"WEBP") {
+            MediaKind::WebP
+        } else if starts_with(b"RIFF") && at(8, b"AVI ") {
+            MediaKind::Avi
+        } else if starts_with(b"FUJIFILMCCD-RAW") {
+            MediaKind::Raf
+        } else if starts_with(b"IIRO") || starts_with(b"IIRS") || starts_with(b"MMOR") {
+            MediaKind::Orf
+        } else if starts_with(b"IIU\0") {
+            MediaKind::Rw2
+        } else if starts_with(b"II*\0") || starts_with(b"MM\0*") {
+            detect_tiff_based(header)
+        } else if at(4, b"ftyp") && header.len() >= 12 {
+            detect_iso_bmff(header)
+        } else if [&b"moov"[..], b"mdat", b"wide", b"free", b"skip", b"pnot"].iter().any(|kind| at(4, kind)) {
+            // QuickTime movies written before ftyp existed
+            MediaKind::Mov
+        } else if starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+            // Matroska, WebM included
+            MediaKind::Mkv
+        } else if starts_with(&[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11]) {
+            // ASF header object
+            MediaKind::Wmv
+        } else if starts_with(&[0x00, 0x00, 0x01, 0xBA]) || starts_with(&[0x00, 0x00, 0x01, 0xB3]) || is_mpeg_transport_stream(header) {
+            // Program stream pack or sequence header, or a transport stream
+            MediaKind::Mpeg
+        } else if starts_with(b"BM") && header.len() >= 14 {
+            MediaKind::Bmp
+        } else if starts_with(&[0x00, 0x00, 0x01, 0x00]) {
+            MediaKind::Ico
+        } else {
+            MediaKind::Unknown
+        }
+    }
+
+    pub fn detect_file(file_path: &Path) -> Result<MediaKind, FixError> {
+        let file = File::open(file_path).map_err(|e| FixError::io(file_path, e))?;
+        MediaKind::detect_reader(file).map_err(|e| FixError::io(file_path, e))
+    }
+
+    // Also used on Takeout zip entries, only the first bytes are read
+    pub fn detect_reader<R: Read>(reader: R) -> io::Result<MediaKind> {
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        reader.take(SNIFF_LEN as u64).read_to_end(&mut header)?;
+        Ok(MediaKind::detect(&header))
+    }
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+// Sync byte every 188 bytes, or every 192 bytes for camcorder .MTS/.M2TS files
+fn is_mpeg_transport_stream(header: &[u8]) -> bool {
+    let synced = |start: usize, packet: usize| (0..3).all(|i| header.get(start + i * packet) == Some(&0x47));
+    synced(0, 188) || synced(4, 192)
+}
+
+// ftyp: major brand at 8, minor version at 12, compatible brands up to the end of the box
+fn detect_iso_bmff(header: &[u8]) -> MediaKind {
+    let box_size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let brands_end = box_size.min(header.len());
+    let major_brand = &header[8..12];
+    let compatible_brands: Vec<&[u8]> = header.get(16..brands_end).unwrap_or(&[]).chunks_exact(4).collect();
+    let has_brand = |brand: &[u8]| major_brand == brand || compatible_brands.contains(&brand);
+
+    match major_brand {
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => MediaKind::Heic,
+        b"avif" | b"avis" => MediaKind::Avif,
+        b"crx " => MediaKind::Cr3,
+        b"qt  " => MediaKind::Mov,
+        b"M4V " | b"M4VH" | b"M4VP" => MediaKind::M4v,
+        brand if brand.starts_with(b"3g") => MediaKind::ThreeGp,
+        // Generic still image brands, the compatible brands tell which codec
+        b"mif1" | b"msf1" if has_brand(b"avif") => MediaKind::Avif,
+        b"mif1" | b"msf1" => MediaKind::Heic,
+        _ => MediaKind::Mp4,
+    }
+}
+
+// DNG and most camera RAW formats are TIFF files, IFD0 tells them apart
+fn detect_tiff_based(header: &[u8]) -> MediaKind {
+    let little_endian = header[0] == b'I';
+    let u16_at = |pos: usize| {
+        header.get(pos..pos + 2).map(|b| if little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    };
+    let u32_at = |pos: usize| {
+        header.get(pos..pos + 4).map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+        })
+    };
+
+    // Canon puts its own signature right after the TIFF header
+    if header.get(8..10) == Some(b"CR") {
+        return MediaKind::Cr2;
+    }
+
+    let Some(ifd0) = u32_at(4).map(|offset| offset as usize) else {
+        return MediaKind::Tiff;
+    };
+    let entry_count = u16_at(ifd0).unwrap_or(0) as usize;
+    let mut make = String::new();
+    let (mut has_sub_ifds, mut has_sr2_private) = (false, false);
+
+    for index in 0..entry_count {
+        let entry = ifd0 + 2 + index * 12;
+        let (Some(tag), Some(count)) = (u16_at(entry), u32_at(entry + 4)) else {
+            break;
+        };
+        match tag {
+            TIFF_TAG_DNG_VERSION => return MediaKind::Dng,
+            TIFF_TAG_MAKE => {
+                let count = count as usize;
+                let value = if count <= 4 {
+                    header.get(entry + 8..entry + 8 + count)
+                } else {
+                    u32_at(entry + 8).and_then(|offset| header.get(offset as usize..offset as usize + count))
+                };
+                make = String::from_utf8_lossy(value.unwrap_or(&[])).to_uppercase();
+            },
+            TIFF_TAG_SUB_IFDS => has_sub_ifds = true,
+            TIFF_TAG_SR2_PRIVATE => has_sr2_private = true,
+            _ => {},
+        }
+    }
+
+    // The Make alone also matches TIFFs saved by the camera or the maker's software
+    if make.starts_with("NIKON") && has_sub_ifds {
+        MediaKind::Nef
+    } else if make.starts_with("SONY") && has_sr2_private {
+        MediaKind::Arw
+    } else {
+        MediaKind::Tiff
+    }
+}
@@ -1,139 +1,397 @@
+use std::env;
+use std::fmt;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus, Stdio};
+use std::process::{self, Command, ExitStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::str;
-use crate::file_utils::{get_extension, restore_file_modification_time};
-use crate::update_media_metadata_from_json;
-
-pub fn add_metadata_wrapper(image_file_path: &str, value: &str) -> std::io::Result<()>  {
-    let actual_extension = get_media_file_type(image_file_path);
-
-    match actual_extension.as_str() {
-        "jpg" | "jpeg" | "png" | "gif" | "heic" | "tiff" | "tif" | "webp" | "mp4" | "mov" => {
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use filetime::{FileTime, set_file_mtime};
+use crate::error::{path_to_str, FixError};
+use crate::existing_date::ExistingDatePolicy;
+use crate::exif::{self, ExifDates};
+use crate::file_utils::restore_file_modification_time;
+use crate::log_line;
+use crate::media_kind::MediaKind;
+use crate::png_metadata;
+use crate::quicktime;
+
+// Keeps the names of the temporary files of several workers apart
+static TEMPORARY_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Formats without a native writer, handed to exiftool
+const EXIFTOOL_MEDIA_KINDS: [MediaKind; 13] = [
+    MediaKind::Gif,
+    MediaKind::Heic,
+    MediaKind::Avif,
+    MediaKind::Tiff,
+    MediaKind::WebP,
+    MediaKind::Dng,
+    MediaKind::Cr2,
+    MediaKind::Cr3,
+    MediaKind::Nef,
+    MediaKind::Arw,
+    MediaKind::Orf,
+    MediaKind::Rw2,
+    MediaKind::Raf,
+];
+
+// Formats that are recognized, but nothing can write tags to: exiftool only reads them
+const READ_ONLY_MEDIA_KINDS: [MediaKind; 6] = [MediaKind::Bmp, MediaKind::Ico, MediaKind::Mkv, MediaKind::Avi, MediaKind::Mpeg, MediaKind::Wmv];
+
+// What add_metadata_wrapper could do with the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataWrite {
+    // The date is written, or a date already there is kept
+    Done,
+    // The format has no writer, only the modification time follows the date
+    NoWriter(MediaKind),
+}
 
-            let original_extension = get_original_extension(image_file_path);
-            let original_metadata = fs::metadata(image_file_path)?;
+// A date already in the file is kept unless on_existing says otherwise
+pub fn add_metadata_wrapper(image_file_path: &str, value: &str, on_existing: ExistingDatePolicy) -> Result<MetadataWrite, FixError> {
+    let media_kind = MediaKind::detect_file(Path::new(image_file_path))?;
 
-            // change png to jpg, for example
-            let new_image_file_path = rename_file(image_file_path, &actual_extension);
-            add_metadata_with_exiftool(&new_image_file_path, value);
+    match media_kind {
+        // Written natively, without exiftool
+        MediaKind::Jpeg => add_metadata_to_jpeg(image_file_path, value, on_existing).map(|_| MetadataWrite::Done),
+        MediaKind::Png => add_metadata_to_png(image_file_path, value, on_existing).map(|_| MetadataWrite::Done),
+        MediaKind::Mp4 | MediaKind::Mov | MediaKind::M4v | MediaKind::ThreeGp => {
+            add_metadata_to_quicktime(image_file_path, value, on_existing).map(|_| MetadataWrite::Done)
+        },
+        kind if EXIFTOOL_MEDIA_KINDS.contains(&kind) => {
 
+            let original_metadata = fs::metadata(image_file_path).map_err(|e| FixError::io(image_file_path, e))?;
 
-            // Rename back to original extension
-            // change jpg to png, for example
-            rename_file(&new_image_file_path, &original_extension);
+            with_detected_extension(image_file_path, kind, |file_path| add_metadata_with_exiftool(file_path, value, on_existing))?;
 
             // Restore back to original modification time
-            restore_file_modification_time(image_file_path, original_metadata);
+            restore_file_modification_time(image_file_path, original_metadata)?;
 
             // Finally, sync the modification time with the creation tag.
             // Google Photos surprisingly ignores the DateTimeOriginal and CreateDate tags of GIFs,
             // but respects the modification time
-            sync_metadata_modification_from_DateTimeOriginal_and_CreateDate(image_file_path);
+            sync_metadata_modification_from_DateTimeOriginal_and_CreateDate(image_file_path)?;
+
+            Ok(MetadataWrite::Done)
+        },
+        kind if READ_ONLY_MEDIA_KINDS.contains(&kind) => {
+            let path = Path::new(image_file_path);
+            let date_time = parse_metadata_value(path, value)?;
+            set_file_mtime(path, FileTime::from_unix_time(date_time.timestamp(), 0)).map_err(|e| FixError::io(path, e))?;
+            log_line!("no metadata written to {}, {} files have no writer", image_file_path, kind);
+            Ok(MetadataWrite::NoWriter(kind))
+        },
+        MediaKind::Unknown if is_apple_aae_file(image_file_path) => {
+            log_line!("do not modify apple *.AAE file: {}", image_file_path);
+            Ok(MetadataWrite::Done)
+        },
+        _ => Err(FixError::UnsupportedMediaType {
+            path: PathBuf::from(image_file_path),
+            media_type: media_kind.to_string(),
+        }),
+    }
+}
+
+// Read-only counterpart of add_metadata_wrapper, used by --dry-run.
+// Describes what add_metadata_wrapper would do to the file without touching it.
+pub fn plan_metadata_wrapper(image_file_path: &str, value: &str, on_existing: ExistingDatePolicy) -> String {
+    let path = Path::new(image_file_path);
+    let media_kind = match MediaKind::detect_file(path) {
+        Ok(media_kind) => media_kind,
+        Err(e) => return format!("fail, {}", e),
+    };
+
+    let existing = match media_kind {
+        MediaKind::Jpeg => exif::read_jpeg_date_tags(path).map(|dates| existing_exif_date(&dates)),
+        MediaKind::Png => png_metadata::read_png_date_tags(path).map(|dates| existing_exif_date(&dates)),
+        MediaKind::Mp4 | MediaKind::Mov | MediaKind::M4v | MediaKind::ThreeGp => {
+            quicktime::read_quicktime_dates(path).map(|dates| dates.movie_creation_time.map(existing_quicktime_date))
+        },
+        kind if EXIFTOOL_MEDIA_KINDS.contains(&kind) => read_date_tags_with_exiftool(image_file_path).map(|dates| existing_exif_date(&dates)),
+        kind if READ_ONLY_MEDIA_KINDS.contains(&kind) => {
+            return format!("copy only, no metadata writer for {} files, modification time set to {}", kind, value);
+        },
+        MediaKind::Unknown if is_apple_aae_file(image_file_path) => {
+            return "skip, do not modify apple *.AAE file".to_string();
+        },
+        _ => return format!("fail, unsupported or misidentified file format: {}", media_kind),
+    };
+    let date_time = match parse_metadata_value(path, value) {
+        Ok(date_time) => date_time,
+        Err(e) => return format!("fail, {}", e),
+    };
+
+    match existing {
+        Ok(Some(existing)) if on_existing.overwrites(existing.date_time.as_ref(), &date_time) => {
+            format!("replace DateTimeOriginal/CreateDate {} with {} (detected type: {})", existing.text, value, media_kind)
+        },
+        Ok(Some(_)) => format!("skip, DateTimeOriginal/CreateDate already present (detected type: {})", media_kind),
+        Ok(None) => format!("set DateTimeOriginal/CreateDate to {} (detected type: {})", value, media_kind),
+        Err(e) => format!("fail, {}", e),
+    }
+}
 
-            Ok(())
+// A date already in the file, as it is written there
+struct ExistingDate {
+    text: String,
+    // None when it cannot be parsed
+    date_time: Option<DateTime<FixedOffset>>,
+}
+
+fn existing_exif_date(dates: &ExifDates) -> Option<ExistingDate> {
+    let text = dates.date_time_original.as_ref().or(dates.create_date.as_ref())?;
+    Some(ExistingDate { text: text.clone(), date_time: exif_dates_to_date_time(dates) })
+}
+
+fn existing_quicktime_date(creation_time: DateTime<Utc>) -> ExistingDate {
+    ExistingDate { text: format_metadata_value(&creation_time), date_time: Some(creation_time.fixed_offset()) }
+}
+
+// Whether the date already in the file, if any, gives way to date_time. A replaced date is logged.
+fn writes_date(image_file_path: &str, existing: Option<ExistingDate>, date_time: &DateTime<FixedOffset>, on_existing: ExistingDatePolicy) -> bool {
+    match existing {
+        None => true,
+        Some(existing) if on_existing.overwrites(existing.date_time.as_ref(), date_time) => {
+            log_line!("Replacing date {} of {} with {}", existing.text, image_file_path, format_metadata_value(date_time));
+            true
         },
-        "unknown" => {
-
-            let extension = get_extension(image_file_path);
-
-            match extension {
-                Some(ext) => {
-                    if ext == "AAE" || ext == "aae" { // Check for both "AAE" and "aae"
-                        println!("Extension: {}", ext);
-                        println!("do not modify apple *.AAE file");
-                        Ok(())
-                    } else {
-                        panic!("Unsupported or misidentified file format: {}, image_file_path: {}", actual_extension, image_file_path);
-                    }
-                },
-                None => {
-                    println!("No extension found");
-                    panic!("Unsupported or misidentified file format: {}, image_file_path: {}", actual_extension, image_file_path);
-                },
+        Some(_) => false,
+    }
+}
+
+fn add_metadata_to_jpeg(image_file_path: &str, value: &str, on_existing: ExistingDatePolicy) -> Result<(), FixError> {
+    add_exif_dates_natively(image_file_path, value, on_existing, exif::read_jpeg_date_tags, |path, date_time| {
+        exif::write_jpeg_date_tags(path, &exif_date_tags(date_time))
+    })
+}
+
+fn add_metadata_to_png(image_file_path: &str, value: &str, on_existing: ExistingDatePolicy) -> Result<(), FixError> {
+    add_exif_dates_natively(image_file_path, value, on_existing, png_metadata::read_png_date_tags, |path, date_time| {
+        // The PNG spec suggests RFC 1123 for "Creation Time", e.g. "Mon, 22 Jun 2015 19:06:40 +0900"
+        png_metadata::write_png_date_tags(path, &exif_date_tags(date_time), &date_time.to_rfc2822())
+    })
+}
+
+// Shared by every container with a native EXIF writer
+fn add_exif_dates_natively(
+    image_file_path: &str,
+    value: &str,
+    on_existing: ExistingDatePolicy,
+    read_dates: fn(&Path) -> Result<ExifDates, FixError>,
+    write_dates: fn(&Path, &DateTime<FixedOffset>) -> Result<(), FixError>,
+) -> Result<(), FixError> {
+    let path = Path::new(image_file_path);
+    let original_metadata = fs::metadata(path).map_err(|e| FixError::io(path, e))?;
+
+    let mut dates = read_dates(path)?;
+    let date_time = parse_metadata_value(path, value)?;
+
+    if writes_date(image_file_path, existing_exif_date(&dates), &date_time, on_existing) {
+        write_dates(path, &date_time)?;
+        log_line!("Date/Time tags added successfully");
+
+        let [date_time_original, create_date, offset_time_original] = exif_date_tags(&date_time).map(|(_, text)| Some(text));
+        dates = ExifDates { date_time_original, create_date, offset_time_original };
+    }
+
+    restore_file_modification_time(image_file_path, original_metadata)?;
+
+    // Same as the exiftool path: the modification time follows DateTimeOriginal, or CreateDate
+    sync_modification_time_from_exif_dates(path, &dates)
+}
+
+// QuickTime stores UTC, so the offset of the value only matters for the Apple creation date key
+fn add_metadata_to_quicktime(image_file_path: &str, value: &str, on_existing: ExistingDatePolicy) -> Result<(), FixError> {
+    let path = Path::new(image_file_path);
+
+    let dates = quicktime::read_quicktime_dates(path)?;
+    let date_time = parse_metadata_value(path, value)?;
+
+    // Same rule as for EXIF dates
+    let creation_time = match dates.movie_creation_time {
+        Some(creation_time) if !writes_date(image_file_path, Some(existing_quicktime_date(creation_time)), &date_time, on_existing) => creation_time,
+        _ => {
+            // e.g. "2015-06-22T19:06:40+0000", the format iPhones write.
+            // Only files that already have the Apple key get it, nothing is added to other MP4s.
+            let apple_creation_date = dates.apple_creation_date.as_ref().map(|_| date_time.format("%Y-%m-%dT%H:%M:%S%z").to_string());
+            let update = quicktime::write_quicktime_dates(path, date_time.with_timezone(&Utc), apple_creation_date.as_deref())?;
+            log_line!("QuickTime creation time set in {} header(s)", update.headers_updated);
+            if apple_creation_date.is_some() && !update.apple_creation_date_updated {
+                log_line!("{} left unchanged in {}", quicktime::APPLE_CREATION_DATE_KEY, image_file_path);
             }
+            date_time.with_timezone(&Utc)
+        },
+    };
+
+    // The modification time follows the creation time, as for photos
+    set_file_mtime(path, FileTime::from_unix_time(creation_time.timestamp(), 0)).map_err(|e| FixError::io(path, e))
+}
+
+// The value handed to add_metadata_wrapper, e.g. "2015:06:22 19:06:40.000+00:00"
+pub fn format_metadata_value<Tz: TimeZone>(date_time: &DateTime<Tz>) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    // %H is 24-hr, verified. Do not change to %I 12-hr
+    date_time.format("%Y:%m:%d %H:%M:%S%.3f%:z").to_string()
+}
+
+pub fn parse_metadata_value(file_path: &Path, value: &str) -> Result<DateTime<FixedOffset>, FixError> {
+    DateTime::parse_from_str(value, "%Y:%m:%d %H:%M:%S%.f%:z").map_err(|e| FixError::InvalidMetadata {
+        path: file_path.to_path_buf(),
+        message: format!("cannot parse date {:?}: {}", value, e),
+    })
+}
+
+fn exif_date_tags(date_time: &DateTime<FixedOffset>) -> [(u16, String); 3] {
+    // %H is 24-hr. Do not change to %I 12-hr
+    let exif_date_time = date_time.format("%Y:%m:%d %H:%M:%S").to_string();
+    [
+        (exif::TAG_DATE_TIME_ORIGINAL, exif_date_time.clone()),
+        (exif::TAG_CREATE_DATE, exif_date_time),
+        (exif::TAG_OFFSET_TIME_ORIGINAL, date_time.format("%:z").to_string()),
+    ]
+}
+
+// Without OffsetTimeOriginal the EXIF date is taken as local time, as exiftool does
+fn exif_dates_to_date_time(dates: &ExifDates) -> Option<DateTime<FixedOffset>> {
+    let exif_date_time = dates.date_time_original.as_ref().or(dates.create_date.as_ref())?;
+    let naive_date_time = NaiveDateTime::parse_from_str(exif_date_time, "%Y:%m:%d %H:%M:%S").ok()?;
+
+    let offset = dates
+        .offset_time_original
+        .as_ref()
+        .and_then(|offset| DateTime::parse_from_str(&format!("2000:01:01 00:00:00{}", offset), "%Y:%m:%d %H:%M:%S%:z").ok())
+        .map(|parsed| *parsed.offset());
+
+    match offset {
+        Some(offset) => offset.from_local_datetime(&naive_date_time).single(),
+        None => Local.from_local_datetime(&naive_date_time).earliest().map(|date_time| date_time.fixed_offset()),
+    }
+}
+
+fn exif_dates_to_file_time(dates: &ExifDates) -> Option<FileTime> {
+    exif_dates_to_date_time(dates).map(|date_time| FileTime::from_unix_time(date_time.timestamp(), 0))
+}
+
+// DateTimeOriginal, or CreateDate, already in the file.
+// None when there is none, or it cannot be parsed, e.g. "0000:00:00 00:00:00".
+pub fn read_exif_date(file_path: &Path) -> Result<Option<DateTime<FixedOffset>>, FixError> {
+    let dates = match MediaKind::detect_file(file_path)? {
+        MediaKind::Jpeg => exif::read_jpeg_date_tags(file_path)?,
+        MediaKind::Png => png_metadata::read_png_date_tags(file_path)?,
+        kind if EXIFTOOL_MEDIA_KINDS.contains(&kind) => read_date_tags_with_exiftool(path_to_str(file_path)?)?,
+        _ => return Ok(None),
+    };
+
+    Ok(exif_dates_to_date_time(&dates))
+}
+
+// The Apple creation date key keeps the local offset, the header creation time is UTC
+pub fn read_quicktime_date(file_path: &Path) -> Result<Option<DateTime<FixedOffset>>, FixError> {
+    match MediaKind::detect_file(file_path)? {
+        MediaKind::Mp4 | MediaKind::Mov | MediaKind::M4v | MediaKind::ThreeGp => {
+            let dates = quicktime::read_quicktime_dates(file_path)?;
+            let apple_creation_date = dates
+                .apple_creation_date
+                .and_then(|creation_date| DateTime::parse_from_str(&creation_date, "%Y-%m-%dT%H:%M:%S%z").ok());
+            Ok(apple_creation_date.or(dates.movie_creation_time.map(|creation_time| creation_time.fixed_offset())))
         },
-        _ => panic!("Unsupported or misidentified file format: {}, image_file_path: {}", actual_extension, image_file_path),
+        _ => Ok(None),
     }
 }
 
-fn get_media_file_type(file_path: &str) -> String {
-    let output = Command::new("file")
-        .arg("--mime-type")
-        .arg("-b")
+fn read_date_tags_with_exiftool(file_path: &str) -> Result<ExifDates, FixError> {
+    let output = Command::new("exiftool")
+        .arg("-DateTimeOriginal")
+        .arg("-CreateDate")
+        .arg("-OffsetTimeOriginal")
+        .arg("-s")
+        .arg("-s")
         .arg(file_path)
         .output()
-        .expect("Failed to execute file command");
-
-    if output.status.success() {
-        let mime_type = str::from_utf8(&output.stdout).unwrap_or("").trim();
-        match mime_type {
-            "image/jpeg" => "jpg",
-            "image/png" => "png",
-            "image/gif" => "gif",
-            "image/heic" => "heic",
-            "image/tiff" => "tiff",
-            "image/webp" => "webp",
-            "video/mp4" => "mp4",
-            "video/quicktime" => "mov",
-            _ => "unknown",
-        }.to_string()
-    } else {
-        panic!("Failed to determine file type: {}", String::from_utf8_lossy(&output.stderr));
+        .map_err(|e| FixError::from_spawn("exiftool", file_path, e))?;
+
+    // e.g. "DateTimeOriginal: 2015:06:22 19:06:40"
+    let mut dates = ExifDates::default();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((tag, value)) = line.split_once(':') else { continue };
+        let value = Some(value.trim().to_string());
+        match tag.trim() {
+            "DateTimeOriginal" => dates.date_time_original = value,
+            "CreateDate" => dates.create_date = value,
+            "OffsetTimeOriginal" => dates.offset_time_original = value,
+            _ => {},
+        }
     }
+    Ok(dates)
 }
 
-fn get_original_extension(file_path: &str) -> String {
-    Path::new(file_path)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("")
-        .to_string()
+fn sync_modification_time_from_exif_dates(file_path: &Path, dates: &ExifDates) -> Result<(), FixError> {
+    match exif_dates_to_file_time(dates) {
+        Some(modified) => set_file_mtime(file_path, modified).map_err(|e| FixError::io(file_path, e)),
+        // An unparsable existing date, e.g. "0000:00:00 00:00:00", leaves the modification time alone
+        None => Ok(()),
+    }
 }
 
-fn rename_file(file_path: &str, new_extension: &str) -> String {
-    let path = Path::new(file_path);
-    let new_file_path = path.with_extension(new_extension);
-    fs::rename(file_path, &new_file_path).expect("Failed to rename file");
-    new_file_path.to_str().unwrap().to_string()
+fn is_apple_aae_file(file_path: &str) -> bool {
+    // Check for both "AAE" and "aae"
+    Path::new(file_path).extension().map(|ext| ext.eq_ignore_ascii_case("aae")).unwrap_or(false)
 }
 
+// exiftool goes by the extension, so the file carries the detected one while work runs on it
+pub(crate) fn with_detected_extension<R>(image_file_path: &str, media_kind: MediaKind, work: impl FnOnce(&str) -> Result<R, FixError>) -> Result<R, FixError> {
+    // change png to jpg, for example
+    // The temporary name is unique, so it never replaces a sibling file, even with several workers
+    let temporary_file_path = temporary_path_with_extension(image_file_path, media_kind.extension())?;
+    rename_file(image_file_path, &temporary_file_path)?;
+    let result = work(&temporary_file_path);
+
+    // Rename back to original name
+    // change jpg to png, for example
+    // This also happens when exiftool failed, so a failure never leaves the file renamed
+    rename_file(&temporary_file_path, image_file_path)?;
+    result
+}
 
-fn sync_metadata_modification_from_DateTimeOriginal_and_CreateDate(image_path: &str) -> Result<(), String> {
-
-    // Check if the file exists
-    if !Path::new(image_path).exists() {
-        panic!("File {} does not exist", image_path);
-    }
+// e.g. "IMG_0001.JPG" -> "IMG_0001.JPG.fixing-4242-7.png"
+fn temporary_path_with_extension(file_path: &str, extension: &str) -> Result<String, FixError> {
+    let path = Path::new(file_path);
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let counter = TEMPORARY_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temporary_path = path.with_file_name(format!("{}.fixing-{}-{}.{}", file_name, process::id(), counter, extension));
+    Ok(path_to_str(&temporary_path)?.to_string())
+}
 
-    // // Check if either "Date/Time Original" or "Create Date" tag exists
-    // let output = Command::new("exiftool")
-    //     .arg("-DateTimeOriginal")
-    //     .arg("-CreateDate")
-    //     .arg("-s")
-    //     .arg(file_path)
-    //     .output()
-    //     .expect("Failed to execute exiftool");
-    //
-    // let output_str = String::from_utf8_lossy(&output.stdout);
+fn rename_file(from: &str, to: &str) -> Result<(), FixError> {
+    fs::rename(from, to).map_err(|e| FixError::Rename {
+        from: PathBuf::from(from),
+        to: PathBuf::from(to),
+        source: e,
+    })
+}
 
-    // if output_str.contains("DateTimeOriginal") || output_str.contains("CreateDate") {
-    //     return Ok(());  // If either tag exists, skip the rest of the code
-    // }
+#[allow(non_snake_case)]
+fn sync_metadata_modification_from_DateTimeOriginal_and_CreateDate(image_path: &str) -> Result<(), FixError> {
+    // Check if the file exists
+    fs::metadata(image_path).map_err(|e| FixError::io(image_path, e))?;
 
     // https://exiftool.org/forum/index.php?topic=7843.0
-    let status = Command::new("exiftool")
+    let status = spawn_exiftool(image_path, Command::new("exiftool")
         .arg("-filemodifydate<createdate")
         .arg("-filecreatedate<createdate")
         .arg("-filemodifydate<datetimeoriginal")
         .arg("-filecreatedate<datetimeoriginal")
         .arg("-overwrite_original")
-        .arg(image_path)
-        .status();
+        .arg(image_path))?;
 
-    match status {
-        Ok(s) if s.success() => Ok(()),
-        _ => panic!("sync_modification error"),
+    if status.success() {
+        Ok(())
+    } else {
+        Err(FixError::tool_failed("exiftool", image_path, "sync_modification error"))
     }
 
 
@@ -141,115 +399,159 @@ fn sync_metadata_modification_from_DateTimeOriginal_and_CreateDate(image_path: &
 
 
 
-fn add_metadata_with_exiftool(file_path: &str, value: &str) -> Result<(), String> {
+fn add_metadata_with_exiftool(file_path: &str, value: &str, on_existing: ExistingDatePolicy) -> Result<(), FixError> {
 
     // Check if the file exists
-    if !Path::new(file_path).exists() {
-        panic!("File {} does not exist", file_path);
+    fs::metadata(file_path).map_err(|e| FixError::io(file_path, e))?;
+
+    let existing = existing_exif_date(&read_date_tags_with_exiftool(file_path)?);
+    if !writes_date(file_path, existing, &parse_metadata_value(Path::new(file_path), value)?, on_existing) {
+        return Ok(());  // If either tag exists and is kept, skip the rest of the code
     }
 
-    // Check if either "Date/Time Original" or "Create Date" tag exists
-    let output = Command::new("exiftool")
-        .arg("-DateTimeOriginal")
-        .arg("-CreateDate")
-        .arg("-s")
-        .arg(file_path)
-        .output()
-        .expect("Failed to execute exiftool");
+    add_date_time_tags(file_path, value)?;
+    log_line!("Date/Time tags added successfully");
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
+    Ok(())
 
-    if output_str.contains("DateTimeOriginal") || output_str.contains("CreateDate") {
-        return Ok(());  // If either tag exists, skip the rest of the code
-    }
+}
 
-    let original_metadata = fs::metadata(file_path).expect("Failed to get file metadata");
+// GPS already in the file always wins over the sidecar location
+pub fn has_gps_tags(file_path: &str, media_kind: MediaKind) -> Result<bool, FixError> {
+    match media_kind {
+        MediaKind::Jpeg => exif::jpeg_has_gps_coordinates(Path::new(file_path)),
+        MediaKind::Png => png_metadata::png_has_gps_coordinates(Path::new(file_path)),
+        _ => {
+            let output = Command::new("exiftool")
+                .arg("-GPSLatitude")
+                .arg("-GPSCoordinates")
+                .arg("-s")
+                .arg(file_path)
+                .output()
+                .map_err(|e| FixError::from_spawn("exiftool", file_path, e))?;
+
+            let output_str = String::from_utf8_lossy(&output.stdout);
 
-    match add_date_time_tags(file_path, value) {
-        Ok(_) => println!("Date/Time tags added successfully"),
-        Err(e) => println!("Error: {}", e),
+            Ok(output_str.contains("GPSLatitude") || output_str.contains("GPSCoordinates"))
+        },
     }
+}
 
+// In the temporary directory, so nothing but the media ends up in the output directory.
+// e.g. "IMG_0001.GIF" -> "/tmp/IMG_0001.GIF.4242-7.xml"
+fn generate_metadata_xml_name(file_path: &str) -> Result<String, FixError> {
+    let file_name = Path::new(file_path).file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let counter = TEMPORARY_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let xml_path = env::temp_dir().join(format!("{}.{}-{}.xml", file_name, process::id(), counter));
+    Ok(path_to_str(&xml_path)?.to_string())
+}
 
-    Ok(())
+// Like Command::status, but exiftool's own output goes through log_line! to keep the per-file log together
+fn spawn_exiftool(file_path: &str, command: &mut Command) -> Result<ExitStatus, FixError> {
+    let output = command
+        .output()
+        .map_err(|e| FixError::from_spawn("exiftool", file_path, e))?;
 
+    for line in String::from_utf8_lossy(&output.stdout).lines().chain(String::from_utf8_lossy(&output.stderr).lines()) {
+        log_line!("{}", line);
+    }
+    Ok(output.status)
 }
 
-fn generate_metadata_xml_name(file_path: &str) -> String {
-    let path = Path::new(file_path);
-    let xml_path = path.with_extension("xml");
-    xml_path.to_str().unwrap().to_string()
+fn run_exiftool(file_path: &str, command: &mut Command, what: &str) -> Result<ExitStatus, FixError> {
+    let status = spawn_exiftool(file_path, command)?;
+
+    if status.success() {
+        Ok(status)
+    } else {
+        Err(FixError::tool_failed("exiftool", file_path, format!("{} ({})", what, status)))
+    }
 }
 
 
 
-fn add_date_time_tags(file_path: &str, value: &str) -> Result<ExitStatus, String> {
-    // Attempt to add the new Date/Time tags
-    let status = Command::new("exiftool")
-        .arg(format!("-DateTimeOriginal={}", value))
-        .arg(format!("-CreateDate={}", value))
+// Writes the given tag assignments, keeping the modification time
+pub(crate) fn run_exiftool_with_args(file_path: &str, args: &[String]) -> Result<(), FixError> {
+    run_exiftool(file_path, Command::new("exiftool")
+        .args(args)
         .arg("-ignoreMinorErrors")
         .arg("-overwrite_original")
         .arg("-preserve")
-        .arg(file_path)
-        .status();
-
-    match status {
-        Ok(s) if s.success() => Ok(s),
-        _ => {
-            // Generate the metadata filename based on the file path name
-            let metadata_xml_filename = generate_metadata_xml_name(file_path);
+        .arg(file_path), "Failed to write sidecar tags")?;
+    Ok(())
+}
 
-            // If it fails, extract and re-add metadata, then add new Date/Time tags
-            let exported_status = Command::new("exiftool")
-                .arg("-ignoreMinorErrors")
-                .arg("-X") // Extract metadata as XML
-                .arg(file_path)
-                .arg("-o")
-                .arg(&metadata_xml_filename)
-                .status()
-                .expect("Failed to extract metadata");
-
-            println!("metadata successfully exported to: {}", metadata_xml_filename);
-
-            let delete_status = Command::new("exiftool")
-                .arg("-all=")
-                .arg("-ignoreMinorErrors")
-                .arg("-overwrite_original")
-                .arg("-preserve")
-                .arg(file_path)
-                .status()
-                .expect("Failed to delete metadata");
+// exiftool drops the offset of the value for DateTimeOriginal, so it goes to OffsetTimeOriginal as well,
+// e.g. "2015:06:22 19:06:40.000+09:00" gives "+09:00"
+fn date_time_tag_args(value: &str) -> Vec<String> {
+    let mut args = vec![format!("-DateTimeOriginal={}", value), format!("-CreateDate={}", value)];
+    if let Ok(date_time) = parse_metadata_value(Path::new(""), value) {
+        args.push(format!("-OffsetTimeOriginal={}", date_time.format("%:z")));
+    }
+    args
+}
 
-            println!("file_path {}'s metadata successfully deleted", file_path);
+fn add_date_time_tags(file_path: &str, value: &str) -> Result<ExitStatus, FixError> {
+    // Attempt to add the new Date/Time tags
+    let status = spawn_exiftool(file_path, Command::new("exiftool")
+        .args(date_time_tag_args(value))
+        .arg("-ignoreMinorErrors")
+        .arg("-overwrite_original")
+        .arg("-preserve")
+        .arg(file_path))?;
 
-            let add_status = Command::new("exiftool")
-                .arg("-ignoreMinorErrors")
-                .arg("-overwrite_original")
-                .arg("-preserve")
-                .arg("-tagsfromfile")
-                .arg(&metadata_xml_filename)
-                .arg(file_path)
-                .status()
-                .expect("Failed to add metadata back");
+    if status.success() {
+        return Ok(status);
+    }
 
-            println!("file_path {}'s metadata successfully add it back", file_path);
+    // Generate the metadata filename based on the file path name
+    let metadata_xml_filename = generate_metadata_xml_name(file_path)?;
 
-            let retry_status = Command::new("exiftool")
-                .arg(format!("-DateTimeOriginal={}", value))
-                .arg(format!("-CreateDate={}", value))
-                .arg("-ignoreMinorErrors")
-                .arg("-overwrite_original")
-                .arg("-preserve")
-                .arg(file_path)
-                .status()
-                .expect("Failed to execute exiftool on retry");
+    // If it fails, extract and re-add metadata, then add new Date/Time tags
+    let result = rewrite_metadata_through_xml(file_path, value, &metadata_xml_filename);
 
-            if retry_status.success() {
-                Ok(retry_status)
-            } else {
-                Err("Failed to add Date/Time tags on retry".to_string())
-            }
+    // Also when a step failed, exiftool may have written part of it
+    if let Err(e) = fs::remove_file(&metadata_xml_filename) {
+        if e.kind() != io::ErrorKind::NotFound {
+            log_line!("could not remove {}: {}", metadata_xml_filename, e);
         }
     }
-}
\ No newline at end of file
+    result
+}
+
+fn rewrite_metadata_through_xml(file_path: &str, value: &str, metadata_xml_filename: &str) -> Result<ExitStatus, FixError> {
+    run_exiftool(file_path, Command::new("exiftool")
+        .arg("-ignoreMinorErrors")
+        .arg("-X") // Extract metadata as XML
+        .arg(file_path)
+        .arg("-o")
+        .arg(metadata_xml_filename), "Failed to extract metadata")?;
+
+    log_line!("metadata successfully exported to: {}", metadata_xml_filename);
+
+    run_exiftool(file_path, Command::new("exiftool")
+        .arg("-all=")
+        .arg("-ignoreMinorErrors")
+        .arg("-overwrite_original")
+        .arg("-preserve")
+        .arg(file_path), "Failed to delete metadata")?;
+
+    log_line!("file_path {}'s metadata successfully deleted", file_path);
+
+    run_exiftool(file_path, Command::new("exiftool")
+        .arg("-ignoreMinorErrors")
+        .arg("-overwrite_original")
+        .arg("-preserve")
+        .arg("-tagsfromfile")
+        .arg(metadata_xml_filename)
+        .arg(file_path), "Failed to add metadata back")?;
+
+    log_line!("file_path {}'s metadata successfully add it back", file_path);
+
+    run_exiftool(file_path, Command::new("exiftool")
+        .args(date_time_tag_args(value))
+        .arg("-ignoreMinorErrors")
+        .arg("-overwrite_original")
+        .arg("-preserve")
+        .arg(file_path), "Failed to add Date/Time tags on retry")
+}
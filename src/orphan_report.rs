@@ -0,0 +1,69 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::date_sources::DateSource;
+use crate::error::FixError;
+
+// Sidecars whose media file could not be found, and media files that no sidecar belongs to.
+// Printed at the end of the run, and written as JSON with --orphan-report.
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrphanMedia {
+    pub path: PathBuf,
+    // The fallback that gave the date, None when the file was copied as it is
+    pub date_source: Option<DateSource>,
+    // e.g. "2019:07:04 18:30:12.000+00:00"
+    pub date_time: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct OrphanReport {
+    pub sidecars_without_media: Vec<PathBuf>,
+    pub media_without_sidecar: Vec<OrphanMedia>,
+}
+
+impl OrphanReport {
+    pub fn record_sidecar(&mut self, sidecar: impl AsRef<Path>) {
+        self.sidecars_without_media.push(sidecar.as_ref().to_path_buf());
+    }
+
+    pub fn record_media(&mut self, path: impl AsRef<Path>, fallback: Option<(DateSource, String)>) {
+        let (date_source, date_time) = fallback.unzip();
+        self.media_without_sidecar.push(OrphanMedia { path: path.as_ref().to_path_buf(), date_source, date_time });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sidecars_without_media.is_empty() && self.media_without_sidecar.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("paths and strings only")
+    }
+
+    pub fn write_json(&self, report_path: &Path) -> Result<(), FixError> {
+        fs::write(report_path, self.to_json() + "\n").map_err(|e| FixError::io(report_path, e))
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+
+        if !self.sidecars_without_media.is_empty() {
+            summary.push_str(&format!("{} sidecar(s) without media:\n", self.sidecars_without_media.len()));
+            for sidecar in &self.sidecars_without_media {
+                summary.push_str(&format!("  {}\n", sidecar.display()));
+            }
+        }
+
+        if !self.media_without_sidecar.is_empty() {
+            summary.push_str(&format!("{} media file(s) without sidecar:\n", self.media_without_sidecar.len()));
+            for media in &self.media_without_sidecar {
+                let outcome = match (&media.date_source, &media.date_time) {
+                    (Some(source), Some(date_time)) => format!("date from {}: {}", source, date_time),
+                    _ => "left alone".to_string(),
+                };
+                summary.push_str(&format!("  {}  {}\n", media.path.display(), outcome));
+            }
+        }
+        summary
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use chrono::Datelike;
+use crate::date_sources::year_of_folder;
+use crate::metadata_utils::parse_metadata_value;
+
+// Where the copies go inside the output directory.
+//
+// flat puts every file at the top, as before. mirror keeps the folders of the source, by-date
+// sorts by the year and month of the date written to the file, and by-album gives every Takeout
+// album its own folder, named after the title of its metadata.json.
+
+// The file Takeout writes into every album folder
+pub const ALBUM_METADATA_FILE_NAME: &str = "metadata.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputLayout {
+    #[default]
+    Flat,
+    Mirror,
+    ByDate,
+    ByAlbum,
+}
+
+impl OutputLayout {
+    pub fn parse(name: &str) -> Result<OutputLayout, String> {
+        match name {
+            "flat" => Ok(OutputLayout::Flat),
+            "mirror" => Ok(OutputLayout::Mirror),
+            "by-date" => Ok(OutputLayout::ByDate),
+            "by-album" => Ok(OutputLayout::ByAlbum),
+            _ => Err(format!("unknown --layout value {:?}, expected flat, mirror, by-date or by-album", name)),
+        }
+    }
+
+    // The directory a media file goes to, relative to the output directory.
+    // relative_path is the path of the file inside its source directory, or its Takeout zip entry name,
+    // and date_time the value written to it. Files without a date, or in no album, stay at the top.
+    pub fn relative_dir(&self, relative_path: &Path, date_time: Option<&str>, album: Option<&str>) -> PathBuf {
+        match self {
+            OutputLayout::Flat => PathBuf::new(),
+            OutputLayout::Mirror => relative_path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            OutputLayout::ByDate => match date_time.and_then(|value| parse_metadata_value(relative_path, value).ok()) {
+                Some(date_time) => Path::new(&format!("{:04}", date_time.year())).join(format!("{:02}", date_time.month())),
+                None => PathBuf::new(),
+            },
+            OutputLayout::ByAlbum => album.map(|title| PathBuf::from(album_folder_name(title))).unwrap_or_default(),
+        }
+    }
+}
+
+// --album-copies: what a photo gets in the albums after the first one it is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlbumCopies {
+    #[default]
+    Hardlink,
+    Symlink,
+    // Only the first album has the photo
+    Single,
+}
+
+impl AlbumCopies {
+    pub fn parse(name: &str) -> Result<AlbumCopies, String> {
+        match name {
+            "hardlink" => Ok(AlbumCopies::Hardlink),
+            "symlink" => Ok(AlbumCopies::Symlink),
+            "single" => Ok(AlbumCopies::Single),
+            _ => Err(format!("unknown --album-copies value {:?}, expected hardlink, symlink or single", name)),
+        }
+    }
+}
+
+// The title of an album metadata.json, from the album folder or a Takeout zip
+pub fn album_title_from_reader<R: Read>(reader: R) -> Option<String> {
+    let metadata: serde_json::Value = serde_json::from_reader(reader).ok()?;
+    let title = metadata.get("title")?.as_str()?.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+// "Photos from YYYY" holds the photos outside of albums, and is no album itself
+pub fn is_year_folder(directory: &Path) -> bool {
+    directory.file_name().and_then(|name| name.to_str()).and_then(year_of_folder).is_some()
+}
+
+// None for "Photos from YYYY", and for folders without an album metadata.json
+pub fn read_album_title(directory: &Path) -> Option<String> {
+    if is_year_folder(directory) {
+        return None;
+    }
+    let file = File::open(directory.join(ALBUM_METADATA_FILE_NAME)).ok()?;
+    album_title_from_reader(BufReader::new(file))
+}
+
+// Album titles are free text, e.g. "Paris / London 2019"
+pub fn album_folder_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    // "." and ".." are no folder names
+    let name = name.trim().trim_end_matches('.').trim_end();
+    if name.is_empty() { "_".to_string() } else { name.to_string() }
+}
+
+// One copy of a media file, as found in the sources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlbumCopy<K> {
+    // The same for copies with the same content, None when there is no other copy
+    pub key: Option<K>,
+    pub album: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlbumPlacement {
+    // Gets a file of its own
+    Own,
+    // Becomes a link to the copy at that index, which is in another album
+    LinkTo(usize),
+    // Left out, the copy at that index already has the photo
+    SameAs(usize),
+}
+
+// With by-album a photo is copied once, into the first album it is in. Its copies in other albums
+// become links to it, or are left out with --album-copies single. Its copy in "Photos from YYYY",
+// or a second copy in the same album, is left out. A photo in no album is copied as before.
+pub fn place_album_copies<K: Hash + Eq>(copies: &[AlbumCopy<K>], album_copies: AlbumCopies) -> Vec<AlbumPlacement> {
+    let mut first_in_album: HashMap<&K, usize> = HashMap::new();
+    for (index, copy) in copies.iter().enumerate() {
+        if let (Some(key), Some(_)) = (&copy.key, &copy.album) {
+            first_in_album.entry(key).or_insert(index);
+        }
+    }
+
+    let mut placed: HashMap<(&K, &str), usize> = HashMap::new();
+    copies
+        .iter()
+        .enumerate()
+        .map(|(index, copy)| {
+            let Some(key) = &copy.key else { return AlbumPlacement::Own };
+            let Some(&first) = first_in_album.get(key) else { return AlbumPlacement::Own };
+            let Some(album) = &copy.album else { return AlbumPlacement::SameAs(first) };
+
+            if let Some(&same) = placed.get(&(key, album.as_str())) {
+                return AlbumPlacement::SameAs(same);
+            }
+            placed.insert((key, album), index);
+            match album_copies {
+                _ if index == first => AlbumPlacement::Own,
+                AlbumCopies::Single => AlbumPlacement::SameAs(first),
+                AlbumCopies::Hardlink | AlbumCopies::Symlink => AlbumPlacement::LinkTo(first),
+            }
+        })
+        .collect()
+}
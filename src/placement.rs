@@ -0,0 +1,261 @@
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use crate::dedup;
+use crate::error::FixError;
+use crate::extension_fix;
+use crate::file_utils::{self, CollisionNaming, CollisionPolicy};
+use crate::journal::{self, JournalHistory};
+use crate::media_kind::MediaKind;
+use crate::output_layout::{self, AlbumCopies, AlbumCopy, AlbumPlacement, OutputLayout};
+use crate::takeout_zip::{TakeoutZipEntry, TakeoutZipSet};
+
+// Where the media files of a source go: the directory --layout gives them, the name --fix-extensions
+// gives them, the copies --album-copies and --dedup link or leave out, and the destination names.
+// The run and --dry-run both plan here, neither touches the output directory while planning.
+
+// Where and under which names the copies go, the same for a run and its dry run
+#[derive(Debug, Clone, Copy)]
+pub struct CopyOptions<'a> {
+    pub output_dir: &'a Path,
+    pub layout: OutputLayout,
+    pub album_copies: AlbumCopies,
+    pub dedup: bool,
+    pub collision_naming: CollisionNaming,
+    pub on_collision: CollisionPolicy,
+    pub output_per_source: bool,
+    pub fix_extensions: bool,
+}
+
+// Where one media file goes
+pub struct Placement {
+    // Relative to the output directory
+    pub dir: PathBuf,
+    // The name of the source, or the one --fix-extensions gives it
+    pub file_name: OsString,
+    pub album: AlbumPlacement,
+    // Content hash, when it was read to find the other copies of the photo
+    pub hash: Option<String>,
+}
+
+// Lays out the media files for --layout, in the same order, and leaves out the duplicates with --dedup.
+// With by-album, only the files sharing a name and size with another one are hashed to find the copies of a photo.
+// --dedup hashes every file sharing its size with another one, whatever its name.
+fn place_media_files(media_files: &[(&str, PathBuf)], get_date: impl Fn(&Path) -> Option<String>, options: &CopyOptions) -> Vec<Placement> {
+    let CopyOptions { layout, album_copies, dedup, fix_extensions, .. } = *options;
+    let file_name = |file_path: &Path| (!dedup).then(|| file_path.file_name().unwrap_or_default().to_os_string());
+    let file_size = |file_path: &Path| fs::metadata(file_path).map(|metadata| metadata.len()).ok();
+
+    let mut albums: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let hashed = match layout == OutputLayout::ByAlbum || dedup {
+        true => dedup::shares_key(&media_files.iter().map(|(_, file_path)| (file_name(file_path), file_size(file_path))).collect::<Vec<_>>()),
+        false => vec![false; media_files.len()],
+    };
+    let new_names = match fix_extensions {
+        true => {
+            let paths: Vec<&Path> = media_files.iter().map(|(_, file_path)| file_path.as_path()).collect();
+            let kinds: Vec<MediaKind> = paths.iter().map(|file_path| MediaKind::detect_file(file_path).unwrap_or(MediaKind::Unknown)).collect();
+            extension_fix::fix_file_names(&paths, &kinds, |path| path.exists())
+        },
+        false => vec![None; media_files.len()],
+    };
+
+    let mut placements = Vec::new();
+    let mut copies = Vec::new();
+    for (((source_dir, file_path), hashed), new_name) in media_files.iter().zip(hashed).zip(new_names) {
+        let album = match layout {
+            OutputLayout::ByAlbum => {
+                let directory = file_path.parent().unwrap_or_else(|| Path::new(""));
+                albums.entry(directory.to_path_buf()).or_insert_with(|| output_layout::read_album_title(directory)).clone()
+            },
+            _ => None,
+        };
+        let relative_path = file_path.strip_prefix(source_dir).unwrap_or(file_path);
+        let dir = layout.relative_dir(relative_path, get_date(file_path).as_deref(), album.as_deref());
+
+        let hash = if hashed { journal::hash_file(file_path).ok() } else { None };
+        copies.push(AlbumCopy { key: hash.clone().map(|hash| (file_name(file_path), hash)), album });
+        let file_name = new_name.map(OsString::from).unwrap_or_else(|| file_path.file_name().unwrap_or_default().to_os_string());
+        placements.push(Placement { dir, file_name, album: AlbumPlacement::Own, hash });
+    }
+
+    set_album_placements(&mut placements, &copies, album_copies, dedup);
+    placements
+}
+
+fn set_album_placements<K: Hash + Eq>(placements: &mut [Placement], copies: &[AlbumCopy<K>], album_copies: AlbumCopies, dedup: bool) {
+    let mut album_placements = output_layout::place_album_copies(copies, album_copies);
+    if dedup {
+        dedup::place_duplicates(copies, &mut album_placements);
+    }
+    for (placement, album) in placements.iter_mut().zip(album_placements) {
+        placement.album = album;
+    }
+}
+
+// Lays out the media entries of the zip parts for --layout, like place_media_files.
+// Only the entries sharing a size and crc32 of the zip directory with another one are read and
+// hashed, a crc32 alone does not tell two photos apart, and with skip-if-identical every entry is.
+// --fix-extensions reads the first bytes of every entry.
+fn place_takeout_zip_entries(
+    zip_set: &mut TakeoutZipSet,
+    media_entries: &[TakeoutZipEntry],
+    get_date: impl Fn(&TakeoutZipEntry) -> Option<String>,
+    options: &CopyOptions,
+) -> Result<Vec<Placement>, FixError> {
+    let CopyOptions { layout, album_copies, dedup, fix_extensions, on_collision, .. } = *options;
+    let mut albums = HashMap::new();
+    if layout == OutputLayout::ByAlbum {
+        for json_entry in zip_set.json_entries(&[]) {
+            let album_dir = Path::new(&json_entry.name).parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+            if json_entry.file_name() != output_layout::ALBUM_METADATA_FILE_NAME || output_layout::is_year_folder(&album_dir) {
+                continue;
+            }
+            if let Some(title) = output_layout::album_title_from_reader(zip_set.open_entry(&json_entry)?) {
+                albums.insert(album_dir, title);
+            }
+        }
+    }
+    let new_names = match fix_extensions {
+        true => {
+            let mut kinds = Vec::new();
+            for media_entry in media_entries {
+                kinds.push(MediaKind::detect_reader(zip_set.open_entry(media_entry)?).unwrap_or(MediaKind::Unknown));
+            }
+            let paths: Vec<&Path> = media_entries.iter().map(|media_entry| Path::new(&media_entry.name)).collect();
+            extension_fix::fix_file_names(&paths, &kinds, |path| zip_set.find(&path.to_string_lossy()).is_some())
+        },
+        false => vec![None; media_entries.len()],
+    };
+
+    let name = |media_entry: &TakeoutZipEntry| (!dedup).then(|| media_entry.file_name().to_string());
+    let checksums: Vec<_> = media_entries
+        .iter()
+        .map(|media_entry| Some((name(media_entry), zip_set.size(media_entry)?, zip_set.crc32(media_entry)?)))
+        .collect();
+    let hashed = match layout == OutputLayout::ByAlbum || dedup {
+        true => dedup::shares_key(&checksums),
+        false => vec![false; media_entries.len()],
+    };
+
+    let mut placements = Vec::new();
+    let mut copies = Vec::new();
+    for (((media_entry, new_name), checksum), hashed) in media_entries.iter().zip(new_names).zip(&checksums).zip(hashed) {
+        let media_path = Path::new(&media_entry.name);
+        let album = media_path.parent().and_then(|album_dir| albums.get(album_dir)).cloned();
+        let date_time = match layout {
+            OutputLayout::ByDate => get_date(media_entry),
+            _ => None,
+        };
+        let dir = layout.relative_dir(media_path, date_time.as_deref(), album.as_deref());
+
+        // An entry without a checksum is never taken for a copy of another one
+        let hash = match checksum {
+            Some(_) if hashed => Some(zip_set.hash_entry(media_entry)?),
+            _ if on_collision == CollisionPolicy::SkipIfIdentical => Some(zip_set.hash_entry(media_entry)?),
+            Some((_, _, crc32)) => Some(format!("crc32:{:08x}", crc32)),
+            None => None,
+        };
+        let key = hash.clone().filter(|_| hashed && checksum.is_some()).map(|hash| (name(media_entry), hash));
+        copies.push(AlbumCopy { key, album });
+        let file_name = OsString::from(new_name.as_deref().unwrap_or(media_entry.file_name()));
+        placements.push(Placement { dir, file_name, album: AlbumPlacement::Own, hash });
+    }
+
+    set_album_placements(&mut placements, &copies, album_copies, dedup);
+    Ok(placements)
+}
+
+// The file that gets the copy a link or a left out duplicate stands for
+pub fn kept_copy(placements: &[Placement], mut index: usize) -> usize {
+    while let AlbumPlacement::LinkTo(first) | AlbumPlacement::SameAs(first) = placements[index].album {
+        index = first;
+    }
+    index
+}
+
+// Where the media files of one source go. The copy step and --dry-run both follow it,
+// so a dry run shows the names the run will use.
+pub struct CopyPlan {
+    pub placements: Vec<Placement>,
+    // Where each file is copied or linked to, None for the duplicates --dedup leaves out
+    pub destinations: Vec<Option<PathBuf>>,
+}
+
+impl CopyPlan {
+    // The name the file gets when no other file is in the way
+    pub fn wanted(&self, output_dir: &Path, index: usize) -> PathBuf {
+        output_dir.join(&self.placements[index].dir).join(&self.placements[index].file_name)
+    }
+}
+
+// Every destination name is picked here, sequentially, so workers never race for the same name.
+// A file an earlier run already worked on goes to the destination recorded in the journal again,
+// and skip-if-identical goes by the source the journal says a file already there was written from.
+// sources are the source files or the zip entry paths, and hash gives the content hash of one of them.
+fn plan_destinations(
+    sources: &[PathBuf],
+    placements: &[Placement],
+    output_dir: &Path,
+    options: &CopyOptions,
+    history: &JournalHistory,
+    hash: impl Fn(usize) -> Option<String>,
+    reserved: &mut HashSet<PathBuf>,
+) -> Vec<Option<PathBuf>> {
+    let mut destinations = Vec::new();
+    for (index, (source, placement)) in sources.iter().zip(placements).enumerate() {
+        // Read at most once, for the name and for skip-if-identical
+        let source_hash = OnceCell::new();
+        let hash = || source_hash.get_or_init(|| hash(index)).clone();
+        let dest_path = match (placement.album, history.previous(source)) {
+            (AlbumPlacement::SameAs(_), _) => None,
+            (_, Some(previous)) => Some(previous.dest.clone()),
+            (_, None) => Some(file_utils::reserve_destination(
+                &output_dir.join(&placement.dir).join(&placement.file_name),
+                options.collision_naming,
+                options.on_collision,
+                source,
+                hash,
+                |existing| hash().is_some_and(|hash| history.written_from(existing, &hash)),
+                reserved,
+            )),
+        };
+        destinations.push(dest_path);
+    }
+    destinations
+}
+
+pub fn plan_media_files(
+    media_files: &[(&str, PathBuf)],
+    get_date: impl Fn(&Path) -> Option<String>,
+    output_dir: &Path,
+    options: &CopyOptions,
+    history: &JournalHistory,
+    reserved: &mut HashSet<PathBuf>,
+) -> CopyPlan {
+    let placements = place_media_files(media_files, get_date, options);
+    let sources: Vec<PathBuf> = media_files.iter().map(|(_, file_path)| file_path.clone()).collect();
+    let hash = |index: usize| placements[index].hash.clone().or_else(|| journal::hash_file(&sources[index]).ok());
+    let destinations = plan_destinations(&sources, &placements, output_dir, options, history, hash, reserved);
+    CopyPlan { placements, destinations }
+}
+
+// Like plan_media_files, the zip directory already has a checksum of every entry
+pub fn plan_takeout_zip_entries(
+    zip_set: &mut TakeoutZipSet,
+    media_entries: &[TakeoutZipEntry],
+    get_date: impl Fn(&TakeoutZipEntry) -> Option<String>,
+    output_dir: &Path,
+    options: &CopyOptions,
+    history: &JournalHistory,
+    reserved: &mut HashSet<PathBuf>,
+) -> Result<CopyPlan, FixError> {
+    let placements = place_takeout_zip_entries(zip_set, media_entries, get_date, options)?;
+    let sources: Vec<PathBuf> = media_entries.iter().map(|media_entry| zip_set.entry_path(media_entry)).collect();
+    let hash = |index: usize| placements[index].hash.clone();
+    let destinations = plan_destinations(&sources, &placements, output_dir, options, history, hash, reserved);
+    Ok(CopyPlan { placements, destinations })
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use crate::error::FixError;
+use crate::exif::{has_gps_coordinates, read_exif_dates, set_exif_ascii_tags, ExifDates, EXIF_HEADER};
+
+// PNG container: the TIFF block lives in an eXIf chunk, the creation time can also be stored
+// as a "Creation Time" tEXt chunk.
+//
+// Every other chunk is copied byte for byte, CRC included, and so is anything after IEND.
+// Only the chunks written here get a newly computed CRC.
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+pub const CREATION_TIME_KEYWORD: &str = "Creation Time";
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    kind: [u8; 4],
+    // The whole chunk: length, type, data and CRC
+    range: Range<usize>,
+    data: Range<usize>,
+}
+
+fn parse_chunks(png: &[u8]) -> Result<Vec<Chunk>, String> {
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err("not a PNG file".to_string());
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 12 <= png.len() {
+        let length = u32::from_be_bytes([png[pos], png[pos + 1], png[pos + 2], png[pos + 3]]) as usize;
+        let end = pos + 12 + length;
+        if end > png.len() {
+            return Err(format!("truncated PNG chunk at byte {}", pos));
+        }
+
+        let mut kind = [0u8; 4];
+        kind.copy_from_slice(&png[pos + 4..pos + 8]);
+        chunks.push(Chunk { kind, range: pos..end, data: pos + 8..end - 4 });
+
+        if &kind == b"IEND" {
+            return Ok(chunks);
+        }
+        pos = end;
+    }
+
+    Err("PNG ended before the IEND chunk".to_string())
+}
+
+fn build_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+
+    // The CRC covers the chunk type and data, not the length
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    chunk.extend_from_slice(&hasher.finalize().to_be_bytes());
+    chunk
+}
+
+// tEXt data: keyword, a null separator, then Latin-1 text
+fn text_chunk_value<'a>(png: &'a [u8], chunk: &Chunk, keyword: &str) -> Option<&'a [u8]> {
+    let data = &png[chunk.data.clone()];
+    let separator = data.iter().position(|&b| b == 0)?;
+    (&data[..separator] == keyword.as_bytes()).then(|| &data[separator + 1..])
+}
+
+// The eXIf data should be the bare TIFF block, but some writers keep the JPEG style "Exif\0\0" in front
+pub fn read_png_exif(png: &[u8]) -> Result<Option<&[u8]>, String> {
+    Ok(parse_chunks(png)?
+        .iter()
+        .find(|chunk| &chunk.kind == b"eXIf")
+        .map(|chunk| {
+            let data = &png[chunk.data.clone()];
+            data.strip_prefix(EXIF_HEADER).unwrap_or(data)
+        }))
+}
+
+pub fn read_png_text(png: &[u8], keyword: &str) -> Result<Option<String>, String> {
+    Ok(parse_chunks(png)?
+        .iter()
+        .filter(|chunk| &chunk.kind == b"tEXt")
+        .find_map(|chunk| text_chunk_value(png, chunk, keyword))
+        .map(|text| text.iter().map(|&b| b as char).collect()))
+}
+
+// Replaces the eXIf chunk and the tEXt chunk with the given keyword, or inserts them right
+// after IHDR. eXIf has to come before the image data, right after IHDR is always valid.
+// Only one of each is kept: the first takes the new data, any further copies are dropped.
+pub fn write_png_chunks(png: &[u8], tiff: &[u8], text: Option<(&str, &str)>) -> Result<Vec<u8>, String> {
+    let chunks = parse_chunks(png)?;
+    if chunks.first().map(|chunk| &chunk.kind) != Some(b"IHDR") {
+        return Err("PNG does not start with an IHDR chunk".to_string());
+    }
+
+    let exif_chunk = build_chunk(b"eXIf", tiff);
+    let text_chunk = match text {
+        Some((keyword, value)) => {
+            if !value.chars().all(|c| (c as u32) < 0x100) {
+                return Err(format!("tEXt value {:?} is not Latin-1", value));
+            }
+            let mut data = keyword.as_bytes().to_vec();
+            data.push(0);
+            data.extend(value.chars().map(|c| c as u8));
+            Some(build_chunk(b"tEXt", &data))
+        },
+        None => None,
+    };
+
+    let is_replaced_text = |chunk: &Chunk| match text {
+        Some((keyword, _)) => &chunk.kind == b"tEXt" && text_chunk_value(png, chunk, keyword).is_some(),
+        None => false,
+    };
+    let has_exif = chunks.iter().any(|chunk| &chunk.kind == b"eXIf");
+    let has_text = chunks.iter().any(is_replaced_text);
+
+    let mut output = Vec::with_capacity(png.len() + exif_chunk.len() + 64);
+    output.extend_from_slice(PNG_SIGNATURE);
+    let (mut exif_written, mut text_written) = (false, false);
+    for chunk in &chunks {
+        if &chunk.kind == b"eXIf" {
+            if !exif_written {
+                output.extend_from_slice(&exif_chunk);
+                exif_written = true;
+            }
+        } else if is_replaced_text(chunk) {
+            if !text_written {
+                // text_chunk is Some whenever is_replaced_text matched
+                output.extend_from_slice(text_chunk.as_deref().unwrap_or(&[]));
+                text_written = true;
+            }
+        } else {
+            output.extend_from_slice(&png[chunk.range.clone()]);
+        }
+
+        if &chunk.kind == b"IHDR" {
+            if !has_exif {
+                output.extend_from_slice(&exif_chunk);
+            }
+            if let (false, Some(text_chunk)) = (has_text, &text_chunk) {
+                output.extend_from_slice(text_chunk);
+            }
+        }
+    }
+
+    // Some apps append data after IEND, e.g. a thumbnail or a Live Photo payload
+    let end_of_chunks = chunks.last().map_or(PNG_SIGNATURE.len(), |chunk| chunk.range.end);
+    output.extend_from_slice(&png[end_of_chunks..]);
+    Ok(output)
+}
+
+pub fn read_png_date_tags(file_path: &Path) -> Result<ExifDates, FixError> {
+    let png = fs::read(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let invalid = |message: String| FixError::InvalidMetadata { path: file_path.to_path_buf(), message };
+
+    match read_png_exif(&png).map_err(invalid)? {
+        Some(tiff) => read_exif_dates(tiff).map_err(invalid),
+        None => Ok(ExifDates::default()),
+    }
+}
+
+pub fn png_has_gps_coordinates(file_path: &Path) -> Result<bool, FixError> {
+    let png = fs::read(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let invalid = |message: String| FixError::InvalidMetadata { path: file_path.to_path_buf(), message };
+
+    match read_png_exif(&png).map_err(invalid)? {
+        Some(tiff) => has_gps_coordinates(tiff).map_err(invalid),
+        None => Ok(false),
+    }
+}
+
+pub fn write_png_date_tags(file_path: &Path, tags: &[(u16, String)], creation_time: &str) -> Result<(), FixError> {
+    let png = fs::read(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let invalid = |message: String| FixError::InvalidMetadata { path: file_path.to_path_buf(), message };
+
+    let existing_tiff = read_png_exif(&png).map_err(invalid)?;
+    let tiff = set_exif_ascii_tags(existing_tiff, tags).map_err(invalid)?;
+    let output = write_png_chunks(&png, &tiff, Some((CREATION_TIME_KEYWORD, creation_time))).map_err(invalid)?;
+
+    fs::write(file_path, output).map_err(|e| FixError::io(file_path, e))
+}
@@ -0,0 +1,297 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use crate::error::FixError;
+
+// MP4/MOV creation and modification times.
+//
+// The movie (mvhd), track (tkhd) and media (mdhd) headers store seconds since 1904-01-01
+// and by the QuickTime convention these are always UTC. All of them have a fixed size,
+// so the moov atom is patched in place and the media data is never moved.
+
+// Seconds between 1904-01-01 and 1970-01-01
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+pub const APPLE_CREATION_DATE_KEY: &str = "com.apple.quicktime.creationdate";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QuickTimeDates {
+    // None when the header holds 0, i.e. no date was ever set
+    pub movie_creation_time: Option<DateTime<Utc>>,
+    pub track_creation_times: Vec<Option<DateTime<Utc>>>,
+    pub media_creation_times: Vec<Option<DateTime<Utc>>>,
+    pub apple_creation_date: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QuickTimeUpdate {
+    pub headers_updated: usize,
+    pub apple_creation_date_updated: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Atom {
+    kind: [u8; 4],
+    payload_start: usize,
+    end: usize,
+}
+
+// Where the header atoms and the Apple creation date value live inside the moov data
+#[derive(Debug, Default)]
+struct MoovLayout {
+    mvhd: Vec<usize>,
+    tkhd: Vec<usize>,
+    mdhd: Vec<usize>,
+    // payload range of the `data` atom holding the value, after its type and locale fields
+    apple_creation_date: Option<(usize, usize)>,
+}
+
+// A 64 bit size can be anything in a broken file
+fn atom_size_out_of_range() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "atom size out of range")
+}
+
+fn parse_children(data: &[u8], start: usize, end: usize) -> io::Result<Vec<Atom>> {
+    let mut atoms = Vec::new();
+    let mut pos = start;
+
+    while pos + 8 <= end {
+        let size32 = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as u64;
+        let mut kind = [0u8; 4];
+        kind.copy_from_slice(&data[pos + 4..pos + 8]);
+
+        let (size, header_len) = match size32 {
+            0 => ((end - pos) as u64, 8),
+            1 if pos + 16 <= end => {
+                let mut large = [0u8; 8];
+                large.copy_from_slice(&data[pos + 8..pos + 16]);
+                (u64::from_be_bytes(large), 16)
+            },
+            _ => (size32, 8),
+        };
+        let atom_end = usize::try_from(size).ok().and_then(|size| pos.checked_add(size)).ok_or_else(atom_size_out_of_range)?;
+
+        if size < header_len || atom_end > end {
+            break;
+        }
+
+        atoms.push(Atom { kind, payload_start: pos + header_len as usize, end: atom_end });
+        pos = atom_end;
+    }
+
+    Ok(atoms)
+}
+
+// QuickTime `meta` atoms have no version/flags field, ISO ones do
+fn meta_children_start(data: &[u8], meta: &Atom) -> usize {
+    let looks_like_atom = |pos: usize| {
+        pos + 8 <= meta.end && data[pos + 4..pos + 8].iter().all(|b| b.is_ascii_alphanumeric() || *b == b' ')
+    };
+    if looks_like_atom(meta.payload_start) {
+        meta.payload_start
+    } else {
+        meta.payload_start + 4
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    data.get(pos..pos + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn find_apple_creation_date(data: &[u8], meta: &Atom) -> io::Result<Option<(usize, usize)>> {
+    let children = parse_children(data, meta_children_start(data, meta), meta.end)?;
+    let (Some(keys), Some(ilst)) = (children.iter().find(|atom| &atom.kind == b"keys"), children.iter().find(|atom| &atom.kind == b"ilst")) else {
+        return Ok(None);
+    };
+    let Some(key_index) = find_key_index(data, keys) else {
+        return Ok(None);
+    };
+
+    // ilst: one item per key, its atom type is the key index
+    let Some(item) = parse_children(data, ilst.payload_start, ilst.end)?
+        .into_iter()
+        .find(|atom| u32::from_be_bytes(atom.kind) == key_index)
+    else {
+        return Ok(None);
+    };
+    let Some(value) = parse_children(data, item.payload_start, item.end)?
+        .into_iter()
+        .find(|atom| &atom.kind == b"data")
+    else {
+        return Ok(None);
+    };
+
+    // data: type indicator (4 bytes), locale (4 bytes), value
+    let value_start = value.payload_start + 8;
+    Ok((value_start <= value.end).then_some((value_start, value.end)))
+}
+
+fn find_key_index(data: &[u8], keys: &Atom) -> Option<u32> {
+    // keys: version/flags, entry count, then (size, namespace, name) entries, 1-based
+    let entry_count = read_u32(data, keys.payload_start + 4)?;
+    let mut pos = keys.payload_start + 8;
+    for index in 1..=entry_count {
+        let key_size = read_u32(data, pos)? as usize;
+        if key_size < 8 || pos + key_size > keys.end {
+            return None;
+        }
+        if &data[pos + 8..pos + key_size] == APPLE_CREATION_DATE_KEY.as_bytes() {
+            return Some(index);
+        }
+        pos += key_size;
+    }
+    None
+}
+
+fn collect_layout(data: &[u8], start: usize, end: usize, layout: &mut MoovLayout) -> io::Result<()> {
+    for atom in parse_children(data, start, end)? {
+        match &atom.kind {
+            b"mvhd" => layout.mvhd.push(atom.payload_start),
+            b"tkhd" => layout.tkhd.push(atom.payload_start),
+            b"mdhd" => layout.mdhd.push(atom.payload_start),
+            b"trak" | b"mdia" => collect_layout(data, atom.payload_start, atom.end, layout)?,
+            b"meta" if layout.apple_creation_date.is_none() => {
+                layout.apple_creation_date = find_apple_creation_date(data, &atom)?;
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+fn quicktime_to_utc(seconds: u64) -> Option<DateTime<Utc>> {
+    if seconds == 0 {
+        return None;
+    }
+    DateTime::from_timestamp(seconds as i64 - QUICKTIME_EPOCH_OFFSET, 0)
+}
+
+fn read_header_creation_time(data: &[u8], payload_start: usize) -> Option<DateTime<Utc>> {
+    let version = *data.get(payload_start)?;
+    let seconds = if version == 1 {
+        let bytes = data.get(payload_start + 4..payload_start + 12)?;
+        u64::from_be_bytes(bytes.try_into().ok()?)
+    } else {
+        read_u32(data, payload_start + 4)? as u64
+    };
+    quicktime_to_utc(seconds)
+}
+
+// Sets both the creation and the modification time of a mvhd/tkhd/mdhd header
+fn write_header_times(data: &mut [u8], payload_start: usize, seconds: u64) -> bool {
+    match data.get(payload_start) {
+        Some(1) if payload_start + 20 <= data.len() => {
+            data[payload_start + 4..payload_start + 12].copy_from_slice(&seconds.to_be_bytes());
+            data[payload_start + 12..payload_start + 20].copy_from_slice(&seconds.to_be_bytes());
+            true
+        },
+        Some(0) if payload_start + 12 <= data.len() => {
+            let seconds = seconds.min(u32::MAX as u64) as u32;
+            data[payload_start + 4..payload_start + 8].copy_from_slice(&seconds.to_be_bytes());
+            data[payload_start + 8..payload_start + 12].copy_from_slice(&seconds.to_be_bytes());
+            true
+        },
+        _ => false,
+    }
+}
+
+// Returns the file offset and the bytes of the top level moov atom
+fn read_moov(file: &mut File) -> io::Result<Option<(u64, Vec<u8>)>> {
+    let file_len = file.metadata()?.len();
+    let mut pos = 0u64;
+
+    while pos + 8 <= file_len {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+
+        let size32 = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let size = match size32 {
+            0 => file_len - pos,
+            1 => {
+                file.read_exact(&mut header[8..16])?;
+                u64::from_be_bytes(header[8..16].try_into().unwrap())
+            },
+            _ => size32,
+        };
+        let atom_end = pos.checked_add(size).ok_or_else(atom_size_out_of_range)?;
+        if size < 8 || atom_end > file_len {
+            return Ok(None);
+        }
+
+        if &header[4..8] == b"moov" {
+            file.seek(SeekFrom::Start(pos))?;
+            let mut moov = vec![0u8; usize::try_from(size).map_err(|_| atom_size_out_of_range())?];
+            file.read_exact(&mut moov)?;
+            return Ok(Some((pos, moov)));
+        }
+
+        pos = atom_end;
+    }
+
+    Ok(None)
+}
+
+fn moov_or_error(file_path: &Path, file: &mut File) -> Result<(u64, Vec<u8>), FixError> {
+    read_moov(file)
+        .map_err(|e| FixError::io(file_path, e))?
+        .ok_or_else(|| FixError::InvalidMetadata {
+            path: file_path.to_path_buf(),
+            message: "no moov atom found".to_string(),
+        })
+}
+
+fn layout_of(file_path: &Path, moov: &[u8]) -> Result<MoovLayout, FixError> {
+    let mut layout = MoovLayout::default();
+    let root = parse_children(moov, 0, moov.len()).map_err(|e| FixError::io(file_path, e))?;
+    if let Some(root) = root.first() {
+        collect_layout(moov, root.payload_start, root.end, &mut layout).map_err(|e| FixError::io(file_path, e))?;
+    }
+    Ok(layout)
+}
+
+pub fn read_quicktime_dates(file_path: &Path) -> Result<QuickTimeDates, FixError> {
+    let mut file = File::open(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let (_, moov) = moov_or_error(file_path, &mut file)?;
+    let layout = layout_of(file_path, &moov)?;
+
+    Ok(QuickTimeDates {
+        movie_creation_time: layout.mvhd.first().and_then(|&pos| read_header_creation_time(&moov, pos)),
+        track_creation_times: layout.tkhd.iter().map(|&pos| read_header_creation_time(&moov, pos)).collect(),
+        media_creation_times: layout.mdhd.iter().map(|&pos| read_header_creation_time(&moov, pos)).collect(),
+        apple_creation_date: layout
+            .apple_creation_date
+            .map(|(start, end)| String::from_utf8_lossy(&moov[start..end]).to_string()),
+    })
+}
+
+// Writes creation_time to every mvhd/tkhd/mdhd header, in place.
+// The Apple creation date key is only rewritten when it already exists and the new value has
+// the same length. It is never added: a file without meta/keys keeps its moov as it is, apart from the headers.
+pub fn write_quicktime_dates(file_path: &Path, creation_time: DateTime<Utc>, apple_creation_date: Option<&str>) -> Result<QuickTimeUpdate, FixError> {
+    let mut file = OpenOptions::new().read(true).write(true).open(file_path).map_err(|e| FixError::io(file_path, e))?;
+    let (moov_offset, mut moov) = moov_or_error(file_path, &mut file)?;
+    let layout = layout_of(file_path, &moov)?;
+
+    let seconds = (creation_time.timestamp() + QUICKTIME_EPOCH_OFFSET).max(0) as u64;
+    let mut update = QuickTimeUpdate::default();
+
+    for &pos in layout.mvhd.iter().chain(&layout.tkhd).chain(&layout.mdhd) {
+        if write_header_times(&mut moov, pos, seconds) {
+            update.headers_updated += 1;
+        }
+    }
+
+    if let (Some(value), Some((start, end))) = (apple_creation_date, layout.apple_creation_date) {
+        if value.len() == end - start {
+            moov[start..end].copy_from_slice(value.as_bytes());
+            update.apple_creation_date_updated = true;
+        }
+    }
+
+    file.seek(SeekFrom::Start(moov_offset)).map_err(|e| FixError::io(file_path, e))?;
+    file.write_all(&moov).map_err(|e| FixError::io(file_path, e))?;
+
+    Ok(update)
+}
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+use crate::date_report::DateReport;
+use crate::dedup::DuplicateReport;
+use crate::error::FixError;
+use crate::extension_fix::ExtensionReport;
+use crate::media_kind::MediaKind;
+use crate::orphan_report::OrphanReport;
+
+#[derive(Debug)]
+pub struct FileFailure {
+    pub path: PathBuf,
+    pub error: FixError,
+}
+
+// A copy of a format nothing can write tags to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnwrittenFile {
+    pub path: PathBuf,
+    pub kind: MediaKind,
+}
+
+// Collected over the whole run and printed at the end,
+// a failing file never stops the other files from being processed
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub failures: Vec<FileFailure>,
+    pub orphans: OrphanReport,
+    pub dates: DateReport,
+    pub duplicates: DuplicateReport,
+    pub extensions: ExtensionReport,
+    pub unwritten: Vec<UnwrittenFile>,
+}
+
+impl RunReport {
+    pub fn new() -> RunReport {
+        RunReport::default()
+    }
+
+    pub fn record_failure(&mut self, path: impl AsRef<Path>, error: FixError) {
+        eprintln!("Failed: {:?}: {}", path.as_ref(), error);
+        self.failures.push(FileFailure { path: path.as_ref().to_path_buf(), error });
+    }
+
+    pub fn record_unwritten(&mut self, path: impl AsRef<Path>, kind: MediaKind) {
+        self.unwritten.push(UnwrittenFile { path: path.as_ref().to_path_buf(), kind });
+    }
+
+    pub fn unwritten_summary(&self) -> String {
+        if self.unwritten.is_empty() {
+            return String::new();
+        }
+        let mut summary = format!("{} file(s) copied, no metadata written, their format has no writer:\n", self.unwritten.len());
+        for file in &self.unwritten {
+            summary.push_str(&format!("  {} ({})\n", file.path.display(), file.kind));
+        }
+        summary
+    }
+
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    pub fn summary_table(&self) -> String {
+        let rows: Vec<(String, String)> = self
+            .failures
+            .iter()
+            .map(|failure| (failure.path.display().to_string(), failure.error.to_string()))
+            .collect();
+
+        let file_width = rows.iter().map(|(file, _)| file.len()).chain(Some("FILE".len())).max().unwrap_or(0);
+
+        let mut table = format!("{:<width$}  REASON\n", "FILE", width = file_width);
+        for (file, reason) in rows {
+            table.push_str(&format!("{:<width$}  {}\n", file, reason, width = file_width));
+        }
+        table
+    }
+
+    pub fn print_summary(&self) {
+        print!("{}", self.orphans.summary());
+        print!("{}", self.dates.summary());
+        print!("{}", self.duplicates.summary());
+        print!("{}", self.extensions.summary());
+        print!("{}", self.unwritten_summary());
+
+        if self.failures.is_empty() {
+            println!("Finished, no failures");
+        } else {
+            println!("Finished with {} failed file(s):", self.failures.len());
+            print!("{}", self.summary_table());
+        }
+    }
+}
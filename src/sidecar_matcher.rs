@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::fix_stupid_google_photos_takeout_naming_bug::{strip_supplemental_metadata, swap_file_name_str_position};
+
+// Finds the media file a Takeout sidecar belongs to.
+//
+// Every directory is indexed once, then each sidecar goes through RULES in order and the first
+// rule that finds a file wins. The same index works for directories on disk and for the entry
+// names of Takeout zips, since both are just paths.
+
+// Takeout cuts sidecar names to 51 characters, ".json" included
+pub const TRUNCATED_NAME_LEN: usize = 46;
+
+// Suffix Google Photos adds to the edited copy, depending on the account language
+pub const EDITED_SUFFIXES: [&str; 6] = ["-edited", "-bearbeitet", "-modifié", "-editado", "-modificato", "-bewerkt"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchRule {
+    // IMG_0001.JPG.json -> IMG_0001.JPG
+    Exact,
+    // IMG_0001.JPG.supplemental-metadata(1).json -> IMG_0001(1).JPG, also when cut short
+    SupplementalMetadata,
+    // IMG_0001.JPG(1).json -> IMG_0001(1).JPG
+    DuplicateCounter,
+    // IMG_0001.JPG.json -> IMG_0001.jpg
+    ExtensionCase,
+    // BAAC2A4F-AF2C-44EE-B4BF-5FCB1FC0EE38-5325-0000.json -> BAAC2A4F-AF2C-44EE-B4BF-5FCB1FC0EE38-5325-00000.png
+    Truncated,
+    // IMG_0001.json -> IMG_0001.JPG
+    MissingExtension,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SidecarMatch {
+    pub media: PathBuf,
+    pub rule: MatchRule,
+    // IMG_0001-edited.JPG and the like, next to the media
+    pub edited: Vec<PathBuf>,
+}
+
+// --edited: which versions of an edited photo end up in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EditedPolicy {
+    #[default]
+    Both,
+    OriginalOnly,
+    EditedOnly,
+}
+
+impl EditedPolicy {
+    pub fn parse(name: &str) -> Result<EditedPolicy, String> {
+        match name {
+            "both" => Ok(EditedPolicy::Both),
+            "original-only" => Ok(EditedPolicy::OriginalOnly),
+            "edited-only" => Ok(EditedPolicy::EditedOnly),
+            _ => Err(format!("unknown --edited value {:?}, expected both, original-only or edited-only", name)),
+        }
+    }
+
+    // Files of the match that are left out of the output.
+    // An original without an edited copy is always kept, it is the only version there is.
+    // Works on paths as well as on Takeout zip entries.
+    pub fn skipped<'a, T>(&self, media: &'a T, edited: &'a [T]) -> Vec<&'a T> {
+        match self {
+            EditedPolicy::Both => Vec::new(),
+            EditedPolicy::OriginalOnly => edited.iter().collect(),
+            EditedPolicy::EditedOnly if edited.is_empty() => Vec::new(),
+            EditedPolicy::EditedOnly => vec![media],
+        }
+    }
+}
+
+impl SidecarMatch {
+    // The original and its edited copies, all of them get the sidecar metadata
+    pub fn versions(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.media).chain(self.edited.iter())
+    }
+}
+
+type Rule = fn(&str, &[String]) -> Option<String>;
+
+const RULES: [(MatchRule, Rule); 6] = [
+    (MatchRule::Exact, match_exact),
+    (MatchRule::SupplementalMetadata, match_supplemental_metadata),
+    (MatchRule::DuplicateCounter, match_duplicate_counter),
+    (MatchRule::ExtensionCase, match_extension_case),
+    (MatchRule::Truncated, match_truncated),
+    (MatchRule::MissingExtension, match_missing_extension),
+];
+
+#[derive(Debug, Default)]
+pub struct MediaIndex {
+    // directory -> names of the non-json files in it, sorted
+    directories: HashMap<PathBuf, Vec<String>>,
+}
+
+impl MediaIndex {
+    pub fn new<I, P>(paths: I) -> MediaIndex
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut directories: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        for path in paths {
+            let path = path.as_ref();
+            let (Some(parent), Some(file_name)) = (path.parent(), path.file_name().and_then(|name| name.to_str())) else {
+                continue;
+            };
+            if json_base(file_name).is_none() {
+                directories.entry(parent.to_path_buf()).or_default().push(file_name.to_string());
+            }
+        }
+
+        for names in directories.values_mut() {
+            names.sort();
+        }
+        MediaIndex { directories }
+    }
+
+    pub fn find_media_for_json(&self, json_path: &Path) -> Option<SidecarMatch> {
+        let json_file_name = json_path.file_name()?.to_str()?;
+        let directory = json_path.parent()?;
+        let names = self.directories.get(directory)?;
+
+        let (rule, media_name) = RULES.iter().find_map(|(rule, find)| find(json_file_name, names).map(|name| (*rule, name)))?;
+        let edited = edited_variants(&media_name, names).into_iter().map(|name| directory.join(name)).collect();
+
+        Some(SidecarMatch { media: directory.join(media_name), rule, edited })
+    }
+}
+
+// "IMG_0001.JPG.json" -> "IMG_0001.JPG"
+fn json_base(json_file_name: &str) -> Option<&str> {
+    let split = json_file_name.len().checked_sub(".json".len())?;
+    let (base, suffix) = (json_file_name.get(..split)?, json_file_name.get(split..)?);
+    (suffix.eq_ignore_ascii_case(".json") && !base.is_empty()).then_some(base)
+}
+
+// "IMG_0001(1).JPG" -> ("IMG_0001", Some("1"), Some("JPG"))
+fn split_media_name(name: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name, None),
+    };
+    match split_counter(stem) {
+        Some((stem, counter)) => (stem, Some(counter), extension),
+        None => (stem, None, extension),
+    }
+}
+
+// "IMG_0001(1)" -> ("IMG_0001", "1")
+fn split_counter(text: &str) -> Option<(&str, &str)> {
+    let (stem, counter) = text.strip_suffix(')')?.rsplit_once('(')?;
+    (!counter.is_empty() && counter.chars().all(|c| c.is_ascii_digit())).then_some((stem, counter))
+}
+
+pub fn is_edited_name(name: &str) -> bool {
+    let (stem, _, _) = split_media_name(name);
+    EDITED_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix))
+}
+
+fn find_name(names: &[String], name: &str) -> Option<String> {
+    names.binary_search_by(|candidate| candidate.as_str().cmp(name)).ok().map(|index| names[index].clone())
+}
+
+// Several candidates means the rule cannot tell them apart, the sidecar then stays unmatched
+fn single<'a>(mut candidates: impl Iterator<Item = &'a String>) -> Option<String> {
+    let first = candidates.next()?;
+    candidates.next().is_none().then(|| first.clone())
+}
+
+fn match_exact(json_file_name: &str, names: &[String]) -> Option<String> {
+    find_name(names, json_base(json_file_name)?)
+}
+
+fn match_supplemental_metadata(json_file_name: &str, names: &[String]) -> Option<String> {
+    find_name(names, &strip_supplemental_metadata(json_file_name)?)
+}
+
+fn match_duplicate_counter(json_file_name: &str, names: &[String]) -> Option<String> {
+    let swapped = swap_file_name_str_position(json_file_name);
+    if swapped == json_file_name {
+        return None;
+    }
+    find_name(names, json_base(&swapped)?)
+}
+
+fn match_extension_case(json_file_name: &str, names: &[String]) -> Option<String> {
+    let swapped = swap_file_name_str_position(json_file_name);
+    let expected = json_base(&swapped)?;
+    single(names.iter().filter(|name| name.eq_ignore_ascii_case(expected)))
+}
+
+// The sidecar name is cut somewhere in the media name, a "(N)" counter is added after the cut
+fn match_truncated(json_file_name: &str, names: &[String]) -> Option<String> {
+    let base = json_base(json_file_name)?;
+    let (prefix, counter) = match split_counter(base) {
+        Some((prefix, counter)) => (prefix, Some(counter)),
+        None => (base, None),
+    };
+    if prefix.chars().count() < TRUNCATED_NAME_LEN {
+        return None;
+    }
+    // "IMG_0001.JPG.supplemental-metadata.json" can be cut right after the media name
+    let cut_after_name = prefix.ends_with('.');
+    let prefix = prefix.trim_end_matches('.');
+
+    single(names.iter().filter(|name| {
+        let (stem, name_counter, extension) = split_media_name(name);
+        let full_name = match extension {
+            Some(extension) => format!("{}.{}", stem, extension),
+            None => stem.to_string(),
+        };
+        let longer = full_name.len() > prefix.len() || (cut_after_name && full_name.len() == prefix.len());
+        longer && full_name.starts_with(prefix) && name_counter == counter && !is_edited_name(name)
+    }))
+}
+
+fn match_missing_extension(json_file_name: &str, names: &[String]) -> Option<String> {
+    let base = json_base(json_file_name)?;
+    single(names.iter().filter(|name| {
+        matches!(name.rsplit_once('.'), Some((stem, _)) if stem == base)
+    }))
+}
+
+// IMG_0001.JPG -> IMG_0001-edited.JPG, IMG_0001(1).JPG -> IMG_0001-edited(1).JPG
+fn edited_variants(media_name: &str, names: &[String]) -> Vec<String> {
+    let (stem, counter, extension) = split_media_name(media_name);
+
+    EDITED_SUFFIXES
+        .iter()
+        .filter_map(|suffix| {
+            let counter = counter.map(|counter| format!("({})", counter)).unwrap_or_default();
+            let edited_stem = format!("{}{}{}", stem, suffix, counter);
+            names.iter().find(|name| match (name.rsplit_once('.'), extension) {
+                (Some((name_stem, name_extension)), Some(extension)) => {
+                    name_stem == edited_stem && name_extension.eq_ignore_ascii_case(extension)
+                },
+                (None, None) => **name == edited_stem,
+                _ => false,
+            })
+        })
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,119 @@
+use std::path::Path;
+use crate::error::FixError;
+use crate::log_line;
+use crate::media_kind::MediaKind;
+use crate::metadata_utils::{has_gps_tags, run_exiftool_with_args, with_detected_extension};
+use crate::takeout_sidecar::{GeoData, TakeoutSidecar};
+
+// Maps the sidecar fields other than the date onto the media file.
+// Each group of tags has its own switch, all of them are off by default. The tags are
+// written with exiftool, also for formats whose dates are written natively.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetadataOptions {
+    // GPSLatitude/GPSLongitude/GPSAltitude from geoDataExif or geoData, only for files without GPS
+    pub restore_gps: bool,
+    // ImageDescription and XMP dc:description
+    pub write_description: bool,
+    // XMP PersonInImage, one entry per people[].name
+    pub write_people: bool,
+    // XMP Rating 5 for favorited photos
+    pub write_favorites: bool,
+}
+
+impl MetadataOptions {
+    pub fn writes_sidecar_fields(&self) -> bool {
+        self.restore_gps || self.write_description || self.write_people || self.write_favorites
+    }
+}
+
+const FAVORITE_RATING: u8 = 5;
+
+fn gps_args(location: &GeoData, media_kind: MediaKind) -> Vec<String> {
+    if media_kind.is_video() {
+        // QuickTime keeps the location as a single ISO 6709 style value
+        return vec![format!("-Keys:GPSCoordinates={}, {}, {}", location.latitude, location.longitude, location.altitude)];
+    }
+
+    vec![
+        format!("-GPSLatitude={}", location.latitude.abs()),
+        format!("-GPSLatitudeRef={}", if location.latitude < 0.0 { "S" } else { "N" }),
+        format!("-GPSLongitude={}", location.longitude.abs()),
+        format!("-GPSLongitudeRef={}", if location.longitude < 0.0 { "W" } else { "E" }),
+        format!("-GPSAltitude={}", location.altitude.abs()),
+        // 0 = above sea level, 1 = below
+        format!("-GPSAltitudeRef#={}", if location.altitude < 0.0 { 1 } else { 0 }),
+    ]
+}
+
+// The exiftool arguments for every enabled option the sidecar has data for
+pub fn sidecar_exiftool_args(sidecar: &TakeoutSidecar, media_kind: MediaKind, options: &MetadataOptions) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if options.restore_gps {
+        if let Some(location) = sidecar.location() {
+            args.extend(gps_args(&location, media_kind));
+        }
+    }
+
+    if options.write_description {
+        if let Some(description) = sidecar.description() {
+            if !media_kind.is_video() {
+                args.push(format!("-EXIF:ImageDescription={}", description));
+            }
+            args.push(format!("-XMP-dc:Description={}", description));
+        }
+    }
+
+    if options.write_people {
+        for name in sidecar.people_names() {
+            args.push(format!("-XMP-iptcExt:PersonInImage+={}", name));
+        }
+    }
+
+    if options.write_favorites && sidecar.favorited {
+        args.push(format!("-XMP-xmp:Rating={}", FAVORITE_RATING));
+    }
+
+    args
+}
+
+// Dry-run description of what add_sidecar_metadata would write
+pub fn plan_sidecar_metadata(sidecar: &TakeoutSidecar, options: &MetadataOptions) -> Option<String> {
+    let mut planned = Vec::new();
+
+    if let (true, Some(location)) = (options.restore_gps, sidecar.location()) {
+        planned.push(format!("GPS {}, {} unless already present", location.latitude, location.longitude));
+    }
+    if let (true, Some(description)) = (options.write_description, sidecar.description()) {
+        planned.push(format!("description {:?}", description));
+    }
+    let people = sidecar.people_names();
+    if options.write_people && !people.is_empty() {
+        planned.push(format!("people {}", people.join(", ")));
+    }
+    if options.write_favorites && sidecar.favorited {
+        planned.push(format!("rating {}", FAVORITE_RATING));
+    }
+
+    (!planned.is_empty()).then(|| planned.join("; "))
+}
+
+pub fn add_sidecar_metadata(image_file_path: &str, sidecar: &TakeoutSidecar, options: &MetadataOptions) -> Result<(), FixError> {
+    let media_kind = MediaKind::detect_file(Path::new(image_file_path))?;
+
+    let mut options = *options;
+    if options.restore_gps && sidecar.location().is_some() && has_gps_tags(image_file_path, media_kind)? {
+        log_line!("{} already has GPS tags, keeping them", image_file_path);
+        options.restore_gps = false;
+    }
+
+    let args = sidecar_exiftool_args(sidecar, media_kind, &options);
+    if args.is_empty() {
+        return Ok(());
+    }
+
+    with_detected_extension(image_file_path, media_kind, |file_path| run_exiftool_with_args(file_path, &args))?;
+    log_line!("Sidecar tags added to {}: {}", image_file_path, args.join(" "));
+    Ok(())
+}
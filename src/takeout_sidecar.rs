@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use crate::error::FixError;
+
+// The Takeout sidecar JSON, e.g. IMG_0001.JPG.json.
+//
+// Every field is optional: sidecars from older exports, shared albums and partner sharing
+// all leave out different parts. A field of an unexpected shape is left out as if it was missing,
+// and so is a single bad entry of people, only JSON that is not an object fails the sidecar.
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TakeoutSidecar {
+    #[serde(default, deserialize_with = "lenient")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub photo_taken_time: Option<TakeoutTimestamp>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub creation_time: Option<TakeoutTimestamp>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub geo_data: Option<GeoData>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub geo_data_exif: Option<GeoData>,
+    #[serde(default, deserialize_with = "lenient_entries")]
+    pub people: Vec<Person>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub favorited: bool,
+    #[serde(default, deserialize_with = "lenient")]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub google_photos_origin: Option<Value>,
+}
+
+// The default when the value does not have the expected shape
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned + Default,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).unwrap_or_default())
+}
+
+// Only the entries that have the expected shape, e.g. a person without a name is left out
+fn lenient_entries<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    let entries: Vec<Value> = lenient(deserializer)?;
+    Ok(entries.into_iter().filter_map(|entry| serde_json::from_value(entry).ok()).collect())
+}
+
+// {"timestamp": "1435000000", "formatted": "Jun 22, 2015, 7:06:40 PM UTC"}
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct TakeoutTimestamp {
+    pub timestamp: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub formatted: Option<String>,
+}
+
+impl TakeoutTimestamp {
+    pub fn to_utc(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.timestamp.trim().parse::<i64>().ok()?, 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeoData {
+    #[serde(default)]
+    pub latitude: f64,
+    #[serde(default)]
+    pub longitude: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub altitude: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub latitude_span: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub longitude_span: f64,
+}
+
+impl GeoData {
+    // Takeout writes 0.0, 0.0 for photos without a location
+    pub fn is_placeholder(&self) -> bool {
+        self.latitude == 0.0 && self.longitude == 0.0
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Person {
+    pub name: String,
+}
+
+impl TakeoutSidecar {
+    pub fn from_reader<R: Read>(reader: R) -> Result<TakeoutSidecar, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+
+    pub fn from_path(json_file_path: &Path) -> Result<TakeoutSidecar, FixError> {
+        let file = File::open(json_file_path).map_err(|e| FixError::io(json_file_path, e))?;
+        TakeoutSidecar::from_reader(BufReader::new(file)).map_err(|e| FixError::InvalidMetadata {
+            path: json_file_path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn photo_taken_time(&self) -> Option<DateTime<Utc>> {
+        self.photo_taken_time.as_ref()?.to_utc()
+    }
+
+    // When the file was uploaded to Google Photos, not when it was taken
+    pub fn creation_time(&self) -> Option<DateTime<Utc>> {
+        self.creation_time.as_ref()?.to_utc()
+    }
+
+    // geoDataExif is what the camera recorded, geoData may have been edited or estimated by Google
+    pub fn location(&self) -> Option<GeoData> {
+        [self.geo_data_exif, self.geo_data]
+            .into_iter()
+            .flatten()
+            .find(|location| !location.is_placeholder())
+    }
+
+    // Takeout writes "" for photos without a caption
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref().map(str::trim).filter(|description| !description.is_empty())
+    }
+
+    // Fills in what this sidecar lacks from the sidecar of another copy of the same photo.
+    // Its own values win, the people of both are kept, and a favorite in either copy stays one.
+    pub fn merge(&mut self, other: &TakeoutSidecar) {
+        if self.description().is_none() {
+            self.description = other.description.clone();
+        }
+        if self.location().is_none() {
+            self.geo_data = other.geo_data;
+            self.geo_data_exif = other.geo_data_exif;
+        }
+        for person in &other.people {
+            if !self.people.contains(person) {
+                self.people.push(person.clone());
+            }
+        }
+        self.favorited |= other.favorited;
+
+        self.title = self.title.take().or_else(|| other.title.clone());
+        self.photo_taken_time = self.photo_taken_time.take().or_else(|| other.photo_taken_time.clone());
+        self.creation_time = self.creation_time.take().or_else(|| other.creation_time.clone());
+        self.url = self.url.take().or_else(|| other.url.clone());
+        self.google_photos_origin = self.google_photos_origin.take().or_else(|| other.google_photos_origin.clone());
+    }
+
+    pub fn people_names(&self) -> Vec<&str> {
+        self.people.iter().map(|person| person.name.trim()).filter(|name| !name.is_empty()).collect()
+    }
+}
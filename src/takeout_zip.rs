@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use chrono::NaiveDate;
+use filetime::{FileTime, set_file_mtime};
+use zip::read::ZipFile;
+use zip::{ExtraField, ZipArchive};
+use crate::error::FixError;
+use crate::journal;
+use crate::sidecar_matcher::MediaIndex;
+
+// Google Takeout splits big exports into takeout-*-001.zip, takeout-*-002.zip, ...
+// A sidecar json and its media file are not guaranteed to end up in the same part,
+// so every part is indexed first and the pairing is done across all of them.
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TakeoutZipEntry {
+    pub part: usize,
+    pub name: String,
+}
+
+impl TakeoutZipEntry {
+    pub fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    pub fn extension(&self) -> Option<&str> {
+        Path::new(self.file_name()).extension().and_then(|ext| ext.to_str())
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.extension().map(|ext| ext.eq_ignore_ascii_case("json")).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TakeoutZipPairing {
+    // (sidecar json, media file)
+    pub pairs: Vec<(TakeoutZipEntry, TakeoutZipEntry)>,
+    // sidecars whose media could not be found in any part
+    pub missing_media: Vec<TakeoutZipEntry>,
+    // media file -> its "-edited" copies, only for media that have some
+    pub edited: HashMap<TakeoutZipEntry, Vec<TakeoutZipEntry>>,
+}
+
+pub struct TakeoutZipSet {
+    paths: Vec<PathBuf>,
+    archives: Vec<ZipArchive<BufReader<File>>>,
+    // entry name inside the archive -> entry, first part wins
+    entries: HashMap<String, TakeoutZipEntry>,
+    // all file entries in part order, then archive order
+    ordered_entries: Vec<TakeoutZipEntry>,
+    // CRC-32 of the uncompressed data, as stored in the zip directory
+    crc32s: HashMap<TakeoutZipEntry, u32>,
+    // uncompressed size
+    sizes: HashMap<TakeoutZipEntry, u64>,
+    // entry names, indexed for pairing
+    media_index: MediaIndex,
+}
+
+impl TakeoutZipSet {
+    pub fn open<P: AsRef<Path>>(zip_paths: &[P]) -> Result<TakeoutZipSet, FixError> {
+        let mut set = TakeoutZipSet {
+            paths: Vec::new(),
+            archives: Vec::new(),
+            entries: HashMap::new(),
+            ordered_entries: Vec::new(),
+            crc32s: HashMap::new(),
+            sizes: HashMap::new(),
+            media_index: MediaIndex::default(),
+        };
+
+        for (part, zip_path) in zip_paths.iter().enumerate() {
+            let zip_path = zip_path.as_ref();
+            let file = File::open(zip_path).map_err(|e| FixError::io(zip_path, e))?;
+            let mut archive = ZipArchive::new(BufReader::new(file))
+                .map_err(|e| zip_error(zip_path, e))?;
+
+            for index in 0..archive.len() {
+                let zip_file = archive.by_index_raw(index)
+                    .map_err(|e| zip_error(zip_path, e))?;
+                if zip_file.is_dir() {
+                    continue;
+                }
+
+                let entry = TakeoutZipEntry { part, name: zip_file.name().to_string() };
+                set.entries.entry(entry.name.clone()).or_insert_with(|| entry.clone());
+                set.crc32s.insert(entry.clone(), zip_file.crc32());
+                set.sizes.insert(entry.clone(), zip_file.size());
+                set.ordered_entries.push(entry);
+            }
+
+            set.paths.push(zip_path.to_path_buf());
+            set.archives.push(archive);
+        }
+
+        set.media_index = MediaIndex::new(set.entries.keys().map(Path::new));
+        Ok(set)
+    }
+
+    pub fn part_path(&self, part: usize) -> &Path {
+        &self.paths[part]
+    }
+
+    // The failure is reported against "<zip part>/<entry name>" so it can be found again
+    pub fn entry_path(&self, entry: &TakeoutZipEntry) -> PathBuf {
+        self.paths[entry.part].join(&entry.name)
+    }
+
+    pub fn crc32(&self, entry: &TakeoutZipEntry) -> Option<u32> {
+        self.crc32s.get(entry).copied()
+    }
+
+    pub fn size(&self, entry: &TakeoutZipEntry) -> Option<u64> {
+        self.sizes.get(entry).copied()
+    }
+
+    pub fn entries(&self) -> &[TakeoutZipEntry] {
+        &self.ordered_entries
+    }
+
+    pub fn find(&self, name: &str) -> Option<&TakeoutZipEntry> {
+        self.entries.get(name)
+    }
+
+    pub fn json_entries(&self, excluded_files: &[&str]) -> Vec<TakeoutZipEntry> {
+        self.ordered_entries
+            .iter()
+            .filter(|entry| entry.is_json())
+            .filter(|entry| !excluded_files.iter().any(|&excluded| entry.file_name().contains(excluded)))
+            .cloned()
+            .collect()
+    }
+
+    // Same matcher as the directory pipeline, applied to the archive entry names
+    pub fn find_media_for_json(&self, json_entry: &TakeoutZipEntry) -> Option<&TakeoutZipEntry> {
+        self.find_media_and_edited_for_json(json_entry).map(|(media_entry, _)| media_entry)
+    }
+
+    fn find_media_and_edited_for_json(&self, json_entry: &TakeoutZipEntry) -> Option<(&TakeoutZipEntry, Vec<TakeoutZipEntry>)> {
+        let sidecar_match = self.media_index.find_media_for_json(Path::new(&json_entry.name))?;
+        let media_entry = self.find_path(&sidecar_match.media)?;
+        let edited = sidecar_match.edited.iter().filter_map(|path| self.find_path(path)).cloned().collect();
+        Some((media_entry, edited))
+    }
+
+    fn find_path(&self, path: &Path) -> Option<&TakeoutZipEntry> {
+        self.find(&path.to_str()?.replace('\\', "/"))
+    }
+
+    pub fn pair_sidecars(&self, json_entries: &[TakeoutZipEntry]) -> TakeoutZipPairing {
+        let mut pairing = TakeoutZipPairing::default();
+
+        for json_entry in json_entries {
+            match self.find_media_and_edited_for_json(json_entry) {
+                Some((media_entry, edited)) => {
+                    if !edited.is_empty() {
+                        pairing.edited.insert(media_entry.clone(), edited);
+                    }
+                    pairing.pairs.push((json_entry.clone(), media_entry.clone()));
+                },
+                None => pairing.missing_media.push(json_entry.clone()),
+            }
+        }
+
+        pairing
+    }
+
+    pub fn open_entry(&mut self, entry: &TakeoutZipEntry) -> Result<impl Read + '_, FixError> {
+        let part_path = &self.paths[entry.part];
+        self.archives[entry.part]
+            .by_name(&entry.name)
+            .map_err(|e| zip_error(part_path, e))
+    }
+
+    // The SHA-256 of the entry, the same as journal::hash_file gives once it is extracted
+    pub fn hash_entry(&mut self, entry: &TakeoutZipEntry) -> Result<String, FixError> {
+        let entry_path = self.entry_path(entry);
+        journal::hash_reader(self.open_entry(entry)?).map_err(|e| FixError::io(&entry_path, e))
+    }
+
+    // Streams the entry to dest and sets its modification time from the zip entry
+    pub fn extract_entry(&mut self, entry: &TakeoutZipEntry, dest: &Path) -> Result<(), FixError> {
+        let part_path = &self.paths[entry.part];
+        let mut zip_file = self.archives[entry.part]
+            .by_name(&entry.name)
+            .map_err(|e| zip_error(part_path, e))?;
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| FixError::io(parent, e))?;
+        }
+
+        let mut dest_file = File::create(dest).map_err(|e| FixError::io(dest, e))?;
+        io::copy(&mut zip_file, &mut dest_file).map_err(|e| FixError::io(dest, e))?;
+        drop(dest_file);
+
+        if let Some(modified) = entry_modified(&zip_file) {
+            set_file_mtime(dest, modified).map_err(|e| FixError::io(dest, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn zip_error(zip_path: &Path, error: zip::result::ZipError) -> FixError {
+    FixError::io(zip_path, io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+// The extended timestamp field holds the modification time in UTC. Without it only the DOS time is
+// left, which has no time zone. It is taken as UTC, the zone the export was made in is not known.
+fn entry_modified<R: Read>(zip_file: &ZipFile<R>) -> Option<FileTime> {
+    let extended = zip_file.extra_data_fields().find_map(|field| match field {
+        ExtraField::ExtendedTimestamp(timestamp) => timestamp.mod_time(),
+        _ => None,
+    });
+    match extended {
+        Some(seconds) => Some(FileTime::from_unix_time(seconds as i64, 0)),
+        None => zip_file.last_modified().and_then(zip_date_time_to_file_time),
+    }
+}
+
+fn zip_date_time_to_file_time(date_time: zip::DateTime) -> Option<FileTime> {
+    let naive_datetime = NaiveDate::from_ymd_opt(date_time.year() as i32, date_time.month() as u32, date_time.day() as u32)?
+        .and_hms_opt(date_time.hour() as u32, date_time.minute() as u32, date_time.second() as u32)?;
+
+    Some(FileTime::from_unix_time(naive_datetime.and_utc().timestamp(), 0))
+}
@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use tzf_rs::DefaultFinder;
+use crate::error::FixError;
+use crate::takeout_sidecar::GeoData;
+
+// Turns the UTC photoTakenTime of a sidecar into the local wall-clock time it was taken at.
+//
+// The zone comes from, in this order: the first matching line of the mapping file, the GPS
+// location (when enabled and inside a time zone boundary), the default zone, and finally UTC,
+// which is what Takeout stores.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimezoneScope {
+    // Media files under this directory, or under this path inside a Takeout zip
+    Directory(PathBuf),
+    // photoTakenTime between these UTC dates, both included
+    DateRange(NaiveDate, NaiveDate),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimezoneMapping {
+    pub scope: TimezoneScope,
+    pub zone: Tz,
+}
+
+impl TimezoneMapping {
+    pub fn matches(&self, media_path: &Path, taken: &DateTime<Utc>) -> bool {
+        match &self.scope {
+            TimezoneScope::Directory(directory) => media_path.starts_with(directory),
+            TimezoneScope::DateRange(first, last) => (*first..=*last).contains(&taken.date_naive()),
+        }
+    }
+}
+
+// One mapping per line, the zone is the last word:
+//   2019-04-01..2019-04-14  Asia/Tokyo
+//   /photos/Takeout/Google Photos/Trip to Lisbon  Europe/Lisbon
+// Blank lines and lines starting with # are ignored.
+pub fn parse_timezone_mappings(text: &str) -> Result<Vec<TimezoneMapping>, String> {
+    let mut mappings = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: String| format!("line {}: {}", index + 1, message);
+        let (scope, zone) = line
+            .rsplit_once(char::is_whitespace)
+            .ok_or_else(|| invalid(format!("expected a directory or date range followed by a time zone: {:?}", line)))?;
+        let zone = parse_timezone(zone).map_err(invalid)?;
+        let scope = scope.trim();
+
+        let scope = match parse_date_range(scope) {
+            Some(Ok((first, last))) => TimezoneScope::DateRange(first, last),
+            Some(Err(message)) => return Err(invalid(message)),
+            None => TimezoneScope::Directory(PathBuf::from(scope)),
+        };
+        mappings.push(TimezoneMapping { scope, zone });
+    }
+
+    Ok(mappings)
+}
+
+pub fn read_timezone_mappings(mapping_file: &Path) -> Result<Vec<TimezoneMapping>, FixError> {
+    let text = fs::read_to_string(mapping_file).map_err(|e| FixError::io(mapping_file, e))?;
+    parse_timezone_mappings(&text).map_err(|message| FixError::InvalidMetadata { path: mapping_file.to_path_buf(), message })
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>().map_err(|_| format!("unknown time zone {:?}, expected an Area/City name such as Asia/Tokyo", name))
+}
+
+// None when the scope is not a date range at all, so it is taken as a directory
+fn parse_date_range(scope: &str) -> Option<Result<(NaiveDate, NaiveDate), String>> {
+    let (first, last) = scope.split_once("..")?;
+    let parse = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok();
+    let (first, last) = (parse(first)?, parse(last)?);
+
+    if first > last {
+        return Some(Err(format!("date range {} ends before it starts", scope)));
+    }
+    Some(Ok((first, last)))
+}
+
+fn finder() -> &'static DefaultFinder {
+    static FINDER: OnceLock<DefaultFinder> = OnceLock::new();
+    FINDER.get_or_init(DefaultFinder::new)
+}
+
+// The zone whose boundary polygon contains the location, from the timezone-boundary-builder data
+// bundled with tzf-rs. None out at sea, where only the Etc/GMT zones of the longitude match.
+pub fn timezone_for_location(latitude: f64, longitude: f64) -> Option<Tz> {
+    let name = finder().get_tz_name(longitude, latitude);
+    if name.is_empty() || name.starts_with("Etc/") {
+        return None;
+    }
+    name.parse::<Tz>().ok()
+}
+
+fn offset_in(zone: Tz, taken: &DateTime<Utc>) -> FixedOffset {
+    taken.with_timezone(&zone).offset().fix()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TimezoneResolver {
+    // --timezone
+    pub default_zone: Option<Tz>,
+    // --timezone-map
+    pub mappings: Vec<TimezoneMapping>,
+    // --timezone-from-gps
+    pub from_gps: bool,
+}
+
+impl TimezoneResolver {
+    pub fn offset(&self, media_path: &Path, taken: &DateTime<Utc>, location: Option<&GeoData>) -> FixedOffset {
+        if let Some(mapping) = self.mappings.iter().find(|mapping| mapping.matches(media_path, taken)) {
+            return offset_in(mapping.zone, taken);
+        }
+
+        let from_location = location.filter(|_| self.from_gps).and_then(|location| timezone_for_location(location.latitude, location.longitude));
+        if let Some(zone) = from_location {
+            return offset_in(zone, taken);
+        }
+
+        match self.default_zone {
+            Some(zone) => offset_in(zone, taken),
+            None => Utc.fix(),
+        }
+    }
+
+    // Same instant, shown as the local time of the place it was taken at
+    pub fn local_time(&self, media_path: &Path, taken: &DateTime<Utc>, location: Option<&GeoData>) -> DateTime<FixedOffset> {
+        taken.with_timezone(&self.offset(media_path, taken, location))
+    }
+
+    // For dates that already are a local time, e.g. read from a file name: only the offset is added.
+    // There is no location to go by, and date ranges are compared with the local date.
+    pub fn from_local(&self, media_path: &Path, local_time: &NaiveDateTime) -> DateTime<FixedOffset> {
+        let as_utc = local_time.and_utc();
+        let zone = match self.mappings.iter().find(|mapping| mapping.matches(media_path, &as_utc)) {
+            Some(mapping) => Some(mapping.zone),
+            None => self.default_zone,
+        };
+
+        // A time skipped by a daylight saving change is taken as UTC
+        zone.and_then(|zone| zone.from_local_datetime(local_time).earliest())
+            .map(|date_time| date_time.fixed_offset())
+            .unwrap_or_else(|| as_utc.fixed_offset())
+    }
+}
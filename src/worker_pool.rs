@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use crate::log_buffer;
+
+// Runs `work` on every item with up to `jobs` threads.
+// `on_result` is called on the calling thread, strictly in item order, together with the lines
+// the item logged through log_line!. With jobs == 1 this is a plain sequential loop.
+pub fn run_ordered<T, R, W, O>(items: &[T], jobs: usize, work: W, mut on_result: O)
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&T) -> R + Sync,
+    O: FnMut(usize, R, Vec<String>),
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+
+    if jobs == 1 {
+        for (index, item) in items.iter().enumerate() {
+            let (result, lines) = log_buffer::capture(|| work(item));
+            on_result(index, result, lines);
+        }
+        return;
+    }
+
+    let next_item = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (next_item, work) = (&next_item, &work);
+            scope.spawn(move || loop {
+                let index = next_item.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let (result, lines) = log_buffer::capture(|| work(item));
+                if sender.send((index, result, lines)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Results arrive in completion order, hold them back until every earlier item is done
+        let mut pending = BTreeMap::new();
+        let mut next_to_report = 0;
+        for (index, result, lines) in receiver {
+            pending.insert(index, (result, lines));
+            while let Some((result, lines)) = pending.remove(&next_to_report) {
+                on_result(next_to_report, result, lines);
+                next_to_report += 1;
+            }
+        }
+    });
+}
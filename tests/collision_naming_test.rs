@@ -0,0 +1,80 @@
+mod common;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use common::{file_tree, fixture, run_ok, SIDECAR};
+use google_photos_fix_metadata::file_utils::{reserve_unique_path, CollisionNaming};
+
+const HASH: &str = "sha256:3f2a9c1e77d0b2aa";
+
+fn reserve(dest: &Path, naming: CollisionNaming, reserved: &mut HashSet<PathBuf>) -> PathBuf {
+    reserve_unique_path(dest, naming, Path::new("Takeout/Trip/IMG_0001.jpg"), || Some(HASH.to_string()), reserved)
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(CollisionNaming::parse("counter"), Ok(CollisionNaming::Counter));
+    assert_eq!(CollisionNaming::parse("hash"), Ok(CollisionNaming::Hash));
+    assert_eq!(CollisionNaming::parse("folder"), Ok(CollisionNaming::Folder));
+    assert!(CollisionNaming::parse("random").is_err());
+}
+
+#[test]
+fn test_reserve_unique_path() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dest = temp_dir.path().join("IMG_0001.jpg");
+    let mut reserved = HashSet::new();
+
+    assert_eq!(reserve(&dest, CollisionNaming::Counter, &mut reserved), dest);
+    assert_eq!(reserve(&dest, CollisionNaming::Counter, &mut reserved), temp_dir.path().join("IMG_0001-1.jpg"));
+    fs::write(temp_dir.path().join("IMG_0001-2.jpg"), b"").unwrap();
+    assert_eq!(reserve(&dest, CollisionNaming::Counter, &mut reserved), temp_dir.path().join("IMG_0001-3.jpg"));
+
+    assert_eq!(reserve(&dest, CollisionNaming::Hash, &mut reserved), temp_dir.path().join("IMG_0001-3f2a9c1e.jpg"));
+    assert_eq!(reserve(&dest, CollisionNaming::Hash, &mut reserved), temp_dir.path().join("IMG_0001-3f2a9c1e-1.jpg"));
+    assert_eq!(reserve(&dest, CollisionNaming::Folder, &mut reserved), temp_dir.path().join("Trip_IMG_0001.jpg"));
+    assert_eq!(reserve(&dest, CollisionNaming::Folder, &mut reserved), temp_dir.path().join("Trip_IMG_0001-1.jpg"));
+
+    // A free name is kept as it is
+    let free = temp_dir.path().join("IMG_0002.jpg");
+    assert_eq!(reserve(&free, CollisionNaming::Folder, &mut reserved), free);
+    let no_extension = temp_dir.path().join("IMG_0002");
+    reserved.insert(no_extension.clone());
+    assert_eq!(reserve(&no_extension, CollisionNaming::Counter, &mut reserved), temp_dir.path().join("IMG_0002-1"));
+}
+
+#[test]
+fn test_same_output_on_every_run() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let takeout = temp_dir.path().join("Takeout");
+    for (folder, fixture_name) in [("Photos from 2015", "no_exif.jpg"), ("Trip", "with_exif.jpg"), ("Family", "no_exif.jpg")] {
+        fs::create_dir_all(takeout.join(folder)).unwrap();
+        let mut photo = fixture(fixture_name);
+        // Different content under the same name
+        photo.extend_from_slice(folder.as_bytes());
+        fs::write(takeout.join(folder).join("IMG_0001.jpg"), photo).unwrap();
+        fs::write(takeout.join(folder).join("IMG_0001.jpg.json"), SIDECAR).unwrap();
+    }
+
+    for naming in ["counter", "hash", "folder"] {
+        let args = ["--src-google-photos", "Takeout", "--collision-naming", naming, "-j", "4"];
+        run_ok(&args, temp_dir.path());
+        let first = file_tree(&temp_dir.path().join("output"));
+        fs::remove_dir_all(temp_dir.path().join("output")).unwrap();
+        run_ok(&args, temp_dir.path());
+        let second = file_tree(&temp_dir.path().join("output"));
+        fs::remove_dir_all(temp_dir.path().join("output")).unwrap();
+
+        assert_eq!(first.len(), 3, "{:?}", first.keys());
+        assert_eq!(first, second, "{}", naming);
+        if naming == "counter" {
+            assert!(first.contains_key(Path::new("IMG_0001-1.jpg")));
+            assert!(first.contains_key(Path::new("IMG_0001-2.jpg")));
+        }
+        if naming == "folder" {
+            assert!(first.contains_key(Path::new("Photos from 2015_IMG_0001.jpg")));
+            assert!(first.contains_key(Path::new("Trip_IMG_0001.jpg")));
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Helpers of the tests that run the tool on a Takeout made up in a temporary directory.
+// Every test file uses only some of them.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+use google_photos_fix_metadata::journal::JOURNAL_FILE_NAME;
+
+// Taken 2015-06-22 19:06:40 UTC
+pub const SIDECAR: &str = r#"{"photoTakenTime": {"timestamp": "1435000000"}}"#;
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+pub fn fixture(name: &str) -> Vec<u8> {
+    fs::read(fixture_path(name)).unwrap()
+}
+
+pub fn copy_fixture(name: &str, dir: &Path) -> PathBuf {
+    let dest = dir.join(name);
+    fs::copy(fixture_path(name), &dest).unwrap();
+    dest
+}
+
+pub fn run(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_google_photos_fix_metadata")).current_dir(dir).args(args).output().unwrap()
+}
+
+// Returns the stdout of a run that has to succeed
+pub fn run_ok(args: &[&str], dir: &Path) -> String {
+    let output = run(args, dir);
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    stdout
+}
+
+// The names in dir, sorted, hidden files such as the journal left out
+pub fn file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+    names
+}
+
+// Every file under dir with its content, relative to dir.
+// The journal is left out, with several jobs its lines come in the order the workers finish.
+pub fn file_tree(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+    WalkDir::new(dir)
+        .into_iter()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() != JOURNAL_FILE_NAME)
+        .map(|entry| (entry.path().strip_prefix(dir).unwrap().to_path_buf(), fs::read(entry.path()).unwrap()))
+        .collect()
+}
+
+pub fn write_zip(zip_path: &Path, entries: &[(&str, &[u8])]) {
+    let mut writer = ZipWriter::new(File::create(zip_path).unwrap());
+    for (name, contents) in entries {
+        writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    writer.finish().unwrap();
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::fs;
+use common::{fixture_path, run, run_ok, SIDECAR};
+use chrono::DateTime;
+use google_photos_fix_metadata::date_report::DateReport;
+use google_photos_fix_metadata::date_sources::{DateSource, ResolvedDate};
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+
+#[test]
+fn test_summary_and_json() {
+    let mut dates = DateReport::default();
+    let date_time = |text: &str| DateTime::parse_from_rfc3339(text).unwrap();
+
+    dates.record("Photos from 2015/IMG_0001.JPG", &ResolvedDate {
+        source: DateSource::PhotoTakenTime,
+        date_time: date_time("2015-06-22T19:06:40+00:00"),
+        disagreements: Vec::new(),
+    });
+    dates.record("Photos from 2015/IMG_20150101_120000.jpg", &ResolvedDate {
+        source: DateSource::PhotoTakenTime,
+        date_time: date_time("2015-06-22T19:06:40+00:00"),
+        disagreements: vec![(DateSource::FileName, date_time("2015-01-01T12:00:00+00:00"))],
+    });
+    assert_eq!(dates.disagreements().count(), 1);
+
+    assert_eq!(
+        dates.summary(),
+        concat!(
+            "1 file(s) with disagreeing dates:\n",
+            "  Photos from 2015/IMG_20150101_120000.jpg  2015:06:22 19:06:40.000+00:00 from photo-taken-time\n",
+            "    but 2015:01:01 12:00:00.000+00:00 from filename\n",
+        )
+    );
+
+    let json = serde_json::to_value(&dates).unwrap();
+    assert_eq!(json["files"][0]["source"], "photo-taken-time");
+    assert_eq!(json["files"][0]["date_time"], "2015:06:22 19:06:40.000+00:00");
+    assert_eq!(json["files"][1]["disagreements"][0]["source"], "filename");
+
+    assert_eq!(DateReport::default().summary(), "");
+}
+
+#[test]
+fn test_date_priority_and_report() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2019");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    let fixture = fixture_path("no_exif.jpg");
+    fs::copy(&fixture, src_dir.join("IMG_20190704_183012.jpg")).unwrap();
+    fs::copy(&fixture, src_dir.join("IMG_0001.jpg")).unwrap();
+    // 2015-06-22 19:06:40 UTC, far from the date in the file name
+    fs::write(src_dir.join("IMG_20190704_183012.jpg.json"), SIDECAR).unwrap();
+
+    let args = [
+        "--src-google-photos",
+        "Takeout",
+        "--date-priority",
+        "filename,photo-taken-time,folder",
+        "--flag-date-disagreement",
+        "1d",
+        "--date-report",
+        "dates.json",
+    ];
+    let stdout = run_ok(&args, temp_dir.path());
+    assert!(stdout.contains("1 file(s) with disagreeing dates:"), "{}", stdout);
+
+    // The file name comes before the sidecar
+    let from_name = read_jpeg_date_tags(&temp_dir.path().join("output/IMG_20190704_183012.jpg")).unwrap();
+    assert_eq!(from_name.date_time_original.as_deref(), Some("2019:07:04 18:30:12"));
+    let from_folder = read_jpeg_date_tags(&temp_dir.path().join("output/IMG_0001.jpg")).unwrap();
+    assert_eq!(from_folder.date_time_original.as_deref(), Some("2019:01:01 00:00:00"));
+
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_dir.path().join("dates.json")).unwrap()).unwrap();
+    let files = json["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    let named = files.iter().find(|file| file["path"].as_str().unwrap().ends_with("IMG_20190704_183012.jpg")).unwrap();
+    assert_eq!(named["source"], "filename");
+    assert_eq!(named["disagreements"][0]["source"], "photo-taken-time");
+    assert_eq!(named["disagreements"][1]["source"], "folder");
+}
+
+#[test]
+fn test_date_priority_conflicts_with_fallback() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let output = run(&["--date-priority", "exif", "--no-sidecar-fallback", "folder"], temp_dir.path());
+    assert!(!output.status.success());
+
+    let output = run(&["--date-priority", "exif", "--flag-date-disagreement", "soon"], temp_dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid date source settings"));
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+use chrono::{Duration, NaiveDateTime};
+use google_photos_fix_metadata::date_sources::{date_from_folder, parse_date_sources, parse_duration, DateInputs, DateResolver, DateSource};
+use google_photos_fix_metadata::takeout_sidecar::TakeoutSidecar;
+use google_photos_fix_metadata::timezone::{parse_timezone, TimezoneResolver};
+
+fn date_time(text: &str) -> Option<NaiveDateTime> {
+    Some(NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap())
+}
+
+#[test]
+fn test_parse_date_sources() {
+    assert_eq!(parse_date_sources("exif,filename,folder"), Ok(vec![DateSource::Exif, DateSource::FileName, DateSource::Folder]));
+    assert_eq!(parse_date_sources("folder, exif"), Ok(vec![DateSource::Folder, DateSource::Exif]));
+    assert_eq!(
+        parse_date_sources("quicktime,photo-taken-time,creation-time,mtime,mtime"),
+        Ok(vec![DateSource::QuickTime, DateSource::PhotoTakenTime, DateSource::CreationTime, DateSource::Mtime])
+    );
+    assert_eq!(parse_date_sources("none"), Ok(vec![]));
+    assert!(parse_date_sources("ctime").is_err());
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("90s"), Ok(Duration::seconds(90)));
+    assert_eq!(parse_duration("30m"), Ok(Duration::minutes(30)));
+    assert_eq!(parse_duration("12h"), Ok(Duration::hours(12)));
+    assert_eq!(parse_duration("2d"), Ok(Duration::days(2)));
+    assert!(parse_duration("12").is_err());
+    assert!(parse_duration("h").is_err());
+    assert!(parse_duration("-1h").is_err());
+    assert!(parse_duration("1w").is_err());
+}
+
+#[test]
+fn test_date_from_folder() {
+    assert_eq!(date_from_folder(Path::new("Takeout/Google Photos/Photos from 2019/IMG_1234.JPG")), date_time("2019-01-01 00:00:00"));
+    assert_eq!(date_from_folder(Path::new("Takeout/Google Photos/Photos from 2019/sub/IMG_1234.JPG")), date_time("2019-01-01 00:00:00"));
+    assert_eq!(date_from_folder(Path::new("Takeout/Google Photos/Trip/IMG_1234.JPG")), None);
+    assert_eq!(date_from_folder(Path::new("Photos from 2019")), None);
+}
+
+#[test]
+fn test_priority_order() {
+    let media_path = Path::new("Takeout/Google Photos/Photos from 2019/IMG_20190704_183012.jpg");
+    let inputs = DateInputs { media_path, file_path: media_path, sidecar: None };
+    let timezones = TimezoneResolver { default_zone: Some(parse_timezone("Europe/Paris").unwrap()), ..TimezoneResolver::default() };
+
+    // The file does not exist, so there is no EXIF date or modification time, and there is no sidecar
+    let dates = DateResolver {
+        priority: vec![DateSource::Exif, DateSource::Mtime, DateSource::PhotoTakenTime, DateSource::FileName, DateSource::Folder],
+        ..DateResolver::default()
+    };
+    let resolved = dates.resolve(&inputs, &timezones).unwrap();
+    assert_eq!(resolved.source, DateSource::FileName);
+    assert_eq!(resolved.date_time.to_rfc3339(), "2019-07-04T18:30:12+02:00");
+    assert!(resolved.disagreements.is_empty());
+
+    let dates = DateResolver { priority: vec![DateSource::Folder], ..DateResolver::default() };
+    let resolved = dates.resolve(&inputs, &TimezoneResolver::default()).unwrap();
+    assert_eq!(resolved.source, DateSource::Folder);
+    assert_eq!(resolved.date_time.to_rfc3339(), "2019-01-01T00:00:00+00:00");
+
+    assert!(!DateResolver::default().works_without_sidecar());
+    assert_eq!(DateResolver::default().resolve(&inputs, &timezones), None);
+}
+
+#[test]
+fn test_sidecar_sources_and_disagreements() {
+    let media_path = Path::new("Photos from 2019/IMG_20190704_183012.jpg");
+    // photoTakenTime 2019-07-04 18:30:12 UTC, uploaded three days later
+    let sidecar = TakeoutSidecar::from_reader(
+        r#"{"photoTakenTime": {"timestamp": "1562265012"}, "creationTime": {"timestamp": "1562524212"}}"#.as_bytes(),
+    )
+    .unwrap();
+    let inputs = DateInputs { media_path, file_path: media_path, sidecar: Some(&sidecar) };
+    let timezones = TimezoneResolver::default();
+
+    let dates = DateResolver {
+        priority: vec![DateSource::PhotoTakenTime, DateSource::FileName, DateSource::CreationTime, DateSource::Folder],
+        disagreement_threshold: Some(Duration::hours(12)),
+        ..DateResolver::default()
+    };
+    assert!(dates.works_without_sidecar());
+    let resolved = dates.resolve(&inputs, &timezones).unwrap();
+    assert_eq!(resolved.source, DateSource::PhotoTakenTime);
+    assert_eq!(resolved.date_time.to_rfc3339(), "2019-07-04T18:30:12+00:00");
+    // The file name agrees, the upload time and the folder do not
+    let disagreeing: Vec<DateSource> = resolved.disagreements.iter().map(|(source, _)| *source).collect();
+    assert_eq!(disagreeing, vec![DateSource::CreationTime, DateSource::Folder]);
+    assert_eq!(resolved.disagreements[0].1.to_rfc3339(), "2019-07-07T18:30:12+00:00");
+
+    // Without a threshold only the winner is read
+    let dates = DateResolver { disagreement_threshold: None, ..dates };
+    assert!(dates.resolve(&inputs, &timezones).unwrap().disagreements.is_empty());
+
+    let dates = DateResolver { priority: vec![DateSource::CreationTime], ..DateResolver::default() };
+    assert_eq!(dates.resolve(&inputs, &timezones).unwrap().date_time.to_rfc3339(), "2019-07-07T18:30:12+00:00");
+}
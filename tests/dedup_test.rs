@@ -0,0 +1,106 @@
+mod common;
+
+use std::fs;
+use common::{file_names, fixture, run_ok, write_zip, SIDECAR};
+use google_photos_fix_metadata::dedup::{place_duplicates, shares_key, DuplicateReport};
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::output_layout::{place_album_copies, AlbumCopies, AlbumCopy, AlbumPlacement};
+
+#[test]
+fn test_shares_key() {
+    assert_eq!(shares_key(&[3, 5, 3, 7]), [true, false, true, false]);
+    assert!(shares_key::<u64>(&[]).is_empty());
+}
+
+#[test]
+fn test_place_duplicates() {
+    let copy = |key: Option<&str>, album: Option<&str>| AlbumCopy { key: key.map(str::to_string), album: album.map(str::to_string) };
+    let copies = [copy(Some("a"), None), copy(Some("a"), None), copy(Some("b"), Some("Trip")), copy(Some("b"), None), copy(None, None), copy(None, None)];
+
+    let mut placements = place_album_copies(&copies, AlbumCopies::Hardlink);
+    place_duplicates(&copies, &mut placements);
+    assert_eq!(
+        placements,
+        [
+            AlbumPlacement::Own,
+            AlbumPlacement::SameAs(0),
+            AlbumPlacement::Own,
+            AlbumPlacement::SameAs(2),
+            AlbumPlacement::Own,
+            AlbumPlacement::Own,
+        ]
+    );
+}
+
+#[test]
+fn test_duplicate_report() {
+    let mut report = DuplicateReport::default();
+    assert_eq!(report.summary(), "");
+
+    report.record("Trip/IMG_0001.jpg", "Photos from 2015/IMG_0001.jpg", 1000);
+    report.record("Family/IMG_0001.jpg", "Photos from 2015/IMG_0001.jpg", 1000);
+    assert_eq!(report.bytes_saved(), 2000);
+    assert_eq!(report.summary(), "2 duplicate file(s) not copied again, 2000 bytes saved\n");
+}
+
+#[test]
+fn test_dedup_directories() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let takeout = temp_dir.path().join("Takeout");
+    fs::create_dir_all(takeout.join("Photos from 2015")).unwrap();
+    fs::create_dir_all(takeout.join("Trip")).unwrap();
+    let photo = fixture("no_exif.jpg");
+    // Only one copy has a sidecar, the copy kept gets its date whichever it is
+    fs::write(takeout.join("Photos from 2015/IMG_0001.jpg"), &photo).unwrap();
+    fs::write(takeout.join("Trip/IMG_0001(1).jpg"), &photo).unwrap();
+    fs::write(takeout.join("Trip/IMG_0001(1).jpg.json"), SIDECAR).unwrap();
+    fs::write(takeout.join("Trip/other.jpg"), fixture("with_exif.jpg")).unwrap();
+
+    let stdout = run_ok(&["--src-google-photos", "Takeout", "--dedup"], temp_dir.path());
+    assert!(stdout.contains(&format!("1 duplicate file(s) not copied again, {} bytes saved", photo.len())), "{}", stdout);
+
+    let files = file_names(&temp_dir.path().join("output"));
+    assert_eq!(files.len(), 2, "{:?}", files);
+    let kept = files.iter().find(|name| *name != "other.jpg").unwrap();
+    assert_eq!(read_jpeg_date_tags(&temp_dir.path().join("output").join(kept)).unwrap().date_time_original.as_deref(), Some("2015:06:22 19:06:40"));
+
+    // Without --dedup both copies are kept
+    fs::remove_dir_all(temp_dir.path().join("output")).unwrap();
+    let stdout = run_ok(&["--src-google-photos", "Takeout"], temp_dir.path());
+    assert!(!stdout.contains("duplicate file(s)"), "{}", stdout);
+    assert_eq!(file_names(&temp_dir.path().join("output")).len(), 3);
+}
+
+#[test]
+fn test_dedup_takeout_zip_parts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let photo = fixture("no_exif.jpg");
+    write_zip(&temp_dir.path().join("takeout-001.zip"), &[("Takeout/Google Photos/Photos from 2015/IMG_0001.jpg", &photo)]);
+    write_zip(
+        &temp_dir.path().join("takeout-002.zip"),
+        &[("Takeout/Google Photos/Trip/IMG_0001.jpg", &photo), ("Takeout/Google Photos/Trip/IMG_0001.jpg.json", SIDECAR.as_bytes())],
+    );
+
+    let stdout = run_ok(&["--src-takeout-zip", "takeout-001.zip", "--src-takeout-zip", "takeout-002.zip", "--dedup"], temp_dir.path());
+    assert!(stdout.contains("Skipping"), "{}", stdout);
+    assert!(stdout.contains(&format!("1 duplicate file(s) not copied again, {} bytes saved", photo.len())), "{}", stdout);
+
+    assert_eq!(file_names(&temp_dir.path().join("output")), ["IMG_0001.jpg"]);
+    // The copy kept is the one in the first part, with the sidecar of the second
+    assert_eq!(read_jpeg_date_tags(&temp_dir.path().join("output/IMG_0001.jpg")).unwrap().date_time_original.as_deref(), Some("2015:06:22 19:06:40"));
+}
+
+#[test]
+fn test_dedup_takeout_zip_same_crc32() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    // Same size and crc32, different content
+    write_zip(
+        &temp_dir.path().join("takeout-001.zip"),
+        &[("Takeout/Google Photos/Trip/a.jpg", b"plumless"), ("Takeout/Google Photos/Trip/b.jpg", b"buckeroo")],
+    );
+
+    let stdout = run_ok(&["--src-takeout-zip", "takeout-001.zip", "--dedup"], temp_dir.path());
+    assert!(!stdout.contains("duplicate file(s)"), "{}", stdout);
+    assert_eq!(fs::read(temp_dir.path().join("output/a.jpg")).unwrap(), b"plumless");
+    assert_eq!(fs::read(temp_dir.path().join("output/b.jpg")).unwrap(), b"buckeroo");
+}
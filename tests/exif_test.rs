@@ -0,0 +1,128 @@
+mod common;
+
+use std::fs;
+use common::{copy_fixture, fixture_path};
+use google_photos_fix_metadata::exif::{
+    has_gps_coordinates, jpeg_has_gps_coordinates, read_ascii_tag, read_jpeg_date_tags, read_jpeg_exif, set_exif_ascii_tags,
+    write_jpeg_date_tags, ExifDates, Ifd, TAG_CREATE_DATE, TAG_DATE_TIME_ORIGINAL, TAG_GPS_IFD_POINTER, TAG_GPS_LATITUDE,
+    TAG_OFFSET_TIME_ORIGINAL,
+};
+
+fn date_tags() -> Vec<(u16, String)> {
+    vec![
+        (TAG_DATE_TIME_ORIGINAL, "2015:06:22 19:06:40".to_string()),
+        (TAG_CREATE_DATE, "2015:06:22 19:06:40".to_string()),
+        (TAG_OFFSET_TIME_ORIGINAL, "+09:00".to_string()),
+    ]
+}
+
+fn written_dates() -> ExifDates {
+    ExifDates {
+        date_time_original: Some("2015:06:22 19:06:40".to_string()),
+        create_date: Some("2015:06:22 19:06:40".to_string()),
+        offset_time_original: Some("+09:00".to_string()),
+    }
+}
+
+// Everything from the start of scan marker on is the compressed image
+fn image_data(jpeg: &[u8]) -> &[u8] {
+    let sos = jpeg.windows(2).position(|marker| marker == [0xFF, 0xDA]).unwrap();
+    &jpeg[sos..]
+}
+
+#[test]
+fn test_read_dates() {
+    assert_eq!(read_jpeg_date_tags(&fixture_path("no_exif.jpg")).unwrap(), ExifDates::default());
+    assert_eq!(read_jpeg_date_tags(&fixture_path("exif_without_dates.jpg")).unwrap(), ExifDates::default());
+
+    let dates = read_jpeg_date_tags(&fixture_path("with_exif.jpg")).unwrap();
+    assert_eq!(dates.date_time_original, Some("2010:01:02 03:04:05".to_string()));
+    assert_eq!(dates.create_date, None);
+}
+
+#[test]
+fn test_write_dates_without_exif() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let jpeg_path = copy_fixture("no_exif.jpg", temp_dir.path());
+
+    write_jpeg_date_tags(&jpeg_path, &date_tags()).unwrap();
+    assert_eq!(read_jpeg_date_tags(&jpeg_path).unwrap(), written_dates());
+
+    let original = fs::read(fixture_path("no_exif.jpg")).unwrap();
+    let written = fs::read(&jpeg_path).unwrap();
+    // The JFIF APP0 segment stays first, the new APP1 segment follows it
+    assert_eq!(written[..20], original[..20]);
+    assert_eq!(written[20..22], [0xFF, 0xE1]);
+    assert_eq!(image_data(&written), image_data(&original));
+}
+
+#[test]
+fn test_write_dates_keeps_existing_tags() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let jpeg_path = copy_fixture("exif_without_dates.jpg", temp_dir.path());
+
+    write_jpeg_date_tags(&jpeg_path, &date_tags()).unwrap();
+    assert_eq!(read_jpeg_date_tags(&jpeg_path).unwrap(), written_dates());
+
+    let written = fs::read(&jpeg_path).unwrap();
+    let tiff = read_jpeg_exif(&written).unwrap().unwrap();
+    assert_eq!(read_ascii_tag(tiff, Ifd::Primary, 0x010F).unwrap(), Some("Apple".to_string()));
+    assert_eq!(read_ascii_tag(tiff, Ifd::Primary, 0x0110).unwrap(), Some("iPhone 4".to_string()));
+    assert_eq!(image_data(&written), image_data(&fs::read(fixture_path("exif_without_dates.jpg")).unwrap()));
+}
+
+#[test]
+fn test_replace_dates_in_existing_exif_ifd() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let jpeg_path = copy_fixture("with_exif.jpg", temp_dir.path());
+
+    write_jpeg_date_tags(&jpeg_path, &date_tags()).unwrap();
+    assert_eq!(read_jpeg_date_tags(&jpeg_path).unwrap(), written_dates());
+
+    let written = fs::read(&jpeg_path).unwrap();
+    let tiff = read_jpeg_exif(&written).unwrap().unwrap();
+    assert_eq!(read_ascii_tag(tiff, Ifd::Primary, 0x010F).unwrap(), Some("Canon".to_string()));
+}
+
+#[test]
+fn test_new_tiff_block() {
+    let tiff = set_exif_ascii_tags(None, &date_tags()).unwrap();
+    assert_eq!(read_ascii_tag(&tiff, Ifd::Exif, TAG_DATE_TIME_ORIGINAL).unwrap(), Some("2015:06:22 19:06:40".to_string()));
+    assert_eq!(read_ascii_tag(&tiff, Ifd::Exif, TAG_OFFSET_TIME_ORIGINAL).unwrap(), Some("+09:00".to_string()));
+    assert_eq!(read_ascii_tag(&tiff, Ifd::Primary, TAG_DATE_TIME_ORIGINAL).unwrap(), None);
+}
+
+#[test]
+fn test_not_a_jpeg() {
+    assert!(read_jpeg_exif(b"\x89PNG\r\n\x1a\n").is_err());
+}
+
+// Little endian TIFF whose IFD0 points to a GPS IFD with the given tags (values left zero)
+fn tiff_with_gps_ifd(gps_tags: &[u16]) -> Vec<u8> {
+    let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+    tiff.extend_from_slice(&1u16.to_le_bytes());
+    tiff.extend_from_slice(&TAG_GPS_IFD_POINTER.to_le_bytes());
+    tiff.extend_from_slice(&4u16.to_le_bytes());
+    tiff.extend_from_slice(&1u32.to_le_bytes());
+    tiff.extend_from_slice(&26u32.to_le_bytes());
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+
+    tiff.extend_from_slice(&(gps_tags.len() as u16).to_le_bytes());
+    for tag in gps_tags {
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&[1, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff
+}
+
+#[test]
+fn test_has_gps_coordinates() {
+    assert!(has_gps_coordinates(&tiff_with_gps_ifd(&[0x0000, TAG_GPS_LATITUDE])).unwrap());
+    // GPSVersionID only
+    assert!(!has_gps_coordinates(&tiff_with_gps_ifd(&[0x0000])).unwrap());
+    assert!(!has_gps_coordinates(&set_exif_ascii_tags(None, &date_tags()).unwrap()).unwrap());
+
+    assert!(!jpeg_has_gps_coordinates(&fixture_path("no_exif.jpg")).unwrap());
+    assert!(!jpeg_has_gps_coordinates(&fixture_path("with_exif.jpg")).unwrap());
+}
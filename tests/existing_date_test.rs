@@ -0,0 +1,105 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use common::{copy_fixture, run, run_ok, SIDECAR};
+use chrono::{DateTime, Duration, Utc};
+use google_photos_fix_metadata::exif::{read_jpeg_date_tags, write_jpeg_date_tags, TAG_CREATE_DATE, TAG_DATE_TIME_ORIGINAL};
+use google_photos_fix_metadata::existing_date::{is_implausible, ExistingDatePolicy};
+use google_photos_fix_metadata::metadata_utils::{add_metadata_wrapper, plan_metadata_wrapper};
+
+const VALUE: &str = "2015:06:22 19:06:40.000+00:00";
+
+fn date_time_original(path: &Path) -> Option<String> {
+    read_jpeg_date_tags(path).unwrap().date_time_original
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(ExistingDatePolicy::parse("keep"), Ok(ExistingDatePolicy::Keep));
+    assert_eq!(ExistingDatePolicy::parse("overwrite"), Ok(ExistingDatePolicy::Overwrite));
+    assert_eq!(ExistingDatePolicy::parse("overwrite-if-implausible"), Ok(ExistingDatePolicy::OverwriteIfImplausible));
+    assert_eq!(ExistingDatePolicy::parse("overwrite-if-differs-by=12h"), Ok(ExistingDatePolicy::OverwriteIfDiffersBy(Duration::hours(12))));
+    assert!(ExistingDatePolicy::parse("overwrite-if-differs-by=soon").is_err());
+    assert!(ExistingDatePolicy::parse("replace").is_err());
+}
+
+#[test]
+fn test_overwrites() {
+    let date_time = |text: &str| DateTime::parse_from_rfc3339(text).unwrap();
+    let resolved = date_time("2015-06-22T19:06:40+00:00");
+    let close = date_time("2015-06-22T21:06:40+00:00");
+    let reset = date_time("2000-01-01T00:12:00+00:00");
+
+    assert!(!ExistingDatePolicy::Keep.overwrites(Some(&reset), &resolved));
+    assert!(!ExistingDatePolicy::Keep.overwrites(None, &resolved));
+    assert!(ExistingDatePolicy::Overwrite.overwrites(Some(&close), &resolved));
+
+    assert!(ExistingDatePolicy::OverwriteIfImplausible.overwrites(Some(&reset), &resolved));
+    assert!(!ExistingDatePolicy::OverwriteIfImplausible.overwrites(Some(&close), &resolved));
+    // An unparsable date, e.g. "0000:00:00 00:00:00"
+    assert!(ExistingDatePolicy::OverwriteIfImplausible.overwrites(None, &resolved));
+
+    let differs_by = ExistingDatePolicy::OverwriteIfDiffersBy(Duration::hours(1));
+    assert!(differs_by.overwrites(Some(&close), &resolved));
+    assert!(!ExistingDatePolicy::OverwriteIfDiffersBy(Duration::hours(2)).overwrites(Some(&close), &resolved));
+}
+
+#[test]
+fn test_is_implausible() {
+    let now = Utc::now();
+    let date_time = |text: &str| DateTime::parse_from_rfc3339(text).unwrap();
+
+    assert!(is_implausible(&date_time("1970-01-01T00:00:00+00:00"), &now));
+    assert!(is_implausible(&date_time("2000-01-01T13:45:00+09:00"), &now));
+    assert!(is_implausible(&(now + Duration::days(30)).fixed_offset(), &now));
+    assert!(!is_implausible(&date_time("2000-01-02T13:45:00+09:00"), &now));
+    assert!(!is_implausible(&now.fixed_offset(), &now));
+}
+
+#[test]
+fn test_policies_on_jpeg() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    // DateTimeOriginal 2010:01:02 03:04:05
+    let image = copy_fixture("with_exif.jpg", temp_dir.path());
+    let image_str = image.to_str().unwrap();
+
+    add_metadata_wrapper(image_str, VALUE, ExistingDatePolicy::Keep).unwrap();
+    add_metadata_wrapper(image_str, VALUE, ExistingDatePolicy::OverwriteIfImplausible).unwrap();
+    add_metadata_wrapper(image_str, "2010:01:02 09:04:05.000+00:00", ExistingDatePolicy::OverwriteIfDiffersBy(Duration::days(1))).unwrap();
+    assert_eq!(date_time_original(&image).as_deref(), Some("2010:01:02 03:04:05"));
+
+    assert!(plan_metadata_wrapper(image_str, VALUE, ExistingDatePolicy::Overwrite)
+        .starts_with("replace DateTimeOriginal/CreateDate 2010:01:02 03:04:05 with 2015:06:22 19:06:40.000+00:00"));
+    add_metadata_wrapper(image_str, VALUE, ExistingDatePolicy::OverwriteIfDiffersBy(Duration::days(1))).unwrap();
+    assert_eq!(date_time_original(&image).as_deref(), Some("2015:06:22 19:06:40"));
+
+    // A camera clock reset
+    let reset = copy_fixture("no_exif.jpg", temp_dir.path());
+    let reset_tags = [(TAG_DATE_TIME_ORIGINAL, "2000:01:01 00:03:10".to_string()), (TAG_CREATE_DATE, "2000:01:01 00:03:10".to_string())];
+    write_jpeg_date_tags(&reset, &reset_tags).unwrap();
+    let reset_str = reset.to_str().unwrap();
+
+    assert!(plan_metadata_wrapper(reset_str, VALUE, ExistingDatePolicy::Keep).starts_with("skip, DateTimeOriginal/CreateDate already present"));
+    add_metadata_wrapper(reset_str, VALUE, ExistingDatePolicy::OverwriteIfImplausible).unwrap();
+    let dates = read_jpeg_date_tags(&reset).unwrap();
+    assert_eq!(dates.date_time_original.as_deref(), Some("2015:06:22 19:06:40"));
+    assert_eq!(dates.offset_time_original.as_deref(), Some("+00:00"));
+}
+
+#[test]
+fn test_on_existing_flag() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout");
+    fs::create_dir_all(&src_dir).unwrap();
+    copy_fixture("with_exif.jpg", &src_dir);
+    fs::write(src_dir.join("with_exif.jpg.json"), SIDECAR).unwrap();
+
+    let stdout = run_ok(&["--src-google-photos", "Takeout", "--on-existing", "overwrite"], temp_dir.path());
+    assert!(stdout.contains("Replacing date 2010:01:02 03:04:05"), "{}", stdout);
+    assert_eq!(date_time_original(&temp_dir.path().join("output/with_exif.jpg")).as_deref(), Some("2015:06:22 19:06:40"));
+
+    let output = run(&["--src-google-photos", "Takeout", "--on-existing", "overwrite-if-differs-by=1y"], temp_dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid --on-existing setting"));
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use common::{file_names, fixture, run_ok, write_zip, SIDECAR};
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::extension_fix::{fix_file_names, fixed_file_name, ExtensionReport};
+use google_photos_fix_metadata::media_kind::MediaKind;
+
+#[test]
+fn test_fixed_file_name() {
+    assert_eq!(fixed_file_name("IMG_0328.JPG", MediaKind::Png).as_deref(), Some("IMG_0328.PNG"));
+    assert_eq!(fixed_file_name("IMG_0328.heic", MediaKind::Jpeg).as_deref(), Some("IMG_0328.jpg"));
+    assert_eq!(fixed_file_name("clip.Mp4", MediaKind::Mov).as_deref(), Some("clip.mov"));
+    assert_eq!(fixed_file_name("IMG_0328.jpeg", MediaKind::Jpeg), None);
+    assert_eq!(fixed_file_name("IMG_0328.AAE", MediaKind::Unknown), None);
+    assert_eq!(fixed_file_name(".jpg", MediaKind::Png), None);
+}
+
+#[test]
+fn test_fix_file_names() {
+    let paths = [
+        Path::new("Trip/IMG_0001.HEIC"),
+        Path::new("Trip/IMG_0001.HEIC.xmp"),
+        Path::new("Trip/IMG_0001.MOV"),
+        Path::new("Trip/IMG_0002.JPG"),
+        Path::new("Trip/IMG_0003.JPG"),
+        Path::new("Trip/IMG_0003.GIF"),
+    ];
+    let kinds = [MediaKind::Jpeg, MediaKind::Unknown, MediaKind::Mov, MediaKind::Png, MediaKind::Png, MediaKind::Png];
+    let taken = |path: &Path| path == Path::new("Trip/IMG_0002.PNG");
+
+    let names = fix_file_names(&paths, &kinds, taken);
+    assert_eq!(
+        names,
+        [Some("IMG_0001.JPG".to_string()), Some("IMG_0001.JPG.xmp".to_string()), None, None, Some("IMG_0003.PNG".to_string()), None]
+    );
+}
+
+#[test]
+fn test_report() {
+    let mut report = ExtensionReport::default();
+    assert_eq!(report.summary(), "");
+
+    report.record("Takeout/IMG_0328.JPG", "output/IMG_0328.PNG");
+    assert_eq!(report.summary(), "1 file(s) renamed to the extension of their content:\n  Takeout/IMG_0328.JPG -> output/IMG_0328.PNG\n");
+}
+
+#[test]
+fn test_fix_extensions_directory() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let takeout = temp_dir.path().join("Takeout");
+    fs::create_dir(&takeout).unwrap();
+    fs::write(takeout.join("IMG_0001.HEIC"), fixture("no_exif.jpg")).unwrap();
+    fs::write(takeout.join("IMG_0001.HEIC.json"), SIDECAR).unwrap();
+    fs::write(takeout.join("IMG_0001.HEIC.xmp"), "<x:xmpmeta/>").unwrap();
+    fs::write(takeout.join("IMG_0002.jpg"), fixture("no_exif.png")).unwrap();
+    fs::write(takeout.join("IMG_0002.png"), fixture("no_exif.png")).unwrap();
+
+    let dry_run = run_ok(&["--src-google-photos", "Takeout", "--fix-extensions", "--dry-run"], temp_dir.path());
+    assert!(dry_run.contains("output/IMG_0001.JPG\""), "{}", dry_run);
+    assert!(!temp_dir.path().join("output").exists());
+
+    let stdout = run_ok(&["--src-google-photos", "Takeout", "--fix-extensions"], temp_dir.path());
+    assert!(stdout.contains("2 file(s) renamed to the extension of their content:"), "{}", stdout);
+    // IMG_0002.png is taken by another file, so IMG_0002.jpg keeps its name
+    assert_eq!(file_names(&temp_dir.path().join("output")), ["IMG_0001.JPG", "IMG_0001.JPG.xmp", "IMG_0002.jpg", "IMG_0002.png"]);
+
+    let fixed = temp_dir.path().join("output/IMG_0001.JPG");
+    assert_eq!(read_jpeg_date_tags(&fixed).unwrap().date_time_original.as_deref(), Some("2015:06:22 19:06:40"));
+    assert!(takeout.join("IMG_0001.HEIC").exists());
+}
+
+#[test]
+fn test_fix_extensions_takeout_zip() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    write_zip(
+        &temp_dir.path().join("takeout-001.zip"),
+        &[
+            ("Takeout/Google Photos/Photos from 2015/Screenshot.jpg", &fixture("no_exif.png")),
+            ("Takeout/Google Photos/Photos from 2015/Screenshot.jpg.json", SIDECAR.as_bytes()),
+        ],
+    );
+
+    let stdout = run_ok(&["--src-takeout-zip", "takeout-001.zip", "--fix-extensions"], temp_dir.path());
+    assert!(stdout.contains("1 file(s) renamed to the extension of their content:"), "{}", stdout);
+    assert_eq!(file_names(&temp_dir.path().join("output")), ["Screenshot.png"]);
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use common::{run, SIDECAR};
+use filetime::FileTime;
+use google_photos_fix_metadata::error::FixError;
+use google_photos_fix_metadata::existing_date::ExistingDatePolicy;
+use google_photos_fix_metadata::media_kind::MediaKind;
+use google_photos_fix_metadata::metadata_utils::{add_metadata_wrapper, MetadataWrite};
+use google_photos_fix_metadata::report::RunReport;
+
+const AVI: &[u8] = b"RIFF\x24\0\0\0AVI LIST";
+
+#[test]
+fn test_summary_table() {
+    let mut report = RunReport::new();
+    assert!(!report.has_failures());
+
+    report.record_failure("a/IMG_0001.JPG", FixError::UnsupportedMediaType {
+        path: PathBuf::from("a/IMG_0001.JPG"),
+        media_type: "unknown".to_string(),
+    });
+    report.record_failure("IMG_0002.PNG.json", FixError::MissingPairedMedia {
+        sidecar: PathBuf::from("IMG_0002.PNG.json"),
+    });
+    report.record_failure("b.jpg", FixError::ToolMissing { tool: "exiftool" });
+
+    assert!(report.has_failures());
+    assert_eq!(
+        report.summary_table(),
+        concat!(
+            "FILE               REASON\n",
+            "a/IMG_0001.JPG     unsupported or misidentified file format unknown for \"a/IMG_0001.JPG\"\n",
+            "IMG_0002.PNG.json  relevant image file not found for sidecar \"IMG_0002.PNG.json\"\n",
+            "b.jpg              exiftool is not installed or not in PATH\n",
+        )
+    );
+}
+
+#[test]
+fn test_spawn_error_kinds() {
+    let not_found = FixError::from_spawn("exiftool", "a.jpg", io::Error::from(io::ErrorKind::NotFound));
+    assert!(matches!(not_found, FixError::ToolMissing { tool: "exiftool" }));
+
+    let denied = FixError::from_spawn("exiftool", "a.jpg", io::Error::from(io::ErrorKind::PermissionDenied));
+    assert!(matches!(denied, FixError::ToolFailed { tool: "exiftool", .. }));
+}
+
+#[test]
+fn test_run_continues_past_missing_media() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2015");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    fs::write(src_dir.join("lost.jpg.json"), SIDECAR).unwrap();
+    fs::write(src_dir.join("IMG_0778.jpg"), b"not really a jpg").unwrap();
+
+    let output = run(&["--src-google-photos", "Takeout"], temp_dir.path());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("Finished with 1 failed file(s):"));
+    assert!(stdout.contains("lost.jpg.json"));
+    assert!(temp_dir.path().join("output/IMG_0778.jpg").exists());
+}
+
+#[test]
+fn test_no_writer_sets_modification_time() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let video = temp_dir.path().join("MOV_0001.avi");
+    fs::write(&video, AVI).unwrap();
+
+    let written = add_metadata_wrapper(video.to_str().unwrap(), "2015:06:22 19:06:40.000+00:00", ExistingDatePolicy::Keep).unwrap();
+    assert_eq!(written, MetadataWrite::NoWriter(MediaKind::Avi));
+    assert_eq!(fs::read(&video).unwrap(), AVI);
+    assert_eq!(FileTime::from_last_modification_time(&fs::metadata(&video).unwrap()).unix_seconds(), 1435000000);
+}
+
+#[test]
+fn test_run_copies_format_without_writer() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2015");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    fs::write(src_dir.join("MOV_0001.avi"), AVI).unwrap();
+    fs::write(
+        src_dir.join("MOV_0001.avi.json"),
+        r#"{"photoTakenTime": {"timestamp": "1435000000"}, "description": "beach", "people": [{"name": "Ann"}]}"#,
+    )
+    .unwrap();
+
+    let output = run(&["--src-google-photos", "Takeout"], temp_dir.path());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("1 file(s) copied, no metadata written"));
+    assert!(stdout.contains("Finished, no failures"));
+    assert_eq!(fs::read(temp_dir.path().join("output/MOV_0001.avi")).unwrap(), AVI);
+}
+
+#[test]
+fn test_run_reports_unreadable_sidecar() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2015");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    fs::write(src_dir.join("IMG_0778.jpg"), b"not really a jpg").unwrap();
+    fs::write(src_dir.join("IMG_0778.jpg.json"), r#"{"photoTakenTime": {"timestamp": "#).unwrap();
+
+    let output = run(&["--src-google-photos", "Takeout"], temp_dir.path());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("Finished with 1 failed file(s):"));
+    assert!(stdout.contains("IMG_0778.jpg.json  invalid metadata in"));
+    assert!(temp_dir.path().join("output/IMG_0778.jpg").exists());
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::fs;
+use common::{fixture_path, run_ok};
+use chrono::NaiveDateTime;
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::filename_date::{parse_file_name_patterns, FileNameDateExtractor};
+
+fn date_time(text: &str) -> Option<NaiveDateTime> {
+    Some(NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap())
+}
+
+#[test]
+fn test_built_in_patterns() {
+    let extractor = FileNameDateExtractor::default();
+
+    assert_eq!(extractor.date_time("IMG_20190704_183012.jpg"), date_time("2019-07-04 18:30:12"));
+    assert_eq!(extractor.date_time("Screenshot_2020-01-02-10-11-12.png"), date_time("2020-01-02 10:11:12"));
+    assert_eq!(extractor.date_time("PXL_20231224_081530123.mp4"), date_time("2023-12-24 08:15:30"));
+    assert_eq!(extractor.date_time("WhatsApp Image 2021-05-06 at 12.34.56.jpeg"), date_time("2021-05-06 12:34:56"));
+    assert_eq!(extractor.date_time("signal-2022-03-04-101112.jpg"), date_time("2022-03-04 10:11:12"));
+    assert_eq!(extractor.date_time("2019-07-04 18.30.12.jpg"), date_time("2019-07-04 18:30:12"));
+    assert_eq!(extractor.date_time("VID-20180512-WA0003.mp4"), date_time("2018-05-12 00:00:00"));
+
+    assert_eq!(extractor.date_time("IMG_1234.JPG"), None);
+    assert_eq!(extractor.date_time("IMG_20191332_000000.jpg"), None);
+    assert_eq!(extractor.date_time("BAAC2A4F-AF2C-44EE.png"), None);
+}
+
+#[test]
+fn test_user_patterns() {
+    let patterns = parse_file_name_patterns(
+        "# DJI drones\n\
+         ^DJI_(?P<year>\\d{4})(?P<month>\\d{2})(?P<day>\\d{2})(?P<hour>\\d{2})(?P<minute>\\d{2})(?P<second>\\d{2})\n\
+         \n\
+         ^scan (?P<day>\\d{2})\\.(?P<month>\\d{2})\\.(?P<year>\\d{2})\n",
+    )
+    .unwrap();
+    let extractor = FileNameDateExtractor::with_patterns(patterns);
+
+    assert_eq!(extractor.date_time("DJI_20210815143000_0001.JPG"), date_time("2021-08-15 14:30:00"));
+    assert_eq!(extractor.date_time("scan 24.12.99.jpg"), date_time("2099-12-24 00:00:00"));
+    // The built-in patterns still apply
+    assert_eq!(extractor.date_time("IMG_20190704_183012.jpg"), date_time("2019-07-04 18:30:12"));
+
+    assert!(parse_file_name_patterns("(?P<year>\\d{4})(?P<month>\\d{2})").unwrap_err().contains("(?P<day>...)"));
+    assert!(parse_file_name_patterns("\n(?P<year>\\d{4}").unwrap_err().starts_with("line 2:"));
+}
+
+#[test]
+fn test_iphone_file_name_before_modification_time() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("iphone");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    let fixture = fixture_path("no_exif.jpg");
+    fs::copy(&fixture, src_dir.join("IMG_20190704_183012.jpg")).unwrap();
+    fs::copy(&fixture, src_dir.join("DJI_20210815143000_0001.jpg")).unwrap();
+    fs::write(temp_dir.path().join("patterns.txt"), r"^DJI_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})").unwrap();
+
+    run_ok(&["--src-iphone-photos", "iphone", "--filename-patterns", "patterns.txt"], temp_dir.path());
+
+    let built_in = read_jpeg_date_tags(&temp_dir.path().join("output/IMG_20190704_183012.jpg")).unwrap();
+    assert_eq!(built_in.date_time_original.as_deref(), Some("2019:07:04 18:30:12"));
+    let user = read_jpeg_date_tags(&temp_dir.path().join("output/DJI_20210815143000_0001.jpg")).unwrap();
+    assert_eq!(user.date_time_original.as_deref(), Some("2021:08:15 14:30:00"));
+}
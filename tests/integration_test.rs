@@ -1,6 +1,6 @@
 use google_photos_fix_metadata::{swap_position, get_new_image_file_path_by_swap_position};
 use std::path::{Path, PathBuf};
-use google_photos_fix_metadata::fix_stupid_google_photos_takeout_naming_bug::swap_file_name_str_position;
+use google_photos_fix_metadata::fix_stupid_google_photos_takeout_naming_bug::{strip_supplemental_metadata, swap_file_name_str_position};
 
 #[test]
 fn test_swap_file_name_str_position() {
@@ -93,4 +93,82 @@ fn test_get_image_file_path() {
         PathBuf::from("/home/fedora/test/ipad_google_photos_2014-2017/takeout-20240625T042623Z-001/Takeout/Google Photos/Photos from 2015/IMG_0894(1).JPG")
     );
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_strip_supplemental_metadata() {
+    assert_eq!(
+        strip_supplemental_metadata("IMG_1234.JPG.supplemental-metadata.json"),
+        Some("IMG_1234.JPG".to_string())
+    );
+    assert_eq!(
+        strip_supplemental_metadata("IMG_1234.JPG.supplemental-metadata(1).json"),
+        Some("IMG_1234(1).JPG".to_string())
+    );
+    // Cut to 51 characters by Takeout
+    assert_eq!(
+        strip_supplemental_metadata("IMG_20190704_183012_HDR.jpg.supplemental-me.json"),
+        Some("IMG_20190704_183012_HDR.jpg".to_string())
+    );
+    assert_eq!(
+        strip_supplemental_metadata("Screenshot_20200102-101112_WhatsApp.jpg.s(2).json"),
+        Some("Screenshot_20200102-101112_WhatsApp(2).jpg".to_string())
+    );
+    assert_eq!(
+        strip_supplemental_metadata("IMG_1234.JPG.SUPPLEMENTAL-METADATA.json"),
+        Some("IMG_1234.JPG".to_string())
+    );
+
+    assert_eq!(strip_supplemental_metadata("IMG_1234.JPG.json"), None);
+    assert_eq!(strip_supplemental_metadata("IMG_1234.JPG(1).json"), None);
+    assert_eq!(strip_supplemental_metadata("IMG_1234.JPG.supplemental-data.json"), None);
+}
+
+#[test]
+fn test_swap_supplemental_metadata() {
+    assert_eq!(
+        swap_file_name_str_position("IMG_1234.JPG.supplemental-metadata(1).json"),
+        "IMG_1234(1).JPG.json"
+    );
+    assert_eq!(
+        swap_position(Path::new("./test/IMG_0253.HEIC.supplemental-metadata(2).json")),
+        Some(PathBuf::from("./test/IMG_0253(2).HEIC.json"))
+    );
+    assert_eq!(
+        get_new_image_file_path_by_swap_position(Path::new("/home/Takeout/Google Photos/Photos from 2024/IMG_1234.JPG.supplemental-metadata.json")),
+        PathBuf::from("/home/Takeout/Google Photos/Photos from 2024/IMG_1234.JPG")
+    );
+    assert_eq!(
+        get_new_image_file_path_by_swap_position(Path::new("PXL_20231224_081530123.mp4.supplemental-metad(1).json")),
+        PathBuf::from("PXL_20231224_081530123(1).mp4")
+    );
+}
+
+#[test]
+fn test_supplemental_metadata_export() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2015");
+    std::fs::create_dir_all(&src_dir).unwrap();
+
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/no_exif.jpg");
+    let sidecar = r#"{"photoTakenTime": {"timestamp": "1435000000"}}"#;
+    for (media, json) in [
+        ("IMG_1234.JPG", "IMG_1234.JPG.supplemental-metadata.json"),
+        ("IMG_1234(1).JPG", "IMG_1234.JPG.supplemental-metadata(1).json"),
+    ] {
+        std::fs::copy(&fixture, src_dir.join(media)).unwrap();
+        std::fs::write(src_dir.join(json), sidecar).unwrap();
+    }
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_google_photos_fix_metadata"))
+        .current_dir(temp_dir.path())
+        .args(["--src-google-photos", "Takeout"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+
+    for media in ["IMG_1234.JPG", "IMG_1234(1).JPG"] {
+        let dates = google_photos_fix_metadata::exif::read_jpeg_date_tags(&temp_dir.path().join("output").join(media)).unwrap();
+        assert_eq!(dates.date_time_original.as_deref(), Some("2015:06:22 19:06:40"), "{}", media);
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+use google_photos_fix_metadata::media_kind::MediaKind;
+
+fn ftyp(major_brand: &[u8; 4], compatible_brands: &[&[u8; 4]]) -> Vec<u8> {
+    let size = 16 + 4 * compatible_brands.len();
+    let mut header = (size as u32).to_be_bytes().to_vec();
+    header.extend_from_slice(b"ftyp");
+    header.extend_from_slice(major_brand);
+    header.extend_from_slice(&[0, 0, 0, 0]);
+    for brand in compatible_brands {
+        header.extend_from_slice(*brand);
+    }
+    header
+}
+
+// Little endian TIFF header with the given IFD0 entries (tag, type, count, value), extra follows IFD0
+fn tiff(entries: &[(u16, u16, u32, [u8; 4])], extra: &[u8]) -> Vec<u8> {
+    let mut header = b"II*\0".to_vec();
+    header.extend_from_slice(&8u32.to_le_bytes());
+    header.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, field_type, count, value) in entries {
+        header.extend_from_slice(&tag.to_le_bytes());
+        header.extend_from_slice(&field_type.to_le_bytes());
+        header.extend_from_slice(&count.to_le_bytes());
+        header.extend_from_slice(value);
+    }
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(extra);
+    header
+}
+
+#[test]
+fn test_detect_images() {
+    assert_eq!(MediaKind::detect(&[0xFF, 0xD8, 0xFF, 0xE0]), MediaKind::Jpeg);
+    assert_eq!(MediaKind::detect(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), MediaKind::Png);
+    assert_eq!(MediaKind::detect(b"GIF89a\x01\0\x01\0"), MediaKind::Gif);
+    assert_eq!(MediaKind::detect(b"RIFF\x24\0\0\0WEBPVP8 "), MediaKind::WebP);
+    assert_eq!(MediaKind::detect(b"BM\x3a\0\0\0\0\0\0\0\x36\0\0\0"), MediaKind::Bmp);
+    assert_eq!(MediaKind::detect(&[0, 0, 1, 0, 1, 0, 16, 16]), MediaKind::Ico);
+    assert_eq!(MediaKind::detect(&ftyp(b"heic", &[b"mif1", b"heic"])), MediaKind::Heic);
+    assert_eq!(MediaKind::detect(&ftyp(b"mif1", &[b"mif1", b"heic"])), MediaKind::Heic);
+    assert_eq!(MediaKind::detect(&ftyp(b"avif", &[b"mif1"])), MediaKind::Avif);
+    assert_eq!(MediaKind::detect(&ftyp(b"mif1", &[b"avif", b"miaf"])), MediaKind::Avif);
+}
+
+#[test]
+fn test_detect_raw() {
+    assert_eq!(MediaKind::detect(&tiff(&[(0x0100, 4, 1, [1, 0, 0, 0])], &[])), MediaKind::Tiff);
+    assert_eq!(MediaKind::detect(&tiff(&[(0xC612, 1, 4, [1, 4, 0, 0])], &[])), MediaKind::Dng);
+
+    // The Make has to come with the RAW specific tag, the Make is at 38, after the two entries
+    let nikon = (0x010F, 2, 18, [38, 0, 0, 0]);
+    let sony = (0x010F, 2, 5, [38, 0, 0, 0]);
+    let sub_ifds = (0x014A, 4, 1, [0, 1, 0, 0]);
+    let sr2_private = (0xC634, 1, 4, [0, 1, 0, 0]);
+    assert_eq!(MediaKind::detect(&tiff(&[nikon, sub_ifds], b"NIKON CORPORATION\0")), MediaKind::Nef);
+    assert_eq!(MediaKind::detect(&tiff(&[sony, sr2_private], b"SONY\0")), MediaKind::Arw);
+    assert_eq!(MediaKind::detect(&tiff(&[(0x010F, 2, 18, [26, 0, 0, 0])], b"NIKON CORPORATION\0")), MediaKind::Tiff);
+    assert_eq!(MediaKind::detect(&tiff(&[(0x010F, 2, 5, [26, 0, 0, 0])], b"SONY\0")), MediaKind::Tiff);
+    assert_eq!(MediaKind::detect(&tiff(&[sony, sub_ifds], b"SONY\0")), MediaKind::Tiff);
+    assert_eq!(MediaKind::detect(b"II*\0\x10\0\0\0CR\x02\0"), MediaKind::Cr2);
+    assert_eq!(MediaKind::detect(&ftyp(b"crx ", &[b"crx ", b"isom"])), MediaKind::Cr3);
+    assert_eq!(MediaKind::detect(b"IIRO\x08\0\0\0"), MediaKind::Orf);
+    assert_eq!(MediaKind::detect(b"IIU\0\x08\0\0\0"), MediaKind::Rw2);
+    assert_eq!(MediaKind::detect(b"FUJIFILMCCD-RAW 0201"), MediaKind::Raf);
+    assert!(MediaKind::Dng.is_raw());
+}
+
+#[test]
+fn test_detect_videos() {
+    assert_eq!(MediaKind::detect(&ftyp(b"isom", &[b"isom", b"mp41"])), MediaKind::Mp4);
+    assert_eq!(MediaKind::detect(&ftyp(b"qt  ", &[b"qt  "])), MediaKind::Mov);
+    assert_eq!(MediaKind::detect(b"\0\0\0\x08wide\0\0\0\0mdat"), MediaKind::Mov);
+    assert_eq!(MediaKind::detect(&ftyp(b"M4V ", &[b"M4V ", b"mp42"])), MediaKind::M4v);
+    assert_eq!(MediaKind::detect(&ftyp(b"3gp4", &[b"3gp4"])), MediaKind::ThreeGp);
+    assert_eq!(MediaKind::detect(&[0x1A, 0x45, 0xDF, 0xA3, 0x9F]), MediaKind::Mkv);
+    assert_eq!(MediaKind::detect(b"RIFF\0\0\0\0AVI LIST"), MediaKind::Avi);
+    assert_eq!(MediaKind::detect(&[0x00, 0x00, 0x01, 0xBA, 0x44]), MediaKind::Mpeg);
+    assert_eq!(MediaKind::detect(&[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, 0xA6]), MediaKind::Wmv);
+
+    let mut transport_stream = vec![0u8; 192 * 3];
+    for packet in 0..3 {
+        transport_stream[4 + packet * 192] = 0x47;
+    }
+    assert_eq!(MediaKind::detect(&transport_stream), MediaKind::Mpeg);
+    assert!(MediaKind::Mov.is_video());
+}
+
+#[test]
+fn test_detect_file() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    assert_eq!(MediaKind::detect_file(&fixtures.join("no_exif.jpg")).unwrap(), MediaKind::Jpeg);
+    assert_eq!(MediaKind::detect_file(&fixtures.join("exif_without_dates.jpg")).unwrap(), MediaKind::Jpeg);
+    assert_eq!(MediaKind::detect_file(&fixtures.join("no_exif.png")).unwrap(), MediaKind::Png);
+
+    assert_eq!(MediaKind::detect(b"not really a jpg"), MediaKind::Unknown);
+    assert_eq!(MediaKind::Unknown.to_string(), "unknown");
+    assert!(MediaKind::detect_file(&fixtures.join("missing.jpg")).is_err());
+}
+
+#[test]
+fn test_fits_extension() {
+    assert_eq!(MediaKind::from_extension("JPEG"), Some(MediaKind::Jpeg));
+    assert_eq!(MediaKind::from_extension("aae"), None);
+
+    assert!(MediaKind::Jpeg.fits_extension("JPG"));
+    assert!(!MediaKind::Png.fits_extension("jpg"));
+    assert!(!MediaKind::Mov.fits_extension("MP4"));
+    assert!(MediaKind::M4v.fits_extension("mp4"));
+    // A RAW without maker tags is detected as TIFF
+    assert!(MediaKind::Tiff.fits_extension("NEF"));
+    assert!(MediaKind::Unknown.fits_extension("jpg"));
+    assert!(MediaKind::Jpeg.fits_extension("xmp"));
+}
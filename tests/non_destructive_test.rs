@@ -0,0 +1,67 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+use common::{fixture_path, run_ok, SIDECAR};
+
+// relative path -> (contents, modification time)
+fn snapshot_tree(root: &Path) -> BTreeMap<PathBuf, (Vec<u8>, SystemTime)> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| {
+            let relative_path = entry.path().strip_prefix(root).unwrap().to_path_buf();
+            let contents = fs::read(entry.path()).unwrap();
+            let modified = fs::metadata(entry.path()).unwrap().modified().unwrap();
+            (relative_path, (contents, modified))
+        })
+        .collect()
+}
+
+#[test]
+fn test_google_photos_source_is_unchanged() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2015");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    // No photoTakenTime, so the copy does not need exiftool either
+    fs::write(src_dir.join("IMG_0777.png.json"), r#"{"title": "IMG_0777.png"}"#).unwrap();
+    fs::write(src_dir.join("IMG_0777.png"), b"not really a png").unwrap();
+    fs::write(src_dir.join("IMG_0778.jpg"), b"not really a jpg").unwrap();
+
+    let before = snapshot_tree(temp_dir.path());
+    run_ok(&["--src-google-photos", "Takeout"], temp_dir.path());
+    let after = snapshot_tree(temp_dir.path());
+
+    let source_after: BTreeMap<_, _> = after
+        .iter()
+        .filter(|(path, _)| path.starts_with("Takeout"))
+        .map(|(path, value)| (path.clone(), value.clone()))
+        .collect();
+    assert_eq!(before, source_after);
+
+    assert_eq!(fs::read(temp_dir.path().join("output/IMG_0777.png")).unwrap(), b"not really a png");
+    assert_eq!(fs::read(temp_dir.path().join("output/IMG_0778.jpg")).unwrap(), b"not really a jpg");
+    assert!(!temp_dir.path().join("output/IMG_0777.png.json").exists());
+}
+
+#[test]
+fn test_metadata_is_written_to_the_copy_only() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2015");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    fs::copy(fixture_path("no_exif.jpg"), src_dir.join("IMG_0894.JPG")).unwrap();
+    fs::write(src_dir.join("IMG_0894.JPG.json"), SIDECAR).unwrap();
+
+    let before = snapshot_tree(&src_dir);
+    run_ok(&["--src-google-photos", "Takeout"], temp_dir.path());
+    assert_eq!(before, snapshot_tree(&src_dir));
+
+    let output_file = temp_dir.path().join("output/IMG_0894.JPG");
+    assert_ne!(fs::read(&output_file).unwrap(), fs::read(src_dir.join("IMG_0894.JPG")).unwrap());
+}
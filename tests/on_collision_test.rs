@@ -0,0 +1,212 @@
+mod common;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use common::{file_names, file_tree, fixture_path, run, run_ok, write_zip, SIDECAR};
+use google_photos_fix_metadata::error::FixError;
+use google_photos_fix_metadata::file_utils::{collision_destination, copy_file_preserving_metadata, reserve_destination, CollisionNaming, CollisionPolicy};
+
+fn photo_dir(dir: &Path, name: &str) -> PathBuf {
+    let photos = dir.join(name);
+    fs::create_dir_all(&photos).unwrap();
+    fs::copy(fixture_path("no_exif.jpg"), photos.join("IMG_0001.jpg")).unwrap();
+    photos
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(CollisionPolicy::parse("skip-if-identical"), Ok(CollisionPolicy::SkipIfIdentical));
+    assert_eq!(CollisionPolicy::parse("fail"), Ok(CollisionPolicy::Fail));
+    assert!(CollisionPolicy::parse("merge").is_err());
+}
+
+#[test]
+fn test_collision_destination() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dest = temp_dir.path().join("IMG_0001.jpg");
+
+    assert_eq!(collision_destination(&dest, CollisionPolicy::Fail).unwrap(), Some(dest.clone()));
+
+    fs::write(&dest, b"earlier run").unwrap();
+    assert_eq!(collision_destination(&dest, CollisionPolicy::Rename).unwrap(), Some(temp_dir.path().join("IMG_0001-1.jpg")));
+    assert_eq!(collision_destination(&dest, CollisionPolicy::Skip).unwrap(), None);
+    assert_eq!(collision_destination(&dest, CollisionPolicy::SkipIfIdentical).unwrap(), None);
+    assert!(matches!(collision_destination(&dest, CollisionPolicy::Fail), Err(FixError::DestinationExists { .. })));
+
+    // A hard link to the file overwritten keeps the old content
+    let link = temp_dir.path().join("album.jpg");
+    fs::hard_link(&dest, &link).unwrap();
+    assert_eq!(collision_destination(&dest, CollisionPolicy::Overwrite).unwrap(), Some(dest.clone()));
+    assert!(!dest.exists());
+    assert_eq!(fs::read(&link).unwrap(), b"earlier run");
+}
+
+#[test]
+fn test_copy_skip_if_identical() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src = photo_dir(temp_dir.path(), "src").join("IMG_0001.jpg");
+    let dest = temp_dir.path().join("IMG_0001.jpg");
+
+    assert_eq!(copy_file_preserving_metadata(&src, &dest, CollisionPolicy::SkipIfIdentical).unwrap(), Some(dest.clone()));
+    assert_eq!(copy_file_preserving_metadata(&src, &dest, CollisionPolicy::SkipIfIdentical).unwrap(), None);
+}
+
+#[test]
+fn test_reserve_destination() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dest = temp_dir.path().join("IMG_0001.jpg");
+    let source = Path::new("src/IMG_0001.jpg");
+    let reserve = |on_collision, identical: bool, reserved: &mut HashSet<PathBuf>| {
+        reserve_destination(&dest, CollisionNaming::Counter, on_collision, source, || None, |_| identical, reserved)
+    };
+    let renamed = |counter: usize| temp_dir.path().join(format!("IMG_0001-{}.jpg", counter));
+
+    // Both names are taken on disk by an earlier run
+    fs::write(&dest, b"earlier run").unwrap();
+    fs::write(renamed(1), b"earlier run").unwrap();
+
+    for on_collision in [CollisionPolicy::Skip, CollisionPolicy::Overwrite, CollisionPolicy::Fail] {
+        let mut reserved = HashSet::new();
+        assert_eq!(reserve(on_collision, false, &mut reserved), dest);
+        assert_eq!(reserve(on_collision, false, &mut reserved), renamed(1));
+    }
+
+    let mut reserved = HashSet::new();
+    assert_eq!(reserve(CollisionPolicy::SkipIfIdentical, true, &mut reserved), dest);
+    assert_eq!(reserve(CollisionPolicy::SkipIfIdentical, false, &mut reserved), renamed(2));
+    assert_eq!(reserve(CollisionPolicy::SkipIfIdentical, false, &mut reserved), renamed(3));
+
+    let mut reserved = HashSet::new();
+    assert_eq!(reserve(CollisionPolicy::Rename, true, &mut reserved), renamed(2));
+    assert_eq!(reserve(CollisionPolicy::Rename, true, &mut reserved), renamed(3));
+}
+
+#[test]
+fn test_output_dir_per_source() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    photo_dir(temp_dir.path(), "Takeout");
+    photo_dir(temp_dir.path(), "iPhone");
+
+    run_ok(&["--src-google-photos", "Takeout", "--src-iphone-photos", "iPhone", "--output", "fixed", "--output-per-source"], temp_dir.path());
+    assert!(!temp_dir.path().join("output").exists());
+    assert_eq!(file_names(&temp_dir.path().join("fixed")), ["google-photos", "iphone-photos"]);
+    assert_eq!(file_names(&temp_dir.path().join("fixed/google-photos")), ["IMG_0001.jpg"]);
+    assert_eq!(file_names(&temp_dir.path().join("fixed/iphone-photos")), ["IMG_0001.jpg"]);
+}
+
+#[test]
+fn test_on_collision_across_runs() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    photo_dir(temp_dir.path(), "Takeout");
+    let output_dir = temp_dir.path().join("output");
+
+    for _ in 0..2 {
+        run_ok(&["--src-google-photos", "Takeout", "--on-collision", "skip"], temp_dir.path());
+    }
+    assert_eq!(file_names(&output_dir), ["IMG_0001.jpg"]);
+
+    let output = run(&["--src-google-photos", "Takeout", "--on-collision", "fail"], temp_dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("already exists in the output directory"));
+
+    run_ok(&["--src-google-photos", "Takeout"], temp_dir.path());
+    assert_eq!(file_names(&output_dir), ["IMG_0001-1.jpg", "IMG_0001.jpg"]);
+
+    let stdout = run_ok(&["--src-google-photos", "Takeout", "--on-collision", "overwrite", "--dry-run"], temp_dir.path());
+    assert!(stdout.contains("(already exists, overwritten)"));
+}
+
+#[test]
+fn test_takeout_zip_skip_if_identical() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    write_zip(&temp_dir.path().join("takeout-001.zip"), &[("Takeout/Google Photos/Photos from 2015/IMG_0001.jpg", b"not fixed, no sidecar")]);
+    let output_dir = temp_dir.path().join("output");
+
+    for _ in 0..2 {
+        run_ok(&["--src-takeout-zip", "takeout-001.zip", "--on-collision", "skip-if-identical"], temp_dir.path());
+    }
+    assert_eq!(file_names(&output_dir), ["IMG_0001.jpg"]);
+
+    fs::write(output_dir.join("IMG_0001.jpg"), b"another photo").unwrap();
+    run_ok(&["--src-takeout-zip", "takeout-001.zip", "--on-collision", "skip-if-identical"], temp_dir.path());
+    assert_eq!(file_names(&output_dir), ["IMG_0001-1.jpg", "IMG_0001.jpg"]);
+}
+
+#[test]
+fn test_takeout_zip_skip_if_identical_same_crc32() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    write_zip(&temp_dir.path().join("takeout-001.zip"), &[("Takeout/Google Photos/Photos from 2015/IMG_0001.jpg", b"plumless")]);
+
+    // Same size and crc32 as the entry, different content
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).unwrap();
+    fs::write(output_dir.join("IMG_0001.jpg"), b"buckeroo").unwrap();
+
+    run_ok(&["--src-takeout-zip", "takeout-001.zip", "--on-collision", "skip-if-identical"], temp_dir.path());
+    assert_eq!(file_names(&output_dir), ["IMG_0001-1.jpg", "IMG_0001.jpg"]);
+    assert_eq!(fs::read(output_dir.join("IMG_0001-1.jpg")).unwrap(), b"plumless");
+}
+
+// The destinations a dry run shows for the copies
+fn planned_destinations(stdout: &str) -> Vec<String> {
+    let mut planned: Vec<String> = stdout
+        .lines()
+        .filter(|line| line.starts_with("[dry-run] copy "))
+        .filter_map(|line| line.split(" -> \"").nth(1)?.split('"').next().map(|dest| dest.to_string()))
+        .collect();
+    planned.sort();
+    planned
+}
+
+#[test]
+fn test_dry_run_matches_run() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    photo_dir(temp_dir.path(), "Takeout/Trip");
+    fs::write(photo_dir(temp_dir.path(), "Takeout/Home").join("IMG_0001.jpg"), b"another photo").unwrap();
+    photo_dir(temp_dir.path(), "iPhone");
+    let args = ["--src-google-photos", "Takeout", "--src-iphone-photos", "iPhone"];
+
+    let planned = planned_destinations(&run_ok(&[&args[..], &["--dry-run"]].concat(), temp_dir.path()));
+    assert_eq!(planned, ["output/IMG_0001-1.jpg", "output/IMG_0001-2.jpg", "output/IMG_0001.jpg"]);
+
+    run_ok(&args, temp_dir.path());
+    let copied: Vec<String> = file_names(&temp_dir.path().join("output")).iter().map(|name| format!("output/{}", name)).collect();
+    assert_eq!(copied, planned);
+
+    // --resume goes on at the destinations in the journal
+    assert_eq!(planned_destinations(&run_ok(&[&args[..], &["--dry-run", "--resume"]].concat(), temp_dir.path())), planned);
+}
+
+#[test]
+fn test_skip_if_identical_with_sidecar() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let photos = photo_dir(temp_dir.path(), "Takeout");
+    fs::write(photos.join("IMG_0001.jpg.json"), SIDECAR).unwrap();
+    let output_dir = temp_dir.path().join("output");
+
+    // The copy gets the date, so it differs from its source
+    for _ in 0..3 {
+        run_ok(&["--src-google-photos", "Takeout", "--on-collision", "skip-if-identical"], temp_dir.path());
+        assert_eq!(file_names(&output_dir), ["IMG_0001.jpg"]);
+    }
+    assert_ne!(fs::read(output_dir.join("IMG_0001.jpg")).unwrap(), fs::read(photos.join("IMG_0001.jpg")).unwrap());
+}
+
+#[test]
+fn test_same_names_across_runs() {
+    for on_collision in ["skip", "overwrite", "skip-if-identical"] {
+        let temp_dir = tempfile::tempdir().unwrap();
+        photo_dir(temp_dir.path(), "Takeout/Trip");
+        fs::write(photo_dir(temp_dir.path(), "Takeout/Home").join("IMG_0001.jpg"), b"another photo").unwrap();
+        let output_dir = temp_dir.path().join("output");
+
+        let mut trees = Vec::new();
+        for _ in 0..2 {
+            run_ok(&["--src-google-photos", "Takeout", "--on-collision", on_collision], temp_dir.path());
+            trees.push(file_tree(&output_dir));
+        }
+        assert_eq!(file_names(&output_dir), ["IMG_0001-1.jpg", "IMG_0001.jpg"], "{}", on_collision);
+        assert_eq!(trees[0], trees[1], "{}", on_collision);
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::fs;
+use common::{fixture_path, run, SIDECAR};
+use google_photos_fix_metadata::date_sources::DateSource;
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::orphan_report::OrphanReport;
+
+#[test]
+fn test_summary_and_json() {
+    let mut orphans = OrphanReport::default();
+    assert!(orphans.is_empty());
+
+    orphans.record_sidecar("Photos from 2015/lost.jpg.json");
+    orphans.record_media("Photos from 2015/IMG_20150622_190640.jpg", Some((DateSource::FileName, "2015:06:22 19:06:40.000+00:00".to_string())));
+    orphans.record_media("Photos from 2015/IMG_0001.JPG", None);
+
+    assert_eq!(
+        orphans.summary(),
+        concat!(
+            "1 sidecar(s) without media:\n",
+            "  Photos from 2015/lost.jpg.json\n",
+            "2 media file(s) without sidecar:\n",
+            "  Photos from 2015/IMG_20150622_190640.jpg  date from filename: 2015:06:22 19:06:40.000+00:00\n",
+            "  Photos from 2015/IMG_0001.JPG  left alone\n",
+        )
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&orphans.to_json()).unwrap();
+    assert_eq!(json["sidecars_without_media"][0], "Photos from 2015/lost.jpg.json");
+    assert_eq!(json["media_without_sidecar"][0]["date_source"], "filename");
+    assert_eq!(json["media_without_sidecar"][1]["date_source"], serde_json::Value::Null);
+}
+
+#[test]
+fn test_fallback_dates_written_to_copy() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join("Takeout/Google Photos/Photos from 2019");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    let fixture = fixture_path("no_exif.jpg");
+    fs::copy(&fixture, src_dir.join("IMG_20190704_183012.jpg")).unwrap();
+    fs::copy(&fixture, src_dir.join("IMG_0001.jpg")).unwrap();
+    fs::write(src_dir.join("lost.jpg.json"), SIDECAR).unwrap();
+
+    let output = run(
+        &["--src-google-photos", "Takeout", "--no-sidecar-fallback", "exif,filename,folder", "--orphan-report", "orphans.json"],
+        temp_dir.path(),
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // The sidecar without media is still a failure
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("2 media file(s) without sidecar:"), "{}", stdout);
+
+    let from_name = read_jpeg_date_tags(&temp_dir.path().join("output/IMG_20190704_183012.jpg")).unwrap();
+    assert_eq!(from_name.date_time_original.as_deref(), Some("2019:07:04 18:30:12"));
+    let from_folder = read_jpeg_date_tags(&temp_dir.path().join("output/IMG_0001.jpg")).unwrap();
+    assert_eq!(from_folder.date_time_original.as_deref(), Some("2019:01:01 00:00:00"));
+
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_dir.path().join("orphans.json")).unwrap()).unwrap();
+    assert_eq!(json["sidecars_without_media"].as_array().unwrap().len(), 1);
+    let sources: Vec<&str> = json["media_without_sidecar"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|media| media["date_source"].as_str().unwrap())
+        .collect();
+    assert_eq!(sources.len(), 2);
+    assert!(sources.contains(&"filename") && sources.contains(&"folder"));
+}
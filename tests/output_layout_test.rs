@@ -0,0 +1,134 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use common::{fixture_path, run_ok, SIDECAR};
+use google_photos_fix_metadata::file_utils::relative_path;
+use google_photos_fix_metadata::output_layout::{
+    album_folder_name, album_title_from_reader, place_album_copies, read_album_title, AlbumCopies, AlbumCopy, AlbumPlacement, OutputLayout,
+};
+
+fn copy(key: Option<&str>, album: Option<&str>) -> AlbumCopy<String> {
+    AlbumCopy { key: key.map(str::to_string), album: album.map(str::to_string) }
+}
+
+fn takeout_with_albums(dir: &Path) -> PathBuf {
+    let takeout = dir.join("Takeout/Google Photos");
+    let fixture = fixture_path("no_exif.jpg");
+    for folder in ["Photos from 2015", "Trip", "Family"] {
+        fs::create_dir_all(takeout.join(folder)).unwrap();
+        fs::copy(&fixture, takeout.join(folder).join("IMG_0001.jpg")).unwrap();
+        fs::write(takeout.join(folder).join("IMG_0001.jpg.json"), SIDECAR).unwrap();
+    }
+    fs::copy(&fixture, takeout.join("Photos from 2015/IMG_0002.jpg")).unwrap();
+    fs::write(takeout.join("Trip/metadata.json"), r#"{"title": "Lisbon / Porto 2015", "description": ""}"#).unwrap();
+    fs::write(takeout.join("Family/metadata.json"), r#"{"title": "Family"}"#).unwrap();
+    takeout
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(OutputLayout::parse("by-album"), Ok(OutputLayout::ByAlbum));
+    assert!(OutputLayout::parse("tree").is_err());
+    assert_eq!(AlbumCopies::parse("symlink"), Ok(AlbumCopies::Symlink));
+    assert!(AlbumCopies::parse("copy").is_err());
+}
+
+#[test]
+fn test_relative_dir() {
+    let media_path = Path::new("Google Photos/Trip/IMG_0001.jpg");
+    let value = Some("2015:06:22 19:06:40.000+09:00");
+
+    assert_eq!(OutputLayout::Flat.relative_dir(media_path, value, Some("Trip")), PathBuf::new());
+    assert_eq!(OutputLayout::Mirror.relative_dir(media_path, value, None), Path::new("Google Photos/Trip"));
+    assert_eq!(OutputLayout::ByDate.relative_dir(media_path, value, None), Path::new("2015/06"));
+    assert_eq!(OutputLayout::ByDate.relative_dir(media_path, None, None), PathBuf::new());
+    assert_eq!(OutputLayout::ByAlbum.relative_dir(media_path, value, Some("Lisbon / Porto")), Path::new("Lisbon _ Porto"));
+    assert_eq!(OutputLayout::ByAlbum.relative_dir(media_path, value, None), PathBuf::new());
+}
+
+#[test]
+fn test_album_titles() {
+    assert_eq!(album_title_from_reader(r#"{"title": " Trip "}"#.as_bytes()).as_deref(), Some("Trip"));
+    assert_eq!(album_title_from_reader(r#"{"title": ""}"#.as_bytes()), None);
+    assert_eq!(album_title_from_reader(r#"{"photoTakenTime": {}}"#.as_bytes()), None);
+
+    assert_eq!(album_folder_name("Paris: day 1?"), "Paris_ day 1_");
+    assert_eq!(album_folder_name(".."), "_");
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let takeout = takeout_with_albums(temp_dir.path());
+    assert_eq!(read_album_title(&takeout.join("Trip")).as_deref(), Some("Lisbon / Porto 2015"));
+    assert_eq!(read_album_title(&takeout.join("Photos from 2015")), None);
+    // Takeout writes a metadata.json into year folders too, they are still no albums
+    fs::write(takeout.join("Photos from 2015/metadata.json"), r#"{"title": "Photos from 2015"}"#).unwrap();
+    assert_eq!(read_album_title(&takeout.join("Photos from 2015")), None);
+}
+
+#[test]
+fn test_place_album_copies() {
+    let copies = [
+        copy(Some("a"), None),
+        copy(Some("a"), Some("Trip")),
+        copy(Some("a"), Some("Family")),
+        copy(Some("a"), Some("Trip")),
+        copy(Some("b"), None),
+        copy(Some("b"), None),
+        copy(None, Some("Trip")),
+    ];
+    assert_eq!(
+        place_album_copies(&copies, AlbumCopies::Hardlink),
+        [
+            AlbumPlacement::SameAs(1),
+            AlbumPlacement::Own,
+            AlbumPlacement::LinkTo(1),
+            AlbumPlacement::SameAs(1),
+            AlbumPlacement::Own,
+            AlbumPlacement::Own,
+            AlbumPlacement::Own,
+        ]
+    );
+    assert_eq!(place_album_copies(&copies, AlbumCopies::Single)[2], AlbumPlacement::SameAs(1));
+}
+
+#[test]
+fn test_relative_path() {
+    assert_eq!(relative_path(Path::new("output/Family"), Path::new("output/Trip/IMG_0001.jpg")), Path::new("../Trip/IMG_0001.jpg"));
+    assert_eq!(relative_path(Path::new("./output"), Path::new("output/2015/06/IMG_0001.jpg")), Path::new("2015/06/IMG_0001.jpg"));
+}
+
+#[test]
+fn test_by_album_layout() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    takeout_with_albums(temp_dir.path());
+    run_ok(&["--src-google-photos", "Takeout", "--layout", "by-album", "--album-copies", "symlink"], temp_dir.path());
+
+    let output = temp_dir.path().join("output");
+    assert!(output.join("IMG_0002.jpg").is_file());
+    assert!(!output.join("IMG_0001.jpg").exists());
+    assert!(!output.join("Photos from 2015").exists());
+
+    // One album has the copy, the other a link to it
+    let in_albums = [output.join("Lisbon _ Porto 2015/IMG_0001.jpg"), output.join("Family/IMG_0001.jpg")];
+    let links: Vec<&PathBuf> = in_albums.iter().filter(|path| fs::symlink_metadata(path).unwrap().file_type().is_symlink()).collect();
+    assert_eq!(links.len(), 1);
+    assert!(links[0].is_file());
+    assert_eq!(fs::read(&in_albums[0]).unwrap(), fs::read(&in_albums[1]).unwrap());
+}
+
+#[test]
+fn test_by_date_and_mirror_layouts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    takeout_with_albums(temp_dir.path());
+
+    run_ok(&["--src-google-photos", "Takeout", "--layout", "by-date"], temp_dir.path());
+    let by_date = temp_dir.path().join("output");
+    assert_eq!(fs::read_dir(by_date.join("2015/06")).unwrap().count(), 3);
+    // No sidecar and no fallback: no date
+    assert!(by_date.join("IMG_0002.jpg").is_file());
+
+    fs::remove_dir_all(&by_date).unwrap();
+    run_ok(&["--src-google-photos", "Takeout", "--layout", "mirror"], temp_dir.path());
+    assert!(by_date.join("Google Photos/Trip/IMG_0001.jpg").is_file());
+    assert!(by_date.join("Google Photos/Photos from 2015/IMG_0002.jpg").is_file());
+}
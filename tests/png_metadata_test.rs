@@ -0,0 +1,124 @@
+mod common;
+
+use std::fs;
+use common::{copy_fixture, fixture, fixture_path};
+use google_photos_fix_metadata::exif::{ExifDates, TAG_CREATE_DATE, TAG_DATE_TIME_ORIGINAL, TAG_OFFSET_TIME_ORIGINAL};
+use google_photos_fix_metadata::png_metadata::{
+    read_png_date_tags, read_png_exif, read_png_text, write_png_date_tags, CREATION_TIME_KEYWORD,
+};
+
+fn date_tags() -> Vec<(u16, String)> {
+    vec![
+        (TAG_DATE_TIME_ORIGINAL, "2015:06:22 19:06:40".to_string()),
+        (TAG_CREATE_DATE, "2015:06:22 19:06:40".to_string()),
+        (TAG_OFFSET_TIME_ORIGINAL, "+09:00".to_string()),
+    ]
+}
+
+const CREATION_TIME: &str = "Mon, 22 Jun 2015 19:06:40 +0900";
+
+// Chunk types in file order, with the CRC of each chunk checked on the way
+fn chunk_types(png: &[u8]) -> Vec<String> {
+    let mut types = Vec::new();
+    let mut pos = 8;
+    while pos < png.len() {
+        let length = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+        let crc = u32::from_be_bytes(png[pos + 8 + length..pos + 12 + length].try_into().unwrap());
+        assert_eq!(crc32fast::hash(&png[pos + 4..pos + 8 + length]), crc);
+        types.push(String::from_utf8_lossy(&png[pos + 4..pos + 8]).to_string());
+        pos += 12 + length;
+    }
+    types
+}
+
+#[test]
+fn test_write_dates_without_exif() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let png_path = copy_fixture("no_exif.png", temp_dir.path());
+    assert_eq!(read_png_date_tags(&png_path).unwrap(), ExifDates::default());
+
+    write_png_date_tags(&png_path, &date_tags(), CREATION_TIME).unwrap();
+
+    let dates = read_png_date_tags(&png_path).unwrap();
+    assert_eq!(dates.date_time_original, Some("2015:06:22 19:06:40".to_string()));
+    assert_eq!(dates.offset_time_original, Some("+09:00".to_string()));
+
+    let written = fs::read(&png_path).unwrap();
+    assert_eq!(chunk_types(&written), ["IHDR", "eXIf", "tEXt", "IDAT", "IEND"]);
+    assert_eq!(read_png_text(&written, CREATION_TIME_KEYWORD).unwrap(), Some(CREATION_TIME.to_string()));
+}
+
+#[test]
+fn test_update_existing_chunks() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let png_path = copy_fixture("with_text.png", temp_dir.path());
+
+    write_png_date_tags(&png_path, &date_tags(), CREATION_TIME).unwrap();
+    // A second write replaces the eXIf chunk instead of adding another one
+    write_png_date_tags(&png_path, &date_tags(), CREATION_TIME).unwrap();
+
+    let written = fs::read(&png_path).unwrap();
+    assert_eq!(chunk_types(&written), ["IHDR", "eXIf", "tEXt", "tEXt", "IDAT", "IEND"]);
+    assert_eq!(read_png_text(&written, "Software").unwrap(), Some("Stitch".to_string()));
+    assert_eq!(read_png_text(&written, CREATION_TIME_KEYWORD).unwrap(), Some(CREATION_TIME.to_string()));
+    assert!(read_png_exif(&written).unwrap().is_some());
+}
+
+// A chunk as it sits in the file: length, type, data, then the CRC of type and data
+fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&crc32fast::hash(&chunk[4..]).to_be_bytes());
+    chunk
+}
+
+#[test]
+fn test_exif_header_and_duplicate_chunks() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let png_path = copy_fixture("no_exif.png", temp_dir.path());
+    write_png_date_tags(&png_path, &date_tags(), CREATION_TIME).unwrap();
+    let written = fs::read(&png_path).unwrap();
+    let tiff = read_png_exif(&written).unwrap().unwrap().to_vec();
+
+    // no_exif.png with two eXIf chunks after IHDR, the first one prefixed the way JPEG APP1 data is
+    let png = fixture("no_exif.png");
+    let ihdr_end = 8 + 25;
+    let mut prefixed = b"Exif\0\0".to_vec();
+    prefixed.extend_from_slice(&tiff);
+    let mut doubled = png[..ihdr_end].to_vec();
+    doubled.extend(png_chunk(b"eXIf", &prefixed));
+    doubled.extend(png_chunk(b"eXIf", &tiff));
+    doubled.extend_from_slice(&png[ihdr_end..]);
+    fs::write(&png_path, &doubled).unwrap();
+
+    assert_eq!(read_png_exif(&doubled).unwrap(), Some(&tiff[..]));
+    assert_eq!(read_png_date_tags(&png_path).unwrap().date_time_original, Some("2015:06:22 19:06:40".to_string()));
+
+    write_png_date_tags(&png_path, &date_tags(), CREATION_TIME).unwrap();
+
+    // The new tEXt goes right after IHDR, the eXIf stays where the first one was
+    let rewritten = fs::read(&png_path).unwrap();
+    assert_eq!(chunk_types(&rewritten), ["IHDR", "tEXt", "eXIf", "IDAT", "IEND"]);
+    assert_eq!(read_png_date_tags(&png_path).unwrap().offset_time_original, Some("+09:00".to_string()));
+}
+
+#[test]
+fn test_keep_data_after_iend() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    // no_exif.png followed by a JPEG thumbnail, as some apps append one
+    let png_path = copy_fixture("trailing_data.png", temp_dir.path());
+    let trailer = fs::read(fixture_path("no_exif.jpg")).unwrap();
+
+    write_png_date_tags(&png_path, &date_tags(), CREATION_TIME).unwrap();
+
+    let written = fs::read(&png_path).unwrap();
+    assert!(written.ends_with(&trailer));
+    assert_eq!(chunk_types(&written[..written.len() - trailer.len()]), ["IHDR", "eXIf", "tEXt", "IDAT", "IEND"]);
+    assert_eq!(read_png_date_tags(&png_path).unwrap().date_time_original, Some("2015:06:22 19:06:40".to_string()));
+}
+
+#[test]
+fn test_not_a_png() {
+    assert!(read_png_exif(b"\xFF\xD8\xFF\xD9").is_err());
+}
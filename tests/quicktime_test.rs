@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, TimeZone, Utc};
+use google_photos_fix_metadata::quicktime::{read_quicktime_dates, write_quicktime_dates, APPLE_CREATION_DATE_KEY};
+
+fn atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut atom = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(payload);
+    atom
+}
+
+// version 0 headers hold 32 bit times, version 1 headers 64 bit ones
+fn header(kind: &[u8; 4], version: u8, seconds: u64) -> Vec<u8> {
+    let mut payload = vec![version, 0, 0, 0];
+    if version == 1 {
+        payload.extend_from_slice(&seconds.to_be_bytes());
+        payload.extend_from_slice(&seconds.to_be_bytes());
+    } else {
+        payload.extend_from_slice(&(seconds as u32).to_be_bytes());
+        payload.extend_from_slice(&(seconds as u32).to_be_bytes());
+    }
+    payload.extend_from_slice(&[0u8; 16]);
+    atom(kind, &payload)
+}
+
+fn apple_meta(creation_date: &str) -> Vec<u8> {
+    let mut keys = vec![0, 0, 0, 0];
+    keys.extend_from_slice(&1u32.to_be_bytes());
+    keys.extend_from_slice(&((APPLE_CREATION_DATE_KEY.len() + 8) as u32).to_be_bytes());
+    keys.extend_from_slice(b"mdta");
+    keys.extend_from_slice(APPLE_CREATION_DATE_KEY.as_bytes());
+
+    let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+    data.extend_from_slice(creation_date.as_bytes());
+    let item = atom(&1u32.to_be_bytes(), &atom(b"data", &data));
+
+    let mut meta = atom(b"hdlr", &[0u8; 24]);
+    meta.extend(atom(b"keys", &keys));
+    meta.extend(atom(b"ilst", &item));
+    atom(b"meta", &meta)
+}
+
+// ftyp, a moov with one track and the Apple metadata, then the media data
+fn build_mov(path: &Path, seconds: u64) {
+    build_movie(path, seconds, Some(apple_meta("2000-01-01T00:00:00+0000")));
+}
+
+fn build_movie(path: &Path, seconds: u64, meta: Option<Vec<u8>>) {
+    let mut mdia = header(b"mdhd", 1, seconds);
+    mdia.extend(atom(b"hdlr", &[0u8; 24]));
+
+    let mut trak = header(b"tkhd", 0, seconds);
+    trak.extend(atom(b"mdia", &mdia));
+
+    let mut moov = header(b"mvhd", 0, seconds);
+    moov.extend(atom(b"trak", &trak));
+    moov.extend(meta.unwrap_or_default());
+
+    let mut file = atom(b"ftyp", b"qt  \0\0\0\0qt  ");
+    file.extend(atom(b"moov", &moov));
+    file.extend(atom(b"mdat", b"not really video data"));
+    fs::write(path, file).unwrap();
+}
+
+fn taken_time() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2015, 6, 22, 10, 6, 40).unwrap()
+}
+
+#[test]
+fn test_read_unset_dates() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mov_path = temp_dir.path().join("video.mov");
+    build_mov(&mov_path, 0);
+
+    let dates = read_quicktime_dates(&mov_path).unwrap();
+    assert_eq!(dates.movie_creation_time, None);
+    assert_eq!(dates.track_creation_times, vec![None]);
+    assert_eq!(dates.media_creation_times, vec![None]);
+    assert_eq!(dates.apple_creation_date, Some("2000-01-01T00:00:00+0000".to_string()));
+}
+
+#[test]
+fn test_write_dates() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mov_path = temp_dir.path().join("video.mov");
+    build_mov(&mov_path, 0);
+    let original = fs::read(&mov_path).unwrap();
+
+    let update = write_quicktime_dates(&mov_path, taken_time(), Some("2015-06-22T19:06:40+0900")).unwrap();
+    assert_eq!(update.headers_updated, 3);
+    assert!(update.apple_creation_date_updated);
+
+    let dates = read_quicktime_dates(&mov_path).unwrap();
+    assert_eq!(dates.movie_creation_time, Some(taken_time()));
+    assert_eq!(dates.track_creation_times, vec![Some(taken_time())]);
+    assert_eq!(dates.media_creation_times, vec![Some(taken_time())]);
+    assert_eq!(dates.apple_creation_date, Some("2015-06-22T19:06:40+0900".to_string()));
+
+    // Patched in place, the media data does not move
+    let written = fs::read(&mov_path).unwrap();
+    assert_eq!(written.len(), original.len());
+    assert!(written.ends_with(b"not really video data"));
+}
+
+#[test]
+fn test_apple_creation_date_needs_same_length() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mov_path = temp_dir.path().join("video.mov");
+    build_mov(&mov_path, 0);
+
+    let update = write_quicktime_dates(&mov_path, taken_time(), Some("2015-06-22T19:06:40Z")).unwrap();
+    assert!(!update.apple_creation_date_updated);
+    assert_eq!(read_quicktime_dates(&mov_path).unwrap().apple_creation_date, Some("2000-01-01T00:00:00+0000".to_string()));
+}
+
+#[test]
+fn test_no_apple_metadata_added() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mp4_path = temp_dir.path().join("video.mp4");
+    build_movie(&mp4_path, 0, None);
+    let original = fs::read(&mp4_path).unwrap();
+
+    let update = write_quicktime_dates(&mp4_path, taken_time(), Some("2015-06-22T19:06:40+0900")).unwrap();
+    assert_eq!(update.headers_updated, 3);
+    assert!(!update.apple_creation_date_updated);
+
+    // Only the header times differ, no meta, keys or ilst atom is added
+    let written = fs::read(&mp4_path).unwrap();
+    assert_eq!(written.len(), original.len());
+    assert!(!written.windows(4).any(|window| window == b"meta" || window == b"keys"));
+    assert_eq!(read_quicktime_dates(&mp4_path).unwrap().apple_creation_date, None);
+}
+
+#[test]
+fn test_not_a_quicktime_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("video.mp4");
+    fs::write(&path, b"\x89PNG\r\n\x1a\n").unwrap();
+    assert!(read_quicktime_dates(&path).is_err());
+}
+
+// size 1, then a 64 bit size
+fn large_atom(kind: &[u8; 4], size: u64) -> Vec<u8> {
+    let mut atom = 1u32.to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(&size.to_be_bytes());
+    atom
+}
+
+#[test]
+fn test_largesize_out_of_range() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("video.mov");
+
+    let mut file = atom(b"ftyp", b"qt  \0\0\0\0qt  ");
+    file.extend(large_atom(b"mdat", u64::MAX));
+    fs::write(&path, &file).unwrap();
+    assert!(read_quicktime_dates(&path).is_err());
+
+    let mut moov = header(b"mvhd", 0, 0);
+    moov.extend(large_atom(b"trak", u64::MAX));
+    let mut file = atom(b"ftyp", b"qt  \0\0\0\0qt  ");
+    file.extend(atom(b"moov", &moov));
+    fs::write(&path, &file).unwrap();
+    assert!(read_quicktime_dates(&path).is_err());
+    assert!(write_quicktime_dates(&path, taken_time(), None).is_err());
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use filetime::FileTime;
+use common::{file_names, fixture_path, run, run_ok, SIDECAR};
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::journal::JOURNAL_FILE_NAME;
+
+fn setup_takeout(work_dir: &Path) -> PathBuf {
+    let src_dir = work_dir.join("Takeout/Google Photos/Photos from 2015");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    let fixture = fixture_path("no_exif.jpg");
+    for name in ["IMG_0001.JPG", "IMG_0002.JPG"] {
+        fs::copy(&fixture, src_dir.join(name)).unwrap();
+        fs::write(src_dir.join(format!("{}.json", name)), SIDECAR).unwrap();
+    }
+    src_dir
+}
+
+// The copies, and the journal left next to them
+fn output_files(work_dir: &Path) -> Vec<String> {
+    assert!(work_dir.join("output").join(JOURNAL_FILE_NAME).is_file());
+    file_names(&work_dir.join("output"))
+}
+
+#[test]
+fn test_resume_skips_completed_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    setup_takeout(temp_dir.path());
+
+    run_ok(&["--src-google-photos", "Takeout"], temp_dir.path());
+    let stdout = run_ok(&["--src-google-photos", "Takeout", "--resume"], temp_dir.path());
+    assert_eq!(stdout.matches("Already done in a previous run").count(), 2);
+    assert_eq!(output_files(temp_dir.path()), ["IMG_0001.JPG", "IMG_0002.JPG"]);
+}
+
+#[test]
+fn test_resume_redoes_interrupted_copy() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = setup_takeout(temp_dir.path());
+
+    // A run killed while copying IMG_0001.JPG: a "started" line and half a file
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&output_dir).unwrap();
+    let partial_copy = output_dir.join("IMG_0001.JPG");
+    fs::write(&partial_copy, &fs::read(src_dir.join("IMG_0001.JPG")).unwrap()[..100]).unwrap();
+    let started = serde_json::json!({
+        "source": src_dir.join("IMG_0001.JPG").strip_prefix(temp_dir.path()).unwrap().to_string_lossy(),
+        "hash": "sha256:unknown",
+        "action": "copy+metadata",
+        "dest": "output/IMG_0001.JPG",
+        "status": "started",
+        "error": null,
+    });
+    fs::write(output_dir.join(JOURNAL_FILE_NAME), format!("{}\n{{\"source\": \"cut sh", started)).unwrap();
+
+    run_ok(&["--src-google-photos", "Takeout", "--resume"], temp_dir.path());
+
+    // Redone in place instead of getting a second, suffixed copy
+    assert_eq!(output_files(temp_dir.path()), ["IMG_0001.JPG", "IMG_0002.JPG"]);
+    let dates = read_jpeg_date_tags(&partial_copy).unwrap();
+    assert_eq!(dates.date_time_original, Some("2015:06:22 19:06:40".to_string()));
+}
+
+#[test]
+fn test_resume_retries_failed_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = setup_takeout(temp_dir.path());
+    fs::write(src_dir.join("IMG_0003.JPG"), b"not really a jpg").unwrap();
+    fs::write(src_dir.join("IMG_0003.JPG.json"), SIDECAR).unwrap();
+
+    assert_eq!(run(&["--src-google-photos", "Takeout"], temp_dir.path()).status.code(), Some(1));
+
+    let output = run(&["--src-google-photos", "Takeout", "--resume"], temp_dir.path());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout.matches("Already done in a previous run").count(), 2);
+    assert!(stdout.contains("Finished with 1 failed file(s):"));
+    assert_eq!(output_files(temp_dir.path()), ["IMG_0001.JPG", "IMG_0002.JPG", "IMG_0003.JPG"]);
+}
+
+fn journal_hashes(work_dir: &Path) -> Vec<String> {
+    fs::read_to_string(work_dir.join("output").join(JOURNAL_FILE_NAME))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["hash"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_resume_compares_content() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = setup_takeout(temp_dir.path());
+
+    run_ok(&["--src-google-photos", "Takeout"], temp_dir.path());
+    assert!(journal_hashes(temp_dir.path()).iter().all(|hash| hash.starts_with("sha256:")));
+
+    // Same size and modification time, other content: redone
+    let changed = src_dir.join("IMG_0002.JPG");
+    let modified = FileTime::from_last_modification_time(&fs::metadata(&changed).unwrap());
+    let mut bytes = fs::read(&changed).unwrap();
+    let last = bytes.len() - 3;
+    bytes[last] ^= 0xff;
+    fs::write(&changed, bytes).unwrap();
+    filetime::set_file_mtime(&changed, modified).unwrap();
+
+    let stdout = run_ok(&["--src-google-photos", "Takeout", "--resume"], temp_dir.path());
+    assert_eq!(stdout.matches("Already done in a previous run").count(), 1);
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use common::{fixture_path, run_ok, SIDECAR};
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::sidecar_matcher::{is_edited_name, EditedPolicy, MatchRule, MediaIndex};
+
+const DIRECTORY: &str = "Takeout/Google Photos/Photos from 2019";
+
+// A directory listing the way Takeout exports it, sidecars included
+fn index(file_names: &[&str]) -> MediaIndex {
+    MediaIndex::new(file_names.iter().map(|name| Path::new(DIRECTORY).join(name)))
+}
+
+fn find(index: &MediaIndex, json_file_name: &str) -> Option<(String, MatchRule)> {
+    let sidecar_match = index.find_media_for_json(&Path::new(DIRECTORY).join(json_file_name))?;
+    let media_name = sidecar_match.media.file_name().unwrap().to_string_lossy().to_string();
+    Some((media_name, sidecar_match.rule))
+}
+
+fn expect(file_names: &[&str], json_file_name: &str, media_name: &str, rule: MatchRule) {
+    assert_eq!(find(&index(file_names), json_file_name), Some((media_name.to_string(), rule)), "{}", json_file_name);
+}
+
+#[test]
+fn test_exact() {
+    expect(&["IMG_0777.png", "IMG_0777.png.json"], "IMG_0777.png.json", "IMG_0777.png", MatchRule::Exact);
+    expect(&["sample.gif", "sample.GIF.JSON"], "sample.GIF.JSON", "sample.gif", MatchRule::ExtensionCase);
+    // The counter form is only tried when there is no file with the plain name
+    expect(&["IMG_0743.PNG(1)", "IMG_0743(1).PNG"], "IMG_0743.PNG(1).json", "IMG_0743.PNG(1)", MatchRule::Exact);
+}
+
+#[test]
+fn test_supplemental_metadata() {
+    let file_names = ["IMG_1234.JPG", "IMG_1234(1).JPG", "IMG_1234.JPG.supplemental-metadata.json"];
+    expect(&file_names, "IMG_1234.JPG.supplemental-metadata.json", "IMG_1234.JPG", MatchRule::SupplementalMetadata);
+    expect(&file_names, "IMG_1234.JPG.supplemental-metadata(1).json", "IMG_1234(1).JPG", MatchRule::SupplementalMetadata);
+    expect(&["IMG_1234.jpg"], "IMG_1234.JPG.supplemental-metadata.json", "IMG_1234.jpg", MatchRule::ExtensionCase);
+    expect(
+        &["PXL_20231224_081530123.NIGHT.jpg"],
+        "PXL_20231224_081530123.NIGHT.jpg.supplemental-m.json",
+        "PXL_20231224_081530123.NIGHT.jpg",
+        MatchRule::SupplementalMetadata,
+    );
+    // Cut right after the media name, or inside it
+    expect(
+        &["original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_ph.jpg"],
+        "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_ph.jpg..json",
+        "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_ph.jpg",
+        MatchRule::Truncated,
+    );
+    expect(
+        &["original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_photo_long.jpg"],
+        "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_phot.json",
+        "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_photo_long.jpg",
+        MatchRule::Truncated,
+    );
+}
+
+#[test]
+fn test_duplicate_counter() {
+    let file_names = ["IMG_0743.PNG", "IMG_0743(1).PNG", "IMG_0743.PNG.json", "IMG_0743.PNG(1).json"];
+    expect(&file_names, "IMG_0743.PNG(1).json", "IMG_0743(1).PNG", MatchRule::DuplicateCounter);
+    expect(&file_names, "IMG_0743.PNG.json", "IMG_0743.PNG", MatchRule::Exact);
+    expect(&["Stitch1714280447(3).png"], "Stitch1714280447.png(3).json", "Stitch1714280447(3).png", MatchRule::DuplicateCounter);
+    expect(&["IMG_0253(2).heic"], "IMG_0253.HEIC(2).json", "IMG_0253(2).heic", MatchRule::ExtensionCase);
+}
+
+#[test]
+fn test_truncated() {
+    // The old "append 0.png" fix, now found by prefix
+    expect(
+        &["BAAC2A4F-AF2C-44EE-B4BF-5FCB1FC0EE38-5325-00000.png"],
+        "BAAC2A4F-AF2C-44EE-B4BF-5FCB1FC0EE38-5325-0000.json",
+        "BAAC2A4F-AF2C-44EE-B4BF-5FCB1FC0EE38-5325-00000.png",
+        MatchRule::Truncated,
+    );
+    expect(
+        &["Screenshot_20190503-120112_Samsung Internet.jpg"],
+        "Screenshot_20190503-120112_Samsung Internet.jp.json",
+        "Screenshot_20190503-120112_Samsung Internet.jpg",
+        MatchRule::Truncated,
+    );
+    // A counter is added after the cut
+    let file_names = [
+        "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_photo_long.jpg",
+        "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_photo_long(1).jpg",
+        "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_photo_long-edited.jpg",
+    ];
+    expect(&file_names, "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_phot.json", file_names[0], MatchRule::Truncated);
+    expect(&file_names, "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_phot(1).json", file_names[1], MatchRule::Truncated);
+
+    // Short names are never taken as cut off
+    assert_eq!(find(&index(&["IMG_00011.JPG"]), "IMG_0001.json"), None);
+    // Two originals share the prefix: no guess
+    let ambiguous = ["original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_photo_A.jpg", "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_photo_B.jpg"];
+    assert_eq!(find(&index(&ambiguous), "original_1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d_phot.json"), None);
+}
+
+#[test]
+fn test_missing_extension() {
+    expect(&["IMG_0001.JPG", "IMG_0001.json"], "IMG_0001.json", "IMG_0001.JPG", MatchRule::MissingExtension);
+    // Live Photo: the image and the video both fit
+    assert_eq!(find(&index(&["IMG_0002.HEIC", "IMG_0002.MOV"]), "IMG_0002.json"), None);
+}
+
+#[test]
+fn test_edited_variants() {
+    let index = index(&[
+        "IMG_1234.JPG",
+        "IMG_1234-edited.jpg",
+        "IMG_1234-bearbeitet.JPG",
+        "IMG_1234(1).JPG",
+        "IMG_1234-modifié(1).JPG",
+        "IMG_12345-edited.JPG",
+    ]);
+
+    let sidecar_match = index.find_media_for_json(&Path::new(DIRECTORY).join("IMG_1234.JPG.json")).unwrap();
+    assert_eq!(sidecar_match.edited, vec![
+        PathBuf::from(DIRECTORY).join("IMG_1234-edited.jpg"),
+        PathBuf::from(DIRECTORY).join("IMG_1234-bearbeitet.JPG"),
+    ]);
+
+    let sidecar_match = index.find_media_for_json(&Path::new(DIRECTORY).join("IMG_1234.JPG(1).json")).unwrap();
+    assert_eq!(sidecar_match.edited, vec![PathBuf::from(DIRECTORY).join("IMG_1234-modifié(1).JPG")]);
+
+    assert!(is_edited_name("IMG_1234-editado.jpg"));
+    assert!(!is_edited_name("IMG_1234.jpg"));
+}
+
+#[test]
+fn test_edited_policy() {
+    let edited = ["IMG_1234-edited.JPG"];
+    assert_eq!(EditedPolicy::Both.skipped(&"IMG_1234.JPG", &edited), Vec::<&&str>::new());
+    assert_eq!(EditedPolicy::OriginalOnly.skipped(&"IMG_1234.JPG", &edited), vec![&"IMG_1234-edited.JPG"]);
+    assert_eq!(EditedPolicy::EditedOnly.skipped(&"IMG_1234.JPG", &edited), vec![&"IMG_1234.JPG"]);
+    // Without an edited copy the original is all there is
+    assert_eq!(EditedPolicy::EditedOnly.skipped(&"IMG_1234.JPG", &[]), Vec::<&&str>::new());
+
+    assert_eq!(EditedPolicy::parse("original-only"), Ok(EditedPolicy::OriginalOnly));
+    assert!(EditedPolicy::parse("original").is_err());
+}
+
+// Runs the binary on one original, its edited copy and the single sidecar Takeout emits for both
+fn run_with_edited_copy(args: &[&str]) -> tempfile::TempDir {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let src_dir = temp_dir.path().join(DIRECTORY);
+    fs::create_dir_all(&src_dir).unwrap();
+
+    let fixture = fixture_path("no_exif.jpg");
+    fs::copy(&fixture, src_dir.join("IMG_1234.JPG")).unwrap();
+    fs::copy(&fixture, src_dir.join("IMG_1234-bearbeitet.JPG")).unwrap();
+    fs::write(src_dir.join("IMG_1234.JPG.json"), SIDECAR).unwrap();
+
+    run_ok(&[&["--src-google-photos", "Takeout"], args].concat(), temp_dir.path());
+    temp_dir
+}
+
+#[test]
+fn test_edited_copy_gets_metadata() {
+    let temp_dir = run_with_edited_copy(&[]);
+    for name in ["IMG_1234.JPG", "IMG_1234-bearbeitet.JPG"] {
+        let dates = read_jpeg_date_tags(&temp_dir.path().join("output").join(name)).unwrap();
+        assert_eq!(dates.date_time_original.as_deref(), Some("2015:06:22 19:06:40"), "{}", name);
+    }
+
+    let temp_dir = run_with_edited_copy(&["--edited", "original-only"]);
+    assert!(temp_dir.path().join("output/IMG_1234.JPG").exists());
+    assert!(!temp_dir.path().join("output/IMG_1234-bearbeitet.JPG").exists());
+
+    let temp_dir = run_with_edited_copy(&["--edited", "edited-only"]);
+    assert!(!temp_dir.path().join("output/IMG_1234.JPG").exists());
+    let dates = read_jpeg_date_tags(&temp_dir.path().join("output/IMG_1234-bearbeitet.JPG")).unwrap();
+    assert_eq!(dates.date_time_original.as_deref(), Some("2015:06:22 19:06:40"));
+}
+
+#[test]
+fn test_other_directory() {
+    let index = index(&["IMG_0001.JPG"]);
+    assert_eq!(index.find_media_for_json(Path::new("Takeout/Google Photos/Album/IMG_0001.JPG.json")), None);
+    assert_eq!(find(&index, "IMG_0009.JPG.json"), None);
+}
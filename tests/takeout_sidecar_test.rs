@@ -0,0 +1,166 @@
+use chrono::{TimeZone, Utc};
+use google_photos_fix_metadata::media_kind::MediaKind;
+use google_photos_fix_metadata::sidecar_metadata::{plan_sidecar_metadata, sidecar_exiftool_args, MetadataOptions};
+use google_photos_fix_metadata::takeout_sidecar::TakeoutSidecar;
+
+const FULL_SIDECAR: &str = r#"{
+  "title": "IMG_0001.JPG",
+  "description": "  Dinner at the harbour ",
+  "imageViews": "12",
+  "creationTime": { "timestamp": "1435100000", "formatted": "Jun 23, 2015, 10:53:20 PM UTC" },
+  "photoTakenTime": { "timestamp": "1435000000", "formatted": "Jun 22, 2015, 7:06:40 PM UTC" },
+  "geoData": { "latitude": -33.8567, "longitude": -151.2153, "altitude": -4.5, "latitudeSpan": 0.0, "longitudeSpan": 0.0 },
+  "geoDataExif": { "latitude": -33.8567, "longitude": -151.2153, "altitude": -4.5, "latitudeSpan": 0.0, "longitudeSpan": 0.0 },
+  "people": [{ "name": "Alex" }, { "name": " " }, { "name": "Sam" }],
+  "favorited": true,
+  "url": "https://photos.google.com/photo/abc",
+  "googlePhotosOrigin": { "mobileUpload": { "deviceType": "IOS_PHONE" } }
+}"#;
+
+const ALL_OPTIONS: MetadataOptions = MetadataOptions {
+    restore_gps: true,
+    write_description: true,
+    write_people: true,
+    write_favorites: true,
+};
+
+fn full_sidecar() -> TakeoutSidecar {
+    TakeoutSidecar::from_reader(FULL_SIDECAR.as_bytes()).unwrap()
+}
+
+#[test]
+fn test_parse_full_sidecar() {
+    let sidecar = full_sidecar();
+
+    assert_eq!(sidecar.title.as_deref(), Some("IMG_0001.JPG"));
+    assert_eq!(sidecar.photo_taken_time(), Some(Utc.timestamp_opt(1435000000, 0).unwrap()));
+    assert_eq!(sidecar.description(), Some("Dinner at the harbour"));
+    assert_eq!(sidecar.people_names(), vec!["Alex", "Sam"]);
+    assert!(sidecar.favorited);
+    assert_eq!(sidecar.location().unwrap().latitude, -33.8567);
+
+    // Old exports only have the timestamp
+    let minimal = TakeoutSidecar::from_reader(r#"{"photoTakenTime": {"timestamp": "0"}}"#.as_bytes()).unwrap();
+    assert_eq!(minimal.description(), None);
+    assert!(minimal.people_names().is_empty());
+    assert!(!minimal.favorited);
+}
+
+#[test]
+fn test_parse_lenient() {
+    let sidecar = TakeoutSidecar::from_reader(
+        r#"{
+          "title": 12,
+          "photoTakenTime": { "timestamp": "1435000000", "formatted": null },
+          "creationTime": "yesterday",
+          "geoData": { "latitude": "north", "longitude": 2.0 },
+          "geoDataExif": { "latitude": 48.85, "longitude": 2.35, "altitude": "high" },
+          "people": [{ "name": "Alex" }, {}, { "name": null }, "Sam", { "name": "Kim" }],
+          "favorited": "yes"
+        }"#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    assert_eq!(sidecar.title, None);
+    assert_eq!(sidecar.photo_taken_time(), Some(Utc.timestamp_opt(1435000000, 0).unwrap()));
+    assert_eq!(sidecar.creation_time(), None);
+    assert_eq!(sidecar.geo_data, None);
+    assert_eq!(sidecar.location().unwrap().latitude, 48.85);
+    assert_eq!(sidecar.location().unwrap().altitude, 0.0);
+    assert_eq!(sidecar.people_names(), vec!["Alex", "Kim"]);
+    assert!(!sidecar.favorited);
+
+    // Not a sidecar at all
+    assert!(TakeoutSidecar::from_reader(r#""photoTakenTime""#.as_bytes()).is_err());
+    assert!(TakeoutSidecar::from_reader(r#"{"photoTakenTime": "#.as_bytes()).is_err());
+}
+
+#[test]
+fn test_image_args() {
+    let args = sidecar_exiftool_args(&full_sidecar(), MediaKind::Jpeg, &ALL_OPTIONS);
+
+    assert_eq!(
+        args,
+        vec![
+            "-GPSLatitude=33.8567",
+            "-GPSLatitudeRef=S",
+            "-GPSLongitude=151.2153",
+            "-GPSLongitudeRef=W",
+            "-GPSAltitude=4.5",
+            "-GPSAltitudeRef#=1",
+            "-EXIF:ImageDescription=Dinner at the harbour",
+            "-XMP-dc:Description=Dinner at the harbour",
+            "-XMP-iptcExt:PersonInImage+=Alex",
+            "-XMP-iptcExt:PersonInImage+=Sam",
+            "-XMP-xmp:Rating=5",
+        ]
+    );
+}
+
+#[test]
+fn test_video_args() {
+    let options = MetadataOptions { restore_gps: true, write_description: true, ..MetadataOptions::default() };
+    let args = sidecar_exiftool_args(&full_sidecar(), MediaKind::Mp4, &options);
+
+    assert_eq!(args, vec!["-Keys:GPSCoordinates=-33.8567, -151.2153, -4.5", "-XMP-dc:Description=Dinner at the harbour"]);
+}
+
+#[test]
+fn test_options_off() {
+    let sidecar = full_sidecar();
+    let options = MetadataOptions::default();
+
+    assert!(!options.writes_sidecar_fields());
+    assert!(sidecar_exiftool_args(&sidecar, MediaKind::Jpeg, &options).is_empty());
+    assert_eq!(plan_sidecar_metadata(&sidecar, &options), None);
+    assert_eq!(
+        plan_sidecar_metadata(&sidecar, &MetadataOptions { write_favorites: true, ..options }).as_deref(),
+        Some("rating 5")
+    );
+}
+
+#[test]
+fn test_location_preference() {
+    let parse = |json: &str| TakeoutSidecar::from_reader(json.as_bytes()).unwrap();
+    let placeholder = r#"{"latitude": 0.0, "longitude": 0.0, "altitude": 0.0}"#;
+    let taken = r#"{"latitude": 48.8584, "longitude": 2.2945, "altitude": 35.0}"#;
+    let edited = r#"{"latitude": 48.8606, "longitude": 2.3376, "altitude": 0.0}"#;
+
+    // geoDataExif is preferred when both are set and differ
+    let both = parse(&format!(r#"{{"geoData": {}, "geoDataExif": {}}}"#, edited, taken));
+    assert_eq!(both.location().unwrap().longitude, 2.2945);
+
+    let edited_only = parse(&format!(r#"{{"geoData": {}, "geoDataExif": {}}}"#, edited, placeholder));
+    assert_eq!(edited_only.location().unwrap().longitude, 2.3376);
+
+    let no_location = parse(&format!(r#"{{"geoData": {}, "geoDataExif": {}}}"#, placeholder, placeholder));
+    assert_eq!(no_location.location(), None);
+    let gps_only = MetadataOptions { restore_gps: true, ..MetadataOptions::default() };
+    assert!(sidecar_exiftool_args(&no_location, MediaKind::Jpeg, &gps_only).is_empty());
+    assert_eq!(plan_sidecar_metadata(&no_location, &gps_only), None);
+
+    let args = sidecar_exiftool_args(&both, MediaKind::Jpeg, &gps_only);
+    assert_eq!(args[..4], ["-GPSLatitude=48.8584", "-GPSLatitudeRef=N", "-GPSLongitude=2.2945", "-GPSLongitudeRef=E"]);
+    assert_eq!(args[5], "-GPSAltitudeRef#=0");
+}
+
+#[test]
+fn test_merge() {
+    let mut sidecar = TakeoutSidecar::from_reader(
+        r#"{"photoTakenTime": {"timestamp": "1435000000"}, "description": "", "people": [{"name": "Sam"}]}"#.as_bytes(),
+    )
+    .unwrap();
+    sidecar.merge(&full_sidecar());
+
+    assert_eq!(sidecar.photo_taken_time(), Some(Utc.timestamp_opt(1435000000, 0).unwrap()));
+    assert_eq!(sidecar.description(), Some("Dinner at the harbour"));
+    assert_eq!(sidecar.people_names(), vec!["Sam", "Alex"]);
+    assert!(sidecar.favorited);
+    assert_eq!(sidecar.location().unwrap().longitude, -151.2153);
+
+    // Its own values win
+    let mut full = full_sidecar();
+    full.merge(&TakeoutSidecar::from_reader(r#"{"description": "Lunch", "photoTakenTime": {"timestamp": "0"}}"#.as_bytes()).unwrap());
+    assert_eq!(full, full_sidecar());
+}
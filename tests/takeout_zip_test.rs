@@ -0,0 +1,121 @@
+mod common;
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use common::write_zip;
+use filetime::FileTime;
+use google_photos_fix_metadata::takeout_zip::TakeoutZipSet;
+use zip::write::{FullFileOptions, SimpleFileOptions};
+use zip::ZipWriter;
+
+#[test]
+fn test_pair_sidecars_across_parts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let part_1 = temp_dir.path().join("takeout-001.zip");
+    let part_2 = temp_dir.path().join("takeout-002.zip");
+
+    write_zip(&part_1, &[
+        ("Takeout/Google Photos/Photos from 2015/IMG_0743.PNG(1).json", b"{}"),
+        ("Takeout/Google Photos/Photos from 2015/IMG_0777.png.json", b"{}"),
+        ("Takeout/Google Photos/Photos from 2015/IMG_0777.png", b"png"),
+    ]);
+    write_zip(&part_2, &[
+        ("Takeout/Google Photos/Photos from 2015/IMG_0743(1).PNG", b"png"),
+        ("Takeout/Google Photos/Photos from 2015/lost.jpg.json", b"{}"),
+        ("Takeout/Google Photos/print-subscriptions.json", b"{}"),
+    ]);
+
+    let zip_set = TakeoutZipSet::open(&[&part_1, &part_2]).unwrap();
+    let json_entries = zip_set.json_entries(&["print-subscriptions.json"]);
+    assert_eq!(json_entries.len(), 3);
+
+    let pairing = zip_set.pair_sidecars(&json_entries);
+    let pairs: Vec<(&str, &str, usize)> = pairing
+        .pairs
+        .iter()
+        .map(|(json_entry, media_entry)| (json_entry.file_name(), media_entry.file_name(), media_entry.part))
+        .collect();
+
+    assert_eq!(pairs, vec![
+        ("IMG_0743.PNG(1).json", "IMG_0743(1).PNG", 1),
+        ("IMG_0777.png.json", "IMG_0777.png", 0),
+    ]);
+    assert_eq!(pairing.missing_media.len(), 1);
+    assert_eq!(pairing.missing_media[0].file_name(), "lost.jpg.json");
+}
+
+#[test]
+fn test_pair_edited_copies() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let part_1 = temp_dir.path().join("takeout-001.zip");
+    let part_2 = temp_dir.path().join("takeout-002.zip");
+
+    write_zip(&part_1, &[
+        ("Takeout/Google Photos/Photos from 2015/IMG_1234.JPG.json", b"{}"),
+        ("Takeout/Google Photos/Photos from 2015/IMG_1234.JPG", b"jpg"),
+        ("Takeout/Google Photos/Photos from 2015/IMG_0777.png.json", b"{}"),
+        ("Takeout/Google Photos/Photos from 2015/IMG_0777.png", b"png"),
+    ]);
+    write_zip(&part_2, &[("Takeout/Google Photos/Photos from 2015/IMG_1234-edited.JPG", b"jpg")]);
+
+    let zip_set = TakeoutZipSet::open(&[&part_1, &part_2]).unwrap();
+    let pairing = zip_set.pair_sidecars(&zip_set.json_entries(&[]));
+
+    assert_eq!(pairing.edited.len(), 1);
+    let original = zip_set.find("Takeout/Google Photos/Photos from 2015/IMG_1234.JPG").unwrap();
+    let edited: Vec<(&str, usize)> = pairing.edited[original].iter().map(|entry| (entry.file_name(), entry.part)).collect();
+    assert_eq!(edited, vec![("IMG_1234-edited.JPG", 1)]);
+}
+
+#[test]
+fn test_extract_and_read_entries() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let part_1 = temp_dir.path().join("takeout-001.zip");
+
+    write_zip(&part_1, &[
+        ("Takeout/Google Photos/Photos from 2015/sample.gif.json", br#"{"photoTakenTime": {"timestamp": "1435000000"}}"#),
+        ("Takeout/Google Photos/Photos from 2015/sample.gif", b"GIF89a"),
+    ]);
+
+    let mut zip_set = TakeoutZipSet::open(&[&part_1]).unwrap();
+    let json_entry = zip_set.find("Takeout/Google Photos/Photos from 2015/sample.gif.json").unwrap().clone();
+    let media_entry = zip_set.find_media_for_json(&json_entry).unwrap().clone();
+
+    let mut json = String::new();
+    zip_set.open_entry(&json_entry).unwrap().read_to_string(&mut json).unwrap();
+    assert!(json.contains("1435000000"));
+
+    let dest_path = temp_dir.path().join("output").join("sample.gif");
+    zip_set.extract_entry(&media_entry, &dest_path).unwrap();
+    assert_eq!(fs::read(&dest_path).unwrap(), b"GIF89a");
+}
+
+#[test]
+fn test_extracted_modification_time() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let part_1 = temp_dir.path().join("takeout-001.zip");
+    let dos_time = zip::DateTime::from_date_and_time(2015, 6, 22, 10, 6, 40).unwrap();
+
+    let mut writer = ZipWriter::new(File::create(&part_1).unwrap());
+    let mut options = FullFileOptions::default().last_modified_time(dos_time);
+    // Extended timestamp: flags (modification time set), then the time in UTC
+    let mut extended = vec![1u8];
+    extended.extend_from_slice(&1435000000u32.to_le_bytes());
+    options.add_extra_data(0x5455, extended.into_boxed_slice(), false).unwrap();
+    writer.start_file("Takeout/Google Photos/Trip/extended.gif", options).unwrap();
+    writer.write_all(b"GIF89a").unwrap();
+    writer.start_file("Takeout/Google Photos/Trip/dos.gif", SimpleFileOptions::default().last_modified_time(dos_time)).unwrap();
+    writer.write_all(b"GIF89a").unwrap();
+    writer.finish().unwrap();
+
+    let mut zip_set = TakeoutZipSet::open(&[&part_1]).unwrap();
+    let modified = |zip_set: &mut TakeoutZipSet, name: &str| {
+        let dest_path = temp_dir.path().join("output").join(name);
+        let entry = zip_set.find(&format!("Takeout/Google Photos/Trip/{}", name)).unwrap().clone();
+        zip_set.extract_entry(&entry, &dest_path).unwrap();
+        FileTime::from_last_modification_time(&fs::metadata(&dest_path).unwrap()).unix_seconds()
+    };
+    assert_eq!(modified(&mut zip_set, "extended.gif"), 1435000000);
+    // Without the extended timestamp the DOS time is taken as UTC
+    assert_eq!(modified(&mut zip_set, "dos.gif"), 1434967600);
+}
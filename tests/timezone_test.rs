@@ -0,0 +1,131 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use common::{fixture_path, run_ok};
+use chrono::{DateTime, NaiveDate, Utc};
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::takeout_sidecar::GeoData;
+use google_photos_fix_metadata::timezone::{
+    parse_timezone, parse_timezone_mappings, timezone_for_location, TimezoneResolver, TimezoneScope,
+};
+
+// 2015-06-22 19:06:40 UTC
+const TAKEN: i64 = 1435000000;
+
+fn taken() -> DateTime<Utc> {
+    DateTime::from_timestamp(TAKEN, 0).unwrap()
+}
+
+fn location(latitude: f64, longitude: f64) -> GeoData {
+    GeoData { latitude, longitude, ..GeoData::default() }
+}
+
+#[test]
+fn test_parse_mappings() {
+    let mappings = parse_timezone_mappings(
+        "# Trips\n\
+         2015-06-20..2015-06-30\tAsia/Tokyo\n\
+         \n\
+         Takeout/Google Photos/Trip to Lisbon   Europe/Lisbon\n",
+    )
+    .unwrap();
+
+    assert_eq!(mappings.len(), 2);
+    assert_eq!(
+        mappings[0].scope,
+        TimezoneScope::DateRange(NaiveDate::from_ymd_opt(2015, 6, 20).unwrap(), NaiveDate::from_ymd_opt(2015, 6, 30).unwrap())
+    );
+    assert_eq!(mappings[1].scope, TimezoneScope::Directory("Takeout/Google Photos/Trip to Lisbon".into()));
+    assert!(mappings[1].matches(Path::new("Takeout/Google Photos/Trip to Lisbon/IMG_0001.JPG"), &taken()));
+    assert!(!mappings[1].matches(Path::new("Takeout/Google Photos/Trip to Lisbon 2/IMG_0001.JPG"), &taken()));
+
+    assert!(parse_timezone_mappings("2015-06-20..2015-06-30 Mars/Olympus_Mons").unwrap_err().starts_with("line 1:"));
+    assert!(parse_timezone_mappings("2015-06-30..2015-06-20 Asia/Tokyo").is_err());
+    assert!(parse_timezone_mappings("Asia/Tokyo").is_err());
+}
+
+#[test]
+fn test_resolver_order() {
+    let media_path = Path::new("Takeout/Google Photos/Trip/IMG_0001.JPG");
+    let paris = location(48.8584, 2.2945);
+
+    assert_eq!(TimezoneResolver::default().offset(media_path, &taken(), Some(&paris)).to_string(), "+00:00");
+
+    let mut resolver = TimezoneResolver { default_zone: Some(parse_timezone("America/New_York").unwrap()), ..TimezoneResolver::default() };
+    // The location is only used with from_gps
+    assert_eq!(resolver.offset(media_path, &taken(), Some(&paris)).to_string(), "-04:00");
+
+    resolver.from_gps = true;
+    assert_eq!(resolver.offset(media_path, &taken(), Some(&paris)).to_string(), "+02:00");
+    assert_eq!(resolver.offset(media_path, &taken(), None).to_string(), "-04:00");
+
+    resolver.mappings = parse_timezone_mappings("Takeout/Google Photos/Trip Asia/Tokyo").unwrap();
+    let local_time = resolver.local_time(media_path, &taken(), Some(&paris));
+    assert_eq!(local_time.to_rfc3339(), "2015-06-23T04:06:40+09:00");
+}
+
+#[test]
+fn test_timezone_for_location() {
+    let zone = |latitude, longitude| timezone_for_location(latitude, longitude).map(|zone| zone.name());
+
+    assert_eq!(zone(35.6762, 139.6503), Some("Asia/Tokyo"));
+    assert_eq!(zone(38.7223, -9.1393), Some("Europe/Lisbon"));
+    assert_eq!(zone(48.8584, 2.2945), Some("Europe/Paris"));
+    assert_eq!(zone(40.7128, -74.0060), Some("America/New_York"));
+    assert_eq!(zone(34.0522, -118.2437), Some("America/Los_Angeles"));
+    assert_eq!(zone(33.4484, -112.0740), Some("America/Phoenix"));
+    assert_eq!(zone(19.4326, -99.1332), Some("America/Mexico_City"));
+    assert_eq!(zone(-33.8567, 151.2153), Some("Australia/Sydney"));
+    assert_eq!(zone(28.6139, 77.2090), Some("Asia/Kolkata"));
+    assert_eq!(zone(22.3193, 114.1694), Some("Asia/Hong_Kong"));
+    assert_eq!(zone(-22.9068, -43.1729), Some("America/Sao_Paulo"));
+
+    // Near borders and away from the zone of their longitude
+    assert_eq!(zone(37.2614, -6.9447), Some("Europe/Madrid"));
+    assert_eq!(zone(38.8794, -6.9707), Some("Europe/Madrid"));
+    assert_eq!(zone(6.3703, 2.3912), Some("Africa/Porto-Novo"));
+    assert_eq!(zone(13.5116, 2.1254), Some("Africa/Niamey"));
+    assert_eq!(zone(43.6150, -116.2023), Some("America/Boise"));
+    assert_eq!(zone(-3.3614, 29.3599), Some("Africa/Bujumbura"));
+
+    // Middle of the Pacific
+    assert_eq!(zone(-30.0, -140.0), None);
+}
+
+#[test]
+fn test_location_without_zone() {
+    let media_path = Path::new("Takeout/Google Photos/Trip/IMG_0001.JPG");
+    let pacific = location(-30.0, -140.0);
+
+    let mut resolver = TimezoneResolver { from_gps: true, ..TimezoneResolver::default() };
+    assert_eq!(resolver.offset(media_path, &taken(), Some(&pacific)).to_string(), "+00:00");
+
+    resolver.default_zone = Some(parse_timezone("Asia/Tokyo").unwrap());
+    assert_eq!(resolver.offset(media_path, &taken(), Some(&pacific)).to_string(), "+09:00");
+}
+
+#[test]
+fn test_local_time_written_to_copy() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_path("no_exif.jpg");
+    for (directory, geo_data) in [("Home", "null"), ("Trip", r#"{"latitude": 38.7223, "longitude": -9.1393}"#)] {
+        let src_dir = temp_dir.path().join("Takeout").join(directory);
+        fs::create_dir_all(&src_dir).unwrap();
+        let name = format!("{}.JPG", directory);
+        fs::copy(&fixture, src_dir.join(&name)).unwrap();
+        let sidecar = format!(r#"{{"photoTakenTime": {{"timestamp": "{}"}}, "geoData": {}}}"#, TAKEN, geo_data);
+        fs::write(src_dir.join(format!("{}.json", name)), sidecar).unwrap();
+    }
+
+    run_ok(&["--src-google-photos", "Takeout", "--timezone", "Asia/Tokyo", "--timezone-from-gps"], temp_dir.path());
+
+    let home = read_jpeg_date_tags(&temp_dir.path().join("output/Home.JPG")).unwrap();
+    assert_eq!(home.date_time_original.as_deref(), Some("2015:06:23 04:06:40"));
+    assert_eq!(home.offset_time_original.as_deref(), Some("+09:00"));
+
+    // Summer time in Lisbon
+    let trip = read_jpeg_date_tags(&temp_dir.path().join("output/Trip.JPG")).unwrap();
+    assert_eq!(trip.date_time_original.as_deref(), Some("2015:06:22 20:06:40"));
+    assert_eq!(trip.offset_time_original.as_deref(), Some("+01:00"));
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use common::{fixture_path, run_ok, SIDECAR};
+use google_photos_fix_metadata::exif::read_jpeg_date_tags;
+use google_photos_fix_metadata::journal::JOURNAL_FILE_NAME;
+use google_photos_fix_metadata::log_line;
+use google_photos_fix_metadata::worker_pool::run_ordered;
+
+#[test]
+fn test_results_are_reported_in_order() {
+    let items: Vec<u64> = (0..20).collect();
+    let mut reported = Vec::new();
+
+    run_ordered(
+        &items,
+        4,
+        |&item| {
+            // Early items finish last
+            thread::sleep(Duration::from_millis(20 - item));
+            log_line!("item {}", item);
+            item * 2
+        },
+        |index, result, lines| reported.push((index, result, lines)),
+    );
+
+    let expected: Vec<_> = items.iter().map(|&item| (item as usize, item * 2, vec![format!("item {}", item)])).collect();
+    assert_eq!(reported, expected);
+}
+
+#[test]
+fn test_parallel_run_with_name_collisions() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_path("no_exif.jpg");
+
+    // The same file name in every album, all of them end up in the flat output directory
+    for album in 0..6 {
+        let src_dir = temp_dir.path().join(format!("Takeout/Google Photos/Album {}", album));
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::copy(&fixture, src_dir.join("IMG_0001.JPG")).unwrap();
+        fs::write(src_dir.join("IMG_0001.JPG.json"), SIDECAR).unwrap();
+    }
+
+    run_ok(&["--src-google-photos", "Takeout", "--jobs", "4"], temp_dir.path());
+
+    let copies: Vec<_> = fs::read_dir(temp_dir.path().join("output"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !path.ends_with(JOURNAL_FILE_NAME))
+        .collect();
+    assert_eq!(copies.len(), 6);
+    for copy in copies {
+        let dates = read_jpeg_date_tags(&copy).unwrap();
+        assert_eq!(dates.date_time_original, Some("2015:06:22 19:06:40".to_string()));
+    }
+}